@@ -5,7 +5,11 @@ use std::path::PathBuf;
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let path: PathBuf = [out_dir.clone(), "mod.rs".to_string()].iter().collect();
-    fs::write(path, "pub mod ttrpc;").unwrap();
+    fs::write(
+        path,
+        "pub mod ttrpc;\npub mod error_details;\npub mod health;\npub mod reflection;",
+    )
+    .unwrap();
 
     let customize = protobuf_codegen::Customize::default()
         .gen_mod_rs(false)
@@ -14,7 +18,12 @@ fn main() {
     protobuf_codegen::Codegen::new()
         .pure()
         .out_dir(out_dir)
-        .inputs(["src/ttrpc.proto"])
+        .inputs([
+            "src/ttrpc.proto",
+            "src/error_details.proto",
+            "src/health.proto",
+            "src/reflection.proto",
+        ])
         .include("src")
         .customize(customize)
         .run()
@@ -160,17 +160,56 @@ impl<'a> MethodGen<'a> {
     }
 
     fn write_handler_impl(&self, w: &mut CodeWriter) {
-        w.block(format!("impl ::ttrpc::MethodHandler for {}Method {{", self.struct_name()), "}",
-        |w| {
-            w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, req: ::ttrpc::Request) -> ::ttrpc::Result<()> {", "}",
-            |w| {
-                w.write_line(format!("::ttrpc::request_handler!(self, ctx, req, {}, {}, {});",
+        match self.method_type().0 {
+            MethodType::Unary => {
+                w.block(format!("impl ::ttrpc::MethodHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, req: ::ttrpc::Request) -> ::ttrpc::Result<()> {", "}",
+                    |w| {
+                        w.write_line(format!("::ttrpc::request_handler!(self, ctx, req, {}, {}, {});",
+                                                proto_path_to_rust_mod(self.root_scope.find_message(self.proto.input_type()).fd.name()),
+                                                self.root_scope.find_message(self.proto.input_type()).rust_name(),
+                                                self.name()));
+                        w.write_line("Ok(())");
+                    });
+                });
+            }
+            // only receive
+            MethodType::ClientStreaming => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::client_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+                });
+            }
+            // only send
+            MethodType::ServerStreaming => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, mut inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::server_streaming_handler!(self, ctx, inner, {}, {}, {});",
                                         proto_path_to_rust_mod(self.root_scope.find_message(self.proto.input_type()).fd.name()),
                                         self.root_scope.find_message(self.proto.input_type()).rust_name(),
                                         self.name()));
-                w.write_line("Ok(())");
-            });
-        });
+                    });
+                });
+            }
+            // receive and send
+            MethodType::Duplex => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::duplex_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+                });
+            }
+        }
     }
 
     fn write_handler_impl_async(&self, w: &mut CodeWriter) {
@@ -226,6 +265,15 @@ impl<'a> MethodGen<'a> {
         }
     }
 
+    // The module of the stream types, `r#async` or `sync`.
+    fn stream_mod(&self, side: &str) -> &'static str {
+        if async_on(self.customize, side) {
+            "r#async"
+        } else {
+            "sync"
+        }
+    }
+
     // Method signatures
     fn unary(&self, method_name: &str) -> String {
         format!(
@@ -242,7 +290,10 @@ impl<'a> MethodGen<'a> {
             "{}(&self, ctx: ttrpc::context::Context) -> {}<{}<{}, {}>>",
             method_name,
             fq_grpc("Result"),
-            fq_grpc("r#async::ClientStreamSender"),
+            fq_grpc(&format!(
+                "{}::ClientStreamSender",
+                self.stream_mod("client")
+            )),
             self.input(),
             self.output()
         )
@@ -254,7 +305,10 @@ impl<'a> MethodGen<'a> {
             method_name,
             self.input(),
             fq_grpc("Result"),
-            fq_grpc("r#async::ClientStreamReceiver"),
+            fq_grpc(&format!(
+                "{}::ClientStreamReceiver",
+                self.stream_mod("client")
+            )),
             self.output()
         )
     }
@@ -264,7 +318,7 @@ impl<'a> MethodGen<'a> {
             "{}(&self, ctx: ttrpc::context::Context) -> {}<{}<{}, {}>>",
             method_name,
             fq_grpc("Result"),
-            fq_grpc("r#async::ClientStream"),
+            fq_grpc(&format!("{}::ClientStream", self.stream_mod("client"))),
             self.input(),
             self.output()
         )
@@ -272,18 +326,54 @@ impl<'a> MethodGen<'a> {
 
     fn write_client(&self, w: &mut CodeWriter) {
         let method_name = self.name();
-        if let MethodType::Unary = self.method_type().0 {
-            w.pub_fn(self.unary(&method_name), |w| {
-                w.write_line(format!("let mut cres = {}::new();", self.output()));
-                w.write_line(format!(
-                    "::ttrpc::client_request!(self, ctx, req, \"{}.{}\", \"{}\", cres);",
-                    self.package_name,
-                    self.service_name,
-                    &self.proto.name(),
-                ));
-                w.write_line("Ok(cres)");
-            });
-        }
+        match self.method_type().0 {
+            // Unary RPC
+            MethodType::Unary => {
+                w.pub_fn(self.unary(&method_name), |w| {
+                    w.write_line(format!("let mut cres = {}::new();", self.output()));
+                    w.write_line(format!(
+                        "::ttrpc::client_request!(self, ctx, req, \"{}.{}\", \"{}\", cres);",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                    w.write_line("Ok(cres)");
+                });
+            }
+            // Client Streaming RPC
+            MethodType::ClientStreaming => {
+                w.pub_fn(self.client_streaming(&method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream_send!(self, ctx, \"{}.{}\", \"{}\");",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                });
+            }
+            // Server Streaming RPC
+            MethodType::ServerStreaming => {
+                w.pub_fn(self.server_streaming(&method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream_receive!(self, ctx, req, \"{}.{}\", \"{}\");",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                });
+            }
+            // Bidirectional streaming RPC
+            MethodType::Duplex => {
+                w.pub_fn(self.duplex_streaming(&method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream!(self, ctx, \"{}.{}\", \"{}\");",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                });
+            }
+        };
     }
 
     fn write_async_client(&self, w: &mut CodeWriter) {
@@ -338,19 +428,24 @@ impl<'a> MethodGen<'a> {
     }
 
     fn write_service(&self, w: &mut CodeWriter) {
+        let stream_mod = self.stream_mod("server");
         let (_req, req_type, resp_type) = match self.method_type().0 {
             MethodType::Unary => ("req", self.input(), self.output()),
             MethodType::ClientStreaming => (
                 "stream",
-                format!("::ttrpc::r#async::ServerStreamReceiver<{}>", self.input()),
+                format!(
+                    "::ttrpc::{}::ServerStreamReceiver<{}>",
+                    stream_mod,
+                    self.input()
+                ),
                 self.output(),
             ),
             MethodType::ServerStreaming => (
                 "req",
                 format!(
-                    "{}, _: {}<{}>",
+                    "{}, _: ::ttrpc::{}::ServerStreamSender<{}>",
                     self.input(),
-                    "::ttrpc::r#async::ServerStreamSender",
+                    stream_mod,
                     self.output()
                 ),
                 "()".to_string(),
@@ -358,8 +453,8 @@ impl<'a> MethodGen<'a> {
             MethodType::Duplex => (
                 "stream",
                 format!(
-                    "{}<{}, {}>",
-                    "::ttrpc::r#async::ServerStream",
+                    "::ttrpc::{}::ServerStream<{}, {}>",
+                    stream_mod,
                     self.output(),
                     self.input(),
                 ),
@@ -367,20 +462,29 @@ impl<'a> MethodGen<'a> {
             ),
         };
 
+        let result = match &self.customize.error_type {
+            Some(error_type) => format!("::std::result::Result<{resp_type}, {error_type}>"),
+            None => format!("::ttrpc::Result<{resp_type}>"),
+        };
         let get_sig = |context_name| {
             format!(
-                "{}(&self, _ctx: &{}, _: {}) -> ::ttrpc::Result<{}>",
+                "{}(&self, _ctx: &{}, _: {}) -> {}",
                 self.name(),
                 fq_grpc(context_name),
                 req_type,
-                resp_type,
+                result,
             )
         };
 
         let cb = |w: &mut CodeWriter| {
-            w.write_line(format!("Err(::ttrpc::Error::RpcStatus(::ttrpc::get_status(::ttrpc::Code::NOT_FOUND, \"/{}.{}/{} is not supported\".to_string())))",
+            let err = format!("::ttrpc::Error::RpcStatus(::ttrpc::get_status(::ttrpc::Code::NOT_FOUND, \"/{}.{}/{} is not supported\".to_string()))",
             self.package_name,
-            self.service_name, self.proto.name(),));
+            self.service_name, self.proto.name(),);
+            if self.customize.error_type.is_some() {
+                w.write_line(format!("Err({err}.into())"));
+            } else {
+                w.write_line(format!("Err({err})"));
+            }
         };
 
         if async_on(self.customize, "server") {
@@ -393,17 +497,27 @@ impl<'a> MethodGen<'a> {
     }
 
     fn write_bind(&self, w: &mut CodeWriter) {
-        let method_handler_name = "::ttrpc::MethodHandler";
-
-        let s = format!(
-            "methods.insert(\"/{}.{}/{}\".to_string(),
+        let s = if matches!(self.method_type().0, MethodType::Unary) {
+            format!(
+                "methods.insert(\"/{}.{}/{}\".to_string(),
                     Box::new({}Method{{service: service.clone()}}) as Box<dyn {} + Send + Sync>);",
-            self.package_name,
-            self.service_name,
-            self.proto.name(),
-            self.struct_name(),
-            method_handler_name,
-        );
+                self.package_name,
+                self.service_name,
+                self.proto.name(),
+                self.struct_name(),
+                "::ttrpc::MethodHandler",
+            )
+        } else {
+            format!(
+                "streams.insert(\"/{}.{}/{}\".to_string(),
+                    Arc::new({}Method{{service: service.clone()}}) as Arc<dyn {} + Send + Sync>);",
+                self.package_name,
+                self.service_name,
+                self.proto.name(),
+                self.struct_name(),
+                "::ttrpc::StreamHandler",
+            )
+        };
         w.write_line(&s);
     }
 
@@ -579,12 +693,38 @@ impl<'a> ServiceGen<'a> {
                 w.write_line("let methods = HashMap::new();");
             }
             for method in &self.methods[0..self.methods.len()] {
-                w.write_line("");
-                method.write_bind(w);
+                if matches!(method.method_type().0, MethodType::Unary) {
+                    w.write_line("");
+                    method.write_bind(w);
+                }
             }
             w.write_line("");
             w.write_line("methods");
         });
+
+        if !self.has_stream_method() {
+            return;
+        }
+
+        // Stream handlers are registered by `Server::register_stream_service`.
+        let s = format!(
+            "create_{}_streams(service: Arc<dyn {} + Send + Sync>) -> HashMap<String, Arc<dyn {} + Send + Sync>>",
+            to_snake_case(&self.service_name()),
+            self.service_name(),
+            "::ttrpc::StreamHandler",
+        );
+        w.write_line("");
+        w.pub_fn(&s, |w| {
+            w.write_line("let mut streams = HashMap::new();");
+            for method in &self.methods[0..self.methods.len()] {
+                if !matches!(method.method_type().0, MethodType::Unary) {
+                    w.write_line("");
+                    method.write_bind(w);
+                }
+            }
+            w.write_line("");
+            w.write_line("streams");
+        });
     }
 
     fn write_async_server_create(&self, w: &mut CodeWriter) {
@@ -803,3 +943,46 @@ where
     let result = gen(&req);
     result.write_to_writer(&mut stdout()).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_pinger(customize: &Customize) -> String {
+        let mut file = FileDescriptorProto::new();
+        file.set_name("pinger.proto".to_string());
+        file.set_package("test".to_string());
+        let mut message = DescriptorProto::new();
+        message.set_name("Ping".to_string());
+        file.message_type.push(message);
+        let mut method = MethodDescriptorProto::new();
+        method.set_name("Ping".to_string());
+        method.set_input_type(".test.Ping".to_string());
+        method.set_output_type(".test.Ping".to_string());
+        let mut service = ServiceDescriptorProto::new();
+        service.set_name("Pinger".to_string());
+        service.method.push(method);
+        file.service.push(service);
+
+        let res = gen(&[file], &["pinger.proto".to_string()], customize);
+        res.file[0].content().to_string()
+    }
+
+    #[test]
+    fn test_error_type() {
+        let code = gen_pinger(&Customize::default());
+        assert!(code.contains("fn ping(&self, _ctx: &::ttrpc::TtrpcContext, _: super::pinger::Ping) -> ::ttrpc::Result<super::pinger::Ping> {"));
+
+        for async_all in [false, true] {
+            let code = gen_pinger(&Customize {
+                async_all,
+                error_type: Some("crate::AgentError".to_string()),
+                ..Default::default()
+            });
+            assert!(code.contains(
+                "_: super::pinger::Ping) -> ::std::result::Result<super::pinger::Ping, crate::AgentError> {"
+            ));
+            assert!(code.contains("is not supported\".to_string())).into())"));
+        }
+    }
+}
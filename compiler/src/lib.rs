@@ -38,4 +38,9 @@ pub struct Customize {
     pub async_server: bool,
     /// Gen mod rs in mod.rs
     pub gen_mod: bool,
+    /// The error type returned by the service methods instead of
+    /// `::ttrpc::Error`, e.g. `crate::error::AgentError`. It must implement
+    /// `::ttrpc::IntoStatus` and `From<::ttrpc::Error>`, so the generated
+    /// code needs the ttrpc 0.9 runtime, the first one with `IntoStatus`.
+    pub error_type: Option<String>,
 }
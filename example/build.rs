@@ -12,14 +12,17 @@ use ttrpc_codegen::{Codegen, Customize, ProtobufCustomize};
 fn main() {
     fs::create_dir_all("protocols/sync").unwrap();
     fs::create_dir_all("protocols/asynchronous").unwrap();
+    fs::create_dir_all("protocols/error_type/sync").unwrap();
+    fs::create_dir_all("protocols/error_type/asynchronous").unwrap();
 
-    let mut protos = vec![
+    let protos = vec![
         "protocols/protos/github.com/gogo/protobuf/gogoproto/gogo.proto",
         "protocols/protos/github.com/kata-containers/agent/pkg/types/types.proto",
         "protocols/protos/agent.proto",
         "protocols/protos/health.proto",
         "protocols/protos/google/protobuf/empty.proto",
         "protocols/protos/oci.proto",
+        "protocols/protos/streaming.proto",
     ];
     let protobuf_customized = ProtobufCustomize::default().gen_mod_rs(true);
 
@@ -36,9 +39,6 @@ fn main() {
         .run()
         .expect("Gen sync code failed.");
 
-    // Only async support stream currently.
-    protos.push("protocols/protos/streaming.proto");
-
     Codegen::new()
         .out_dir("protocols/asynchronous")
         .inputs(&protos)
@@ -53,6 +53,42 @@ fn main() {
         .run()
         .expect("Gen async code failed.");
 
+    // The services of the error-type example return their own error type.
+    let protos = vec![
+        "protocols/protos/google/protobuf/empty.proto",
+        "protocols/protos/streaming.proto",
+    ];
+    let protobuf_customized = ProtobufCustomize::default().gen_mod_rs(true);
+
+    Codegen::new()
+        .out_dir("protocols/error_type/sync")
+        .inputs(&protos)
+        .include("protocols/protos")
+        .rust_protobuf()
+        .customize(Customize {
+            gen_mod: true,
+            error_type: Some("crate::ExampleError".to_string()),
+            ..Default::default()
+        })
+        .rust_protobuf_customize(protobuf_customized.clone())
+        .run()
+        .expect("Gen sync code with error type failed.");
+
+    Codegen::new()
+        .out_dir("protocols/error_type/asynchronous")
+        .inputs(&protos)
+        .include("protocols/protos")
+        .rust_protobuf()
+        .customize(Customize {
+            gen_mod: true,
+            async_all: true,
+            error_type: Some("crate::ExampleError".to_string()),
+            ..Default::default()
+        })
+        .rust_protobuf_customize(protobuf_customized)
+        .run()
+        .expect("Gen async code with error type failed.");
+
     // There is a message named 'Box' in oci.proto
     // so there is a struct named 'Box', we should replace Box<Self> to ::std::boxed::Box<Self>
     // to avoid the conflict.
@@ -0,0 +1,270 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The services generated with the `error_type` option, which return their
+//! own error type, answered to the clients with its status code. The sync
+//! and the async servers and clients run in the same process.
+
+#[rustfmt::skip]
+#[path = "protocols/error_type/sync/mod.rs"]
+mod sync_protocols;
+
+#[rustfmt::skip]
+#[path = "protocols/error_type/asynchronous/mod.rs"]
+mod async_protocols;
+
+use std::fmt;
+use std::sync::Arc;
+
+use ttrpc::context::{self, Context};
+use ttrpc::{Code, IntoStatus, Status};
+
+#[cfg(unix)]
+const SYNC_ADDR: &str = "memory://ttrpc-error-type";
+
+#[cfg(windows)]
+const SYNC_ADDR: &str = r"\\.\pipe\ttrpc-error-type";
+
+const ASYNC_ADDR: &str = "memory://ttrpc-error-type";
+
+/// The error type of the services, set with `error_type` in `build.rs`.
+#[derive(Debug)]
+pub enum ExampleError {
+    /// The sequence number 0 is reserved.
+    ReservedSeq,
+    /// A sum can't be divided in less than one part.
+    InvalidNum(i32),
+    /// The errors of ttrpc, e.g. of the streams.
+    Ttrpc(ttrpc::Error),
+}
+
+impl fmt::Display for ExampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExampleError::ReservedSeq => write!(f, "the sequence number 0 is reserved"),
+            ExampleError::InvalidNum(num) => write!(f, "can't divide a sum in {num} parts"),
+            ExampleError::Ttrpc(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<ttrpc::Error> for ExampleError {
+    fn from(e: ttrpc::Error) -> Self {
+        ExampleError::Ttrpc(e)
+    }
+}
+
+impl IntoStatus for ExampleError {
+    fn code(&self) -> Code {
+        match self {
+            ExampleError::ReservedSeq | ExampleError::InvalidNum(_) => Code::INVALID_ARGUMENT,
+            ExampleError::Ttrpc(e) => e.code(),
+        }
+    }
+
+    fn into_status(self) -> Status {
+        match self {
+            ExampleError::Ttrpc(e) => e.into_status(),
+            e => ttrpc::get_status(e.code(), e),
+        }
+    }
+}
+
+/// The sequence number of the answer to `seq`.
+fn echo(seq: u32) -> Result<u32, ExampleError> {
+    if seq == 0 {
+        return Err(ExampleError::ReservedSeq);
+    }
+    Ok(seq + 1)
+}
+
+/// The parts of the divided `sum`, the first one has it all.
+fn divide(sum: i32, num: i32) -> Result<Vec<i32>, ExampleError> {
+    if num < 1 {
+        return Err(ExampleError::InvalidNum(num));
+    }
+    let mut parts = vec![0; num as usize];
+    parts[0] = sum;
+    Ok(parts)
+}
+
+fn assert_status(e: ttrpc::Error, code: Code, message: &str) {
+    match e {
+        ttrpc::Error::RpcStatus(status) => {
+            assert_eq!(status.code(), code);
+            assert_eq!(status.message, message);
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+fn default_ctx() -> Context {
+    context::with_timeout(0)
+}
+
+mod sync {
+    use super::sync_protocols::{streaming, streaming_ttrpc};
+    use super::*;
+
+    struct StreamingService;
+
+    impl streaming_ttrpc::Streaming for StreamingService {
+        fn echo(
+            &self,
+            _ctx: &ttrpc::TtrpcContext,
+            mut req: streaming::EchoPayload,
+        ) -> Result<streaming::EchoPayload, ExampleError> {
+            req.seq = echo(req.seq)?;
+            Ok(req)
+        }
+
+        fn divide_stream(
+            &self,
+            _ctx: &ttrpc::TtrpcContext,
+            sum: streaming::Sum,
+            s: ttrpc::sync::ServerStreamSender<streaming::Part>,
+        ) -> Result<(), ExampleError> {
+            for add in divide(sum.sum, sum.num)? {
+                s.send(&streaming::Part {
+                    add,
+                    ..Default::default()
+                })?;
+            }
+            Ok(())
+        }
+    }
+
+    pub fn run() {
+        let service = Arc::new(StreamingService);
+        let mut server = ttrpc::Server::new()
+            .bind(SYNC_ADDR)
+            .unwrap()
+            .register_service(streaming_ttrpc::create_streaming(service.clone()))
+            .register_stream_service(streaming_ttrpc::create_streaming_streams(service));
+        server.start().unwrap();
+
+        let client = ttrpc::Client::connect(SYNC_ADDR).unwrap();
+        let client = streaming_ttrpc::StreamingClient::new(client);
+
+        let req = streaming::EchoPayload {
+            seq: 1,
+            ..Default::default()
+        };
+        assert_eq!(client.echo(default_ctx(), &req).unwrap().seq, 2);
+        let req = streaming::EchoPayload::default();
+        let e = client.echo(default_ctx(), &req).unwrap_err();
+        assert_status(
+            e,
+            Code::INVALID_ARGUMENT,
+            "the sequence number 0 is reserved",
+        );
+
+        let sum = streaming::Sum {
+            sum: 392,
+            num: 4,
+            ..Default::default()
+        };
+        let mut stream = client.divide_stream(default_ctx(), &sum).unwrap();
+        let mut total = 0;
+        while let Some(part) = stream.recv().unwrap() {
+            total += part.add;
+        }
+        assert_eq!(total, 392);
+
+        let sum = streaming::Sum::default();
+        let mut stream = client.divide_stream(default_ctx(), &sum).unwrap();
+        let e = stream.recv().unwrap_err();
+        assert_status(e, Code::INVALID_ARGUMENT, "can't divide a sum in 0 parts");
+
+        server.shutdown();
+        println!("***** Sync error type test is OK! *****");
+    }
+}
+
+mod asynchronous {
+    use super::async_protocols::{streaming, streaming_ttrpc};
+    use super::*;
+
+    struct StreamingService;
+
+    #[async_trait::async_trait]
+    impl streaming_ttrpc::Streaming for StreamingService {
+        async fn echo(
+            &self,
+            _ctx: &ttrpc::r#async::TtrpcContext,
+            mut req: streaming::EchoPayload,
+        ) -> Result<streaming::EchoPayload, ExampleError> {
+            req.seq = echo(req.seq)?;
+            Ok(req)
+        }
+
+        async fn divide_stream(
+            &self,
+            _ctx: &ttrpc::r#async::TtrpcContext,
+            sum: streaming::Sum,
+            s: ttrpc::r#async::ServerStreamSender<streaming::Part>,
+        ) -> Result<(), ExampleError> {
+            for add in divide(sum.sum, sum.num)? {
+                s.send(&streaming::Part {
+                    add,
+                    ..Default::default()
+                })
+                .await?;
+            }
+            Ok(())
+        }
+    }
+
+    pub async fn run() {
+        let service = Arc::new(StreamingService);
+        let mut server = ttrpc::r#async::Server::new()
+            .bind(ASYNC_ADDR)
+            .unwrap()
+            .register_service(streaming_ttrpc::create_streaming(service));
+        server.start().await.unwrap();
+
+        let client = ttrpc::r#async::Client::connect(ASYNC_ADDR).await.unwrap();
+        let client = streaming_ttrpc::StreamingClient::new(client);
+
+        let req = streaming::EchoPayload {
+            seq: 1,
+            ..Default::default()
+        };
+        assert_eq!(client.echo(default_ctx(), &req).await.unwrap().seq, 2);
+        let req = streaming::EchoPayload::default();
+        let e = client.echo(default_ctx(), &req).await.unwrap_err();
+        assert_status(
+            e,
+            Code::INVALID_ARGUMENT,
+            "the sequence number 0 is reserved",
+        );
+
+        let sum = streaming::Sum {
+            sum: 392,
+            num: 4,
+            ..Default::default()
+        };
+        let mut stream = client.divide_stream(default_ctx(), &sum).await.unwrap();
+        let mut total = 0;
+        while let Some(part) = stream.recv().await.unwrap() {
+            total += part.add;
+        }
+        assert_eq!(total, 392);
+
+        let sum = streaming::Sum::default();
+        let mut stream = client.divide_stream(default_ctx(), &sum).await.unwrap();
+        let e = stream.recv().await.unwrap_err();
+        assert_status(e, Code::INVALID_ARGUMENT, "can't divide a sum in 0 parts");
+
+        server.shutdown().await.unwrap();
+        println!("***** Async error type test is OK! *****");
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    simple_logging::log_to_stderr(log::LevelFilter::Info);
+
+    tokio::task::spawn_blocking(sync::run).await.unwrap();
+    asynchronous::run().await;
+}
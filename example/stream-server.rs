@@ -0,0 +1,187 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod protocols;
+mod utils;
+
+use std::sync::Arc;
+use std::thread;
+
+use log::LevelFilter;
+
+use protocols::sync::{empty, streaming, streaming_ttrpc};
+use ttrpc::{Error, Server};
+
+struct StreamingService;
+
+impl streaming_ttrpc::Streaming for StreamingService {
+    fn echo(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        mut e: streaming::EchoPayload,
+    ) -> ttrpc::Result<streaming::EchoPayload> {
+        e.seq += 1;
+        Ok(e)
+    }
+
+    fn echo_stream(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        mut s: ttrpc::sync::ServerStream<streaming::EchoPayload, streaming::EchoPayload>,
+    ) -> ttrpc::Result<()> {
+        while let Some(mut e) = s.recv()? {
+            e.seq += 1;
+            s.send(&e)?;
+        }
+
+        Ok(())
+    }
+
+    fn sum_stream(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        mut s: ttrpc::sync::ServerStreamReceiver<streaming::Part>,
+    ) -> ttrpc::Result<streaming::Sum> {
+        let mut sum = streaming::Sum::new();
+        while let Some(part) = s.recv()? {
+            sum.sum += part.add;
+            sum.num += 1;
+        }
+
+        Ok(sum)
+    }
+
+    fn divide_stream(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        sum: streaming::Sum,
+        s: ttrpc::sync::ServerStreamSender<streaming::Part>,
+    ) -> ttrpc::Result<()> {
+        let mut parts = vec![streaming::Part::new(); sum.num as usize];
+
+        let mut total = 0i32;
+        for i in 1..(sum.num - 2) {
+            let add = (rand::random::<u32>() % 1000) as i32 - 500;
+            parts[i as usize].add = add;
+            total += add;
+        }
+
+        parts[sum.num as usize - 2].add = sum.sum - total;
+
+        for part in parts {
+            s.send(&part).unwrap();
+        }
+
+        Ok(())
+    }
+
+    fn echo_null(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        mut s: ttrpc::sync::ServerStreamReceiver<streaming::EchoPayload>,
+    ) -> ttrpc::Result<empty::Empty> {
+        let mut seq = 0;
+        while let Some(e) = s.recv()? {
+            assert_eq!(e.seq, seq);
+            assert_eq!(e.msg.as_str(), "non-empty empty");
+            seq += 1;
+        }
+        Ok(empty::Empty::new())
+    }
+
+    fn echo_null_stream(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        s: ttrpc::sync::ServerStream<empty::Empty, streaming::EchoPayload>,
+    ) -> ttrpc::Result<()> {
+        let msg = "non-empty empty".to_string();
+
+        let mut threads = Vec::new();
+
+        let (tx, mut rx) = s.split();
+        let mut seq = 0u32;
+        while let Some(e) = rx.recv()? {
+            assert_eq!(e.seq, seq);
+            assert_eq!(e.msg, msg);
+            seq += 1;
+
+            for _i in 0..10 {
+                let tx = tx.clone();
+                threads.push(thread::spawn(move || tx.send(&empty::Empty::new())));
+            }
+        }
+
+        for t in threads {
+            t.join().unwrap().map_err(|e| {
+                ttrpc::Error::RpcStatus(ttrpc::get_status(ttrpc::Code::UNKNOWN, e.to_string()))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn echo_default_value(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        e: streaming::EchoPayload,
+        s: ttrpc::sync::ServerStreamSender<streaming::EchoPayload>,
+    ) -> ttrpc::Result<()> {
+        if e.seq != 0 || !e.msg.is_empty() {
+            return Err(Error::Others(
+                "Expect a request with empty payload".to_string(),
+            ));
+        }
+
+        s.send(&e).unwrap();
+
+        Ok(())
+    }
+
+    fn server_send_stream(
+        &self,
+        _ctx: &ttrpc::TtrpcContext,
+        _: empty::Empty,
+        s: ttrpc::sync::ServerStreamSender<streaming::EchoPayload>,
+    ) -> ttrpc::Result<()> {
+        let mut seq = 0;
+        while seq < 10 {
+            thread::sleep(std::time::Duration::from_millis(100));
+            let mut e = streaming::EchoPayload::new();
+            e.seq = seq;
+            e.msg = "hello".to_string();
+            s.send(&e).unwrap();
+            seq += 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    simple_logging::log_to_stderr(LevelFilter::Info);
+    let service = Arc::new(StreamingService {});
+    let methods = streaming_ttrpc::create_streaming(service.clone());
+    let streams = streaming_ttrpc::create_streaming_streams(service);
+
+    let sock_addr = utils::get_sock_addr();
+    utils::remove_if_sock_exist(sock_addr).unwrap();
+
+    let mut server = Server::new()
+        .bind(sock_addr)
+        .unwrap()
+        .register_service(methods)
+        .register_stream_service(streams);
+
+    server.start().unwrap();
+
+    // Hold the main thread until receiving signal SIGTERM
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        ctrlc::set_handler(move || {
+            tx.send(()).unwrap();
+        })
+        .expect("Error setting Ctrl-C handler");
+        println!("Server is running, press Ctrl + C to exit");
+    });
+
+    rx.recv().unwrap();
+}
@@ -10,30 +10,85 @@ use std::convert::TryInto;
 use std::os::unix::io::RawFd;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::{self, sync::mpsc, task};
+use tokio::{
+    self,
+    sync::{mpsc, watch},
+    task,
+};
 
-use crate::error::{get_rpc_status, Error, Result};
+use crate::error::{get_rpc_status, Error, IntoStatus, Result};
+use crate::metrics::{CallRecord, Side};
 use crate::proto::{
-    Code, Codec, GenMessage, Message, MessageHeader, Request, Response, FLAG_NO_DATA,
+    Code, Codec, GenMessage, Message, MessageHeader, MessageSizeLimits, Request, Response,
+    SharedSizeLimits, FLAG_NO_DATA,
     FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
 };
 use crate::r#async::connection::*;
+use crate::r#async::interceptor::{
+    ClientInterceptor, ClientInterceptors, ClientNext, ClientStreamNext,
+};
 use crate::r#async::shutdown;
 use crate::r#async::stream::{
     Kind, MessageReceiver, MessageSender, ResultReceiver, ResultSender, StreamInner,
 };
+use crate::r#async::utils;
+use crate::retry::{Retry, RetryPolicy};
 
 use super::stream::SendingMessage;
 use super::transport::Socket;
 
+type SharedMessageReceiver = Arc<tokio::sync::Mutex<MessageReceiver>>;
+
+/// The state of the connection of a [`Client`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The connection is established, requests are sent to the server.
+    Connected,
+    /// The connection is lost and the client is re-dialing the server,
+    /// requests fail with [`Error::LocalClosed`] in the meantime.
+    Reconnecting,
+    /// The connection is lost for good.
+    Closed,
+}
+
+/// How a [`Client`] created by [`Client::connect_with_reconnect`] re-dials the
+/// server after the connection is lost.
+///
+/// The delay before each attempt starts from `initial_backoff` and is
+/// multiplied by `multiplier` after each failed attempt, up to `max_backoff`.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// The number of attempts before giving up, `None` means retrying forever.
+    pub max_attempts: Option<usize>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            max_attempts: None,
+        }
+    }
+}
+
 /// A ttrpc Client (async).
 #[derive(Clone)]
 pub struct Client {
     req_tx: MessageSender,
     next_stream_id: Arc<AtomicU32>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    interceptors: ClientInterceptors,
+    retry_policy: Option<Arc<RetryPolicy>>,
+    size_limits: Arc<SharedSizeLimits>,
+    state: watch::Receiver<ConnectionState>,
 }
 
 impl Client {
@@ -44,6 +99,19 @@ impl Client {
         Ok(Self::new(socket))
     }
 
+    /// Connects to `sockaddr` like [`Client::connect`], and re-dials the same
+    /// address following `policy` whenever the connection is lost.
+    ///
+    /// The requests and streams in flight when the connection is lost fail
+    /// with an error, they are not resent. Use [`Client::connection_state`]
+    /// to follow the state of the connection.
+    pub async fn connect_with_reconnect(sockaddr: &str, policy: ReconnectPolicy) -> Result<Client> {
+        let socket = Socket::connect(sockaddr)
+            .await
+            .map_err(err_to_others_err!(e, "Socket::connect error "))?;
+        Ok(Self::start(socket, Some((sockaddr.to_string(), policy))))
+    }
+
     #[cfg(unix)]
     /// # Safety
     /// The file descriptor must represent a unix socket.
@@ -54,31 +122,138 @@ impl Client {
 
     /// Initialize a new [`Client`].
     pub fn new(stream: Socket) -> Client {
+        Self::start(stream, None)
+    }
+
+    fn start(stream: Socket, redial: Option<(String, ReconnectPolicy)>) -> Client {
         let (req_tx, rx): (MessageSender, MessageReceiver) = mpsc::channel(100);
+        let (state_tx, state) = watch::channel(ConnectionState::Connected);
 
         let req_map = Arc::new(Mutex::new(HashMap::new()));
         let delegate = ClientBuilder {
-            rx: Some(rx),
+            rx: Arc::new(tokio::sync::Mutex::new(rx)),
             streams: req_map.clone(),
         };
 
-        let conn = Connection::new(stream, delegate);
-        // Long-running receiver task
-        tokio::spawn(async move { conn.run().await });
+        let size_limits = Arc::new(SharedSizeLimits::default());
+        let conn_limits = size_limits.clone();
+        let conn = Connection::new(stream, delegate.clone(), conn_limits.clone());
+        let weak_tx = req_tx.downgrade();
+        // Long-running receiver task, which re-dials the server on disconnection
+        // until all the clients are dropped.
+        tokio::spawn(async move {
+            let mut conn = conn;
+            loop {
+                conn.run().await.ok();
+                if weak_tx.upgrade().is_none() {
+                    break;
+                }
+                let Some((sockaddr, policy)) = &redial else {
+                    break;
+                };
+                state_tx.send_replace(ConnectionState::Reconnecting);
+                match redial_with_backoff(sockaddr, policy, &delegate, &weak_tx).await {
+                    Some(socket) => {
+                        conn = Connection::new(socket, delegate.clone(), conn_limits.clone());
+                        state_tx.send_replace(ConnectionState::Connected);
+                    }
+                    None => break,
+                }
+            }
+            state_tx.send_replace(ConnectionState::Closed);
+            delegate.fail_pending().await;
+        });
 
         Client {
             req_tx,
             next_stream_id: Arc::new(AtomicU32::new(1)),
             streams: req_map,
+            interceptors: Arc::new(Vec::new()),
+            retry_policy: None,
+            size_limits,
+            state,
         }
     }
 
+    /// Returns a receiver following the state of the connection.
+    ///
+    /// The state of a [`Client`] which is not created by
+    /// [`Client::connect_with_reconnect`] goes from `Connected` to `Closed`.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    fn check_connected(&self) -> Result<()> {
+        match *self.state.borrow() {
+            ConnectionState::Connected => Ok(()),
+            _ => Err(Error::LocalClosed),
+        }
+    }
+
+    /// Appends an interceptor to the chain wrapping every request and stream.
+    ///
+    /// Interceptors run in the order they are added. The chain is owned by this
+    /// [`Client`] and the clones created afterwards.
+    pub fn add_interceptor(mut self, interceptor: impl ClientInterceptor + 'static) -> Client {
+        Arc::make_mut(&mut self.interceptors).push(Arc::new(interceptor));
+        self
+    }
+
+    /// Sets the policy retrying the unary requests which fail.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Client {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Sets the maximum sizes of the messages sent and received.
+    ///
+    /// The limits apply to the connection, they are shared by all the clones
+    /// of this [`Client`].
+    pub fn with_message_size_limits(self, limits: MessageSizeLimits) -> Client {
+        self.size_limits.set(limits);
+        self
+    }
+
     /// Requsts a unary request and returns with response.
-    pub async fn request(&self, req: Request) -> Result<Response> {
+    pub async fn request(&self, mut req: Request) -> Result<Response> {
+        let Some(policy) = self.retry_policy.as_deref() else {
+            return ClientNext::new(&self.interceptors, self).run(req).await;
+        };
+
+        let path = utils::get_path(&req.service, &req.method);
+        let mut retry = Retry::new(policy.get(&path), req.timeout_nano);
+        loop {
+            let e = match ClientNext::new(&self.interceptors, self)
+                .run(req.clone())
+                .await
+            {
+                Err(e) => e,
+                res => return res,
+            };
+            let Some(delay) = retry.backoff(&e) else {
+                return Err(e);
+            };
+            debug!("Retry request {} in {:?} on error {:?}", path, delay, e);
+            tokio::time::sleep(delay).await;
+            req.timeout_nano = retry.timeout_nano();
+        }
+    }
+
+    pub(crate) async fn do_request(&self, req: Request) -> Result<Response> {
+        let record = CallRecord::start(Side::Client, &req.service, &req.method, false);
+        let res = self.send_request(req).await;
+        if let Some(record) = record {
+            record.finish(res.as_ref().map_or_else(IntoStatus::code, |_| Code::OK));
+        }
+        res
+    }
+
+    async fn send_request(&self, req: Request) -> Result<Response> {
+        self.check_connected()?;
         let timeout_nano = req.timeout_nano;
         let stream_id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
 
-        let msg: GenMessage = Message::new_request(stream_id, req)?
+        let msg: GenMessage = Message::new_request_with_limit(stream_id, req, self.size_limits.max_send())?
             .try_into()
             .map_err(|e: protobuf::Error| Error::Others(e.to_string()))?;
 
@@ -88,11 +263,18 @@ impl Client {
             .lock()
             .map_err(|_| Error::Others("Failed to acquire lock on streams".to_string()))?
             .insert(stream_id, tx);
+        let mut guard = RequestGuard {
+            client: self,
+            stream_id,
+            sent: false,
+            done: false,
+        };
 
         self.req_tx
             .send(SendingMessage::new(msg))
             .await
             .map_err(|_| Error::LocalClosed)?;
+        guard.sent = true;
 
         let result = if timeout_nano == 0 {
             rx.recv().await.ok_or_else(|| Error::RemoteClosed)?
@@ -106,6 +288,7 @@ impl Client {
             .ok_or_else(|| Error::RemoteClosed)?
         };
 
+        guard.done = true;
         let msg = result?;
 
         let res = Response::decode(msg.payload)
@@ -126,10 +309,48 @@ impl Client {
         streaming_client: bool,
         streaming_server: bool,
     ) -> Result<StreamInner> {
+        ClientStreamNext::new(
+            &self.interceptors,
+            self,
+            streaming_client,
+            streaming_server,
+        )
+        .run(req)
+        .await
+    }
+
+    pub(crate) async fn do_new_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        let record = CallRecord::start(Side::Client, &req.service, &req.method, true);
+        match self
+            .open_stream(req, streaming_client, streaming_server)
+            .await
+        {
+            Ok(stream) => Ok(stream.with_call(record)),
+            Err(e) => {
+                if let Some(record) = record {
+                    record.finish(e.code());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn open_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        self.check_connected()?;
         let stream_id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
         let is_req_payload_empty = req.payload.is_empty();
 
-        let mut msg: GenMessage = Message::new_request(stream_id, req)?
+        let mut msg: GenMessage = Message::new_request_with_limit(stream_id, req, self.size_limits.max_send())?
             .try_into()
             .map_err(|e: protobuf::Error| Error::Others(e.to_string()))?;
 
@@ -164,20 +385,113 @@ impl Client {
             streaming_server,
             Kind::Client,
             self.streams.clone(),
-        ))
+        )
+        .with_max_send(self.size_limits.max_send()))
     }
 }
 
-#[derive(Debug)]
+/// Cleans up an unary request which is given up before receiving its response,
+/// e.g. it is timed out or its future is dropped.
+struct RequestGuard<'a> {
+    client: &'a Client,
+    stream_id: u32,
+    sent: bool,
+    done: bool,
+}
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Ok(mut streams) = self.client.streams.lock() {
+            streams.remove(&self.stream_id);
+        }
+        if !self.sent {
+            return;
+        }
+
+        // Tell the server to cancel the request by closing the stream, the
+        // same as the golang ttrpc client closing a stream.
+        let mut header = MessageHeader::new_data(self.stream_id, 0);
+        header.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+        let msg = GenMessage {
+            header,
+            payload: Vec::new(),
+        };
+        if let Err(e) = self.client.req_tx.try_send(SendingMessage::new(msg)) {
+            debug!("Failed to cancel the request {}: {}", self.stream_id, e);
+        }
+    }
+}
+
+/// Re-dials `sockaddr` until it succeeds, returns `None` if the attempts run
+/// out or all the clients are dropped.
+async fn redial_with_backoff(
+    sockaddr: &str,
+    policy: &ReconnectPolicy,
+    delegate: &ClientBuilder,
+    weak_tx: &mpsc::WeakSender<SendingMessage>,
+) -> Option<Socket> {
+    let mut backoff = policy.initial_backoff;
+    let mut attempts = 0;
+    loop {
+        if policy.max_attempts.is_some_and(|max| attempts >= max) {
+            warn!("Give up reconnecting to {} after {} attempts", sockaddr, attempts);
+            return None;
+        }
+        attempts += 1;
+
+        tokio::time::sleep(backoff).await;
+        // Fail the requests issued since the connection was lost.
+        delegate.fail_pending().await;
+        // Give up once all the clients are dropped.
+        weak_tx.upgrade()?;
+
+        match Socket::connect(sockaddr).await {
+            Ok(socket) => {
+                info!("Reconnected to {} after {} attempts", sockaddr, attempts);
+                return Some(socket);
+            }
+            Err(e) => debug!("Failed to reconnect to {}: {:?}", sockaddr, e),
+        }
+        // A multiplier which is negative or NaN, or a backoff which overflows,
+        // backs off for `max_backoff`.
+        backoff = Duration::try_from_secs_f64(backoff.as_secs_f64() * policy.multiplier)
+            .unwrap_or(policy.max_backoff)
+            .min(policy.max_backoff);
+    }
+}
+
+#[derive(Clone, Debug)]
 struct ClientBuilder {
-    rx: Option<MessageReceiver>,
+    rx: SharedMessageReceiver,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
 }
 
+impl ClientBuilder {
+    /// Drops the messages which are not sent yet and fails the pending
+    /// requests, as the connection they were issued on is gone.
+    async fn fail_pending(&self) {
+        let mut rx = self.rx.lock().await;
+        while let Ok(mut sending_msg) = rx.try_recv() {
+            sending_msg.send_result(Err(Error::LocalClosed));
+        }
+        drop(rx);
+
+        let map = std::mem::take(&mut *self.streams.lock().unwrap());
+        for (_stream_id, resp_tx) in map {
+            resp_tx.try_send(Err(Error::LocalClosed)).ok();
+        }
+    }
+}
+
 impl Builder for ClientBuilder {
     type Reader = ClientReader;
     type Writer = ClientWriter;
 
+    const SIDE: Side = Side::Client;
+
     fn build(&mut self) -> (Self::Reader, Self::Writer) {
         let (notifier, waiter) = shutdown::new();
         (
@@ -186,7 +500,7 @@ impl Builder for ClientBuilder {
                 streams: self.streams.clone(),
             },
             ClientWriter {
-                rx: self.rx.take().unwrap(),
+                rx: self.rx.clone(),
                 shutdown_notifier: notifier,
 
                 streams: self.streams.clone(),
@@ -196,7 +510,7 @@ impl Builder for ClientBuilder {
 }
 
 struct ClientWriter {
-    rx: MessageReceiver,
+    rx: SharedMessageReceiver,
     shutdown_notifier: shutdown::Notifier,
 
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
@@ -205,7 +519,7 @@ struct ClientWriter {
 #[async_trait]
 impl WriterDelegate for ClientWriter {
     async fn recv(&mut self) -> Option<SendingMessage> {
-        self.rx.recv().await
+        self.rx.lock().await.recv().await
     }
 
     async fn disconnect(&self, msg: &GenMessage, e: Error) {
@@ -336,3 +650,121 @@ impl ReaderDelegate for ClientReader {
         });
     }
 }
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::get_rpc_status;
+    use crate::r#async::{MethodHandler, Server, Service, TtrpcContext};
+    use crate::retry::RetryPolicy;
+    use tokio::time::timeout;
+
+    async fn wait_state(client: &Client, state: ConnectionState) {
+        let mut rx = client.connection_state();
+        timeout(Duration::from_secs(5), rx.wait_for(|s| *s == state))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    fn request() -> Request {
+        Request {
+            service: "test".to_string(),
+            method: "Unknown".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-reconnect";
+        let mut server = Server::new().bind(addr).unwrap();
+        server.start().await.unwrap();
+
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let client = Client::connect_with_reconnect(addr, policy).await.unwrap();
+        let res = client.request(request()).await;
+        assert!(matches!(res, Err(Error::RpcStatus(_))));
+
+        server.shutdown().await.unwrap();
+        wait_state(&client, ConnectionState::Reconnecting).await;
+        let res = client.request(request()).await;
+        assert!(matches!(res, Err(Error::LocalClosed)));
+
+        let mut server = Server::new().bind(addr).unwrap();
+        server.start().await.unwrap();
+        wait_state(&client, ConnectionState::Connected).await;
+        let res = client.request(request()).await;
+        assert!(matches!(res, Err(Error::RpcStatus(_))));
+    }
+
+    struct Flaky {
+        failures: AtomicU32,
+    }
+
+    #[async_trait]
+    impl MethodHandler for Flaky {
+        async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let failure = self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            if failure.is_ok() {
+                return Err(get_rpc_status(Code::UNAVAILABLE, "try again"));
+            }
+            Ok(Response::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-retry";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert(
+            "Flaky".to_string(),
+            Box::new(Flaky {
+                failures: AtomicU32::new(3),
+            }),
+        );
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let req = Request {
+            service: "test".to_string(),
+            method: "Flaky".to_string(),
+            ..Default::default()
+        };
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        // Stop retrying once the attempts run out.
+        let client = Client::connect(addr)
+            .await
+            .unwrap()
+            .with_retry_policy(policy.clone().with_method(
+                "/test/Flaky",
+                RetryPolicy {
+                    max_attempts: 2,
+                    ..policy.clone()
+                },
+            ));
+        let res = client.request(req.clone()).await;
+        assert!(matches!(res, Err(Error::RpcStatus(s)) if s.code() == Code::UNAVAILABLE));
+
+        // The third failure is retried with the default policy of 3 attempts.
+        let client = client.with_retry_policy(policy);
+        client.request(req.clone()).await.unwrap();
+    }
+}
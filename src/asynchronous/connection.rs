@@ -4,13 +4,18 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use log::{error, trace};
 use tokio::io::split;
 use tokio::{io::ReadHalf, select, task};
 
 use crate::error::Error;
-use crate::proto::{GenMessage, GenMessageError, MessageHeader};
+use crate::metrics::{self, ConnectionRecord, Side};
+use crate::proto::{
+    GenMessage, GenMessageError, MessageHeader, SharedSizeLimits, MESSAGE_HEADER_LENGTH,
+};
 
 use super::{stream::SendingMessage, transport::Socket};
 
@@ -18,6 +23,9 @@ pub trait Builder {
     type Reader;
     type Writer;
 
+    /// The side the metrics of the connection are recorded on.
+    const SIDE: Side;
+
     fn build(&mut self) -> (Self::Reader, Self::Writer);
 }
 
@@ -41,6 +49,7 @@ pub struct Connection<B: Builder> {
     reader: ReadHalf<Socket>,
     writer_task: task::JoinHandle<()>,
     reader_delegate: B::Reader,
+    size_limits: Arc<SharedSizeLimits>,
 }
 
 impl<B> Connection<B>
@@ -49,7 +58,7 @@ where
     B::Reader: ReaderDelegate + Send + Sync + 'static,
     B::Writer: WriterDelegate + Send + Sync + 'static,
 {
-    pub fn new(conn: Socket, mut builder: B) -> Self {
+    pub fn new(conn: Socket, mut builder: B, size_limits: Arc<SharedSizeLimits>) -> Self {
         let (reader, mut writer) = split(conn);
 
         let (reader_delegate, mut writer_delegate) = builder.build();
@@ -62,6 +71,9 @@ where
                     error!("write_message got error: {:?}", e);
                     sending_msg.send_result(Err(e.clone()));
                     writer_delegate.disconnect(&sending_msg.msg, e).await;
+                } else {
+                    let len = MESSAGE_HEADER_LENGTH + sending_msg.msg.payload.len();
+                    metrics::message_sent(B::SIDE, len);
                 }
                 sending_msg.send_result(Ok(()));
             }
@@ -73,6 +85,7 @@ where
             reader,
             writer_task,
             reader_delegate,
+            size_limits,
         }
     }
 
@@ -81,17 +94,23 @@ where
             mut reader,
             mut writer_task,
             reader_delegate,
+            size_limits,
         } = self;
+        let _record = ConnectionRecord::open(B::SIDE);
         loop {
             select! {
-                res = GenMessage::read_from(&mut reader) => {
+                res = GenMessage::read_from_with_limit(&mut reader, size_limits.max_recv()) => {
                     match res {
                         Ok(msg) => {
                             trace!("Got Message {:?}", msg);
+                            let len = MESSAGE_HEADER_LENGTH + msg.payload.len();
+                            metrics::message_received(B::SIDE, len);
                             reader_delegate.handle_msg(msg).await;
                         }
                         Err(GenMessageError::ReturnError(header, e)) => {
                             trace!("Read msg err (can be return): {:?}", e);
+                            let len = MESSAGE_HEADER_LENGTH + header.length as usize;
+                            metrics::message_received(B::SIDE, len);
                             reader_delegate.handle_err(header, e).await;
                         }
 
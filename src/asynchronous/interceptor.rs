@@ -0,0 +1,528 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Interceptors wrapping the handling of every request (async).
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::error::Result;
+use crate::proto::{Request, Response};
+use crate::r#async::stream::StreamInner;
+use crate::r#async::{Client, MethodHandler, StreamHandler, TtrpcContext};
+
+/// Server side interceptor, registered by [`Server::add_interceptor`].
+///
+/// Interceptors are called in the order they were added, each one deciding
+/// whether to call `next.run()` to continue the chain or to short-circuit it
+/// by returning a response or an error (e.g. `Error::RpcStatus`) directly.
+///
+/// [`Server::add_interceptor`]: crate::asynchronous::Server::add_interceptor
+#[async_trait]
+pub trait ServerInterceptor: Send + Sync {
+    /// Intercepts an unary request.
+    async fn intercept(
+        &self,
+        ctx: TtrpcContext,
+        req: Request,
+        next: MethodNext<'_>,
+    ) -> Result<Response> {
+        next.run(ctx, req).await
+    }
+
+    /// Intercepts a streaming request.
+    ///
+    /// `req` carries the service, method and metadata of the stream, its
+    /// payload (if any) is delivered through `stream` as the first message.
+    async fn intercept_stream(
+        &self,
+        ctx: TtrpcContext,
+        req: &Request,
+        stream: StreamInner,
+        next: StreamNext<'_>,
+    ) -> Result<Option<Response>> {
+        next.run(ctx, req, stream).await
+    }
+}
+
+pub(crate) type ServerInterceptors = Arc<Vec<Arc<dyn ServerInterceptor>>>;
+
+/// The remaining part of the unary interceptor chain.
+pub struct MethodNext<'a> {
+    interceptors: &'a [Arc<dyn ServerInterceptor>],
+    handler: &'a (dyn MethodHandler + Send + Sync),
+}
+
+impl<'a> MethodNext<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn ServerInterceptor>],
+        handler: &'a (dyn MethodHandler + Send + Sync),
+    ) -> Self {
+        Self {
+            interceptors,
+            handler,
+        }
+    }
+
+    /// Calls the next interceptor, or the method handler at the end of the chain.
+    pub async fn run(self, ctx: TtrpcContext, req: Request) -> Result<Response> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor
+                    .intercept(ctx, req, MethodNext::new(rest, self.handler))
+                    .await
+            }
+            None => self.handler.handler(ctx, req).await,
+        }
+    }
+}
+
+/// The remaining part of the streaming interceptor chain.
+pub struct StreamNext<'a> {
+    interceptors: &'a [Arc<dyn ServerInterceptor>],
+    handler: &'a (dyn StreamHandler + Send + Sync),
+}
+
+impl<'a> StreamNext<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn ServerInterceptor>],
+        handler: &'a (dyn StreamHandler + Send + Sync),
+    ) -> Self {
+        Self {
+            interceptors,
+            handler,
+        }
+    }
+
+    /// Calls the next interceptor, or the stream handler at the end of the chain.
+    pub async fn run(
+        self,
+        ctx: TtrpcContext,
+        req: &Request,
+        stream: StreamInner,
+    ) -> Result<Option<Response>> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor
+                    .intercept_stream(ctx, req, stream, StreamNext::new(rest, self.handler))
+                    .await
+            }
+            None => self.handler.handler(ctx, stream).await,
+        }
+    }
+}
+
+/// Client side interceptor, registered by [`Client::add_interceptor`].
+///
+/// Interceptors are called in the order they were added. They may rewrite the
+/// outgoing request, observe the response and map the returned error.
+#[async_trait]
+pub trait ClientInterceptor: Send + Sync {
+    /// Intercepts an unary request.
+    async fn intercept(&self, req: Request, next: ClientNext<'_>) -> Result<Response> {
+        next.run(req).await
+    }
+
+    /// Intercepts the creation of a stream.
+    async fn intercept_stream(
+        &self,
+        req: Request,
+        next: ClientStreamNext<'_>,
+    ) -> Result<StreamInner> {
+        next.run(req).await
+    }
+}
+
+pub(crate) type ClientInterceptors = Arc<Vec<Arc<dyn ClientInterceptor>>>;
+
+/// The remaining part of the client unary interceptor chain.
+pub struct ClientNext<'a> {
+    interceptors: &'a [Arc<dyn ClientInterceptor>],
+    client: &'a Client,
+}
+
+impl<'a> ClientNext<'a> {
+    pub(crate) fn new(interceptors: &'a [Arc<dyn ClientInterceptor>], client: &'a Client) -> Self {
+        Self {
+            interceptors,
+            client,
+        }
+    }
+
+    /// Calls the next interceptor, or sends the request at the end of the chain.
+    pub async fn run(self, req: Request) -> Result<Response> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor
+                    .intercept(req, ClientNext::new(rest, self.client))
+                    .await
+            }
+            None => self.client.do_request(req).await,
+        }
+    }
+}
+
+/// The remaining part of the client streaming interceptor chain.
+pub struct ClientStreamNext<'a> {
+    interceptors: &'a [Arc<dyn ClientInterceptor>],
+    client: &'a Client,
+    streaming_client: bool,
+    streaming_server: bool,
+}
+
+impl<'a> ClientStreamNext<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn ClientInterceptor>],
+        client: &'a Client,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Self {
+        Self {
+            interceptors,
+            client,
+            streaming_client,
+            streaming_server,
+        }
+    }
+
+    /// Returns `true` if the client sends a stream of messages.
+    pub fn streaming_client(&self) -> bool {
+        self.streaming_client
+    }
+
+    /// Returns `true` if the server sends a stream of messages.
+    pub fn streaming_server(&self) -> bool {
+        self.streaming_server
+    }
+
+    /// Calls the next interceptor, or creates the stream at the end of the chain.
+    pub async fn run(self, req: Request) -> Result<StreamInner> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                let next = ClientStreamNext::new(
+                    rest,
+                    self.client,
+                    self.streaming_client,
+                    self.streaming_server,
+                );
+                interceptor.intercept_stream(req, next).await
+            }
+            None => {
+                self.client
+                    .do_new_stream(req, self.streaming_client, self.streaming_server)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::error::{get_rpc_status, Error};
+    use crate::proto::{Code, KeyValue, MessageHeader};
+    use crate::r#async::{Server, Service};
+
+    struct Echo;
+
+    #[async_trait]
+    impl MethodHandler for Echo {
+        async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+            let mut resp = Response::new();
+            resp.payload = req.payload;
+            Ok(resp)
+        }
+    }
+
+    struct Record {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        reject: bool,
+    }
+
+    #[async_trait]
+    impl ServerInterceptor for Record {
+        async fn intercept(
+            &self,
+            ctx: TtrpcContext,
+            req: Request,
+            next: MethodNext<'_>,
+        ) -> Result<Response> {
+            self.calls.lock().unwrap().push(self.name);
+            if self.reject {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, self.name));
+            }
+            next.run(ctx, req).await
+        }
+
+        async fn intercept_stream(
+            &self,
+            ctx: TtrpcContext,
+            req: &Request,
+            stream: StreamInner,
+            next: StreamNext<'_>,
+        ) -> Result<Option<Response>> {
+            self.calls.lock().unwrap().push(self.name);
+            if self.reject {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, self.name));
+            }
+            next.run(ctx, req, stream).await
+        }
+    }
+
+    struct Hello;
+
+    #[async_trait]
+    impl StreamHandler for Hello {
+        async fn handler(
+            &self,
+            _ctx: TtrpcContext,
+            inner: StreamInner,
+        ) -> Result<Option<Response>> {
+            inner.send(b"hello".to_vec()).await?;
+            Ok(None)
+        }
+    }
+
+    fn context() -> TtrpcContext {
+        TtrpcContext {
+            mh: MessageHeader::default(),
+            metadata: HashMap::new(),
+            timeout_nano: 0,
+            deadline: None,
+            cancel: crate::r#async::shutdown::new().1,
+            peer: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptor_chain() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let interceptors: Vec<Arc<dyn ServerInterceptor>> = vec![
+            Arc::new(Record {
+                name: "first",
+                calls: calls.clone(),
+                reject: false,
+            }),
+            Arc::new(Record {
+                name: "second",
+                calls: calls.clone(),
+                reject: false,
+            }),
+        ];
+
+        let mut req = Request::new();
+        req.payload = vec![1, 2, 3];
+        let resp = MethodNext::new(&interceptors, &Echo)
+            .run(context(), req)
+            .await
+            .unwrap();
+        assert_eq!(resp.payload, vec![1, 2, 3]);
+        assert_eq!(*calls.lock().unwrap(), vec!["first", "second"]);
+
+        calls.lock().unwrap().clear();
+        let interceptors: Vec<Arc<dyn ServerInterceptor>> = vec![
+            Arc::new(Record {
+                name: "deny",
+                calls: calls.clone(),
+                reject: true,
+            }),
+            Arc::new(Record {
+                name: "never",
+                calls: calls.clone(),
+                reject: false,
+            }),
+        ];
+        let err = MethodNext::new(&interceptors, &Echo)
+            .run(context(), Request::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::PERMISSION_DENIED));
+        assert_eq!(*calls.lock().unwrap(), vec!["deny"]);
+    }
+
+    #[tokio::test]
+    async fn stream_interceptor_chain() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let record = |name, reject| Record {
+            name,
+            calls: calls.clone(),
+            reject,
+        };
+        let server = |addr, interceptors: [Record; 2]| {
+            let mut streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>> = HashMap::new();
+            streams.insert("Hello".to_string(), Arc::new(Hello));
+            let service = Service {
+                methods: HashMap::new(),
+                streams,
+            };
+            let [first, second] = interceptors;
+            Server::new()
+                .bind(addr)
+                .unwrap()
+                .register_service(HashMap::from([("test".to_string(), service)]))
+                .add_interceptor(first)
+                .add_interceptor(second)
+        };
+        let req = || Request {
+            service: "test".to_string(),
+            method: "Hello".to_string(),
+            ..Default::default()
+        };
+
+        let addr = "memory://interceptor-unit-test-stream";
+        let mut passing = server(addr, [record("first", false), record("second", false)]);
+        passing.start().await.unwrap();
+        let client = Client::connect(addr).await.unwrap();
+        let mut stream = client.new_stream(req(), false, true).await.unwrap();
+        assert_eq!(stream.recv().await.unwrap(), b"hello");
+        assert_eq!(*calls.lock().unwrap(), vec!["first", "second"]);
+        passing.shutdown().await.unwrap();
+
+        calls.lock().unwrap().clear();
+        let addr = "memory://interceptor-unit-test-stream-deny";
+        let mut denying = server(addr, [record("deny", true), record("never", false)]);
+        denying.start().await.unwrap();
+        let client = Client::connect(addr).await.unwrap();
+        let mut stream = client.new_stream(req(), false, true).await.unwrap();
+        let err = stream.recv().await.unwrap_err();
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::PERMISSION_DENIED));
+        assert_eq!(*calls.lock().unwrap(), vec!["deny"]);
+        denying.shutdown().await.unwrap();
+    }
+
+    /// The tags added to the metadata by the client interceptors.
+    fn tags(ctx: &TtrpcContext) -> String {
+        ctx.metadata
+            .get("tag")
+            .map_or(String::new(), |t| t.join(","))
+    }
+
+    struct Tags;
+
+    #[async_trait]
+    impl MethodHandler for Tags {
+        async fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<Response> {
+            let mut resp = Response::new();
+            resp.payload = req.payload;
+            resp.payload.extend(format!("|{}", tags(&ctx)).into_bytes());
+            Ok(resp)
+        }
+    }
+
+    struct TagsStream;
+
+    #[async_trait]
+    impl StreamHandler for TagsStream {
+        async fn handler(&self, ctx: TtrpcContext, inner: StreamInner) -> Result<Option<Response>> {
+            inner.send(tags(&ctx).into_bytes()).await?;
+            Ok(None)
+        }
+    }
+
+    /// Tags the requests, or rejects them.
+    struct Tag {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        reject: bool,
+    }
+
+    impl Tag {
+        fn tag(&self, req: &mut Request) -> Result<()> {
+            self.calls.lock().unwrap().push(self.name);
+            if self.reject {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, self.name));
+            }
+            req.payload.extend(format!("{}.", self.name).into_bytes());
+            req.metadata.push(KeyValue {
+                key: "tag".to_string(),
+                value: self.name.to_string(),
+                ..Default::default()
+            });
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl ClientInterceptor for Tag {
+        async fn intercept(&self, mut req: Request, next: ClientNext<'_>) -> Result<Response> {
+            self.tag(&mut req)?;
+            next.run(req).await
+        }
+
+        async fn intercept_stream(
+            &self,
+            mut req: Request,
+            next: ClientStreamNext<'_>,
+        ) -> Result<StreamInner> {
+            assert!(!next.streaming_client());
+            assert!(next.streaming_server());
+            self.tag(&mut req)?;
+            next.run(req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn client_interceptor_chain() {
+        let addr = "memory://interceptor-unit-test-client";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Tags".to_string(), Box::new(Tags));
+        let mut streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>> = HashMap::new();
+        streams.insert("TagsStream".to_string(), Arc::new(TagsStream));
+        let service = Service { methods, streams };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let tag = |name, reject| Tag {
+            name,
+            calls: calls.clone(),
+            reject,
+        };
+        let client = Client::connect(addr).await.unwrap();
+        let tagged = client
+            .clone()
+            .add_interceptor(tag("first", false))
+            .add_interceptor(tag("second", false));
+        let rejected = client
+            .add_interceptor(tag("deny", true))
+            .add_interceptor(tag("never", false));
+        let req = |method: &str| Request {
+            service: "test".to_string(),
+            method: method.to_string(),
+            ..Default::default()
+        };
+
+        // The interceptors run in order, and change the request and its
+        // metadata.
+        let resp = tagged.request(req("Tags")).await.unwrap();
+        assert_eq!(resp.payload, b"first.second.|first,second");
+        assert_eq!(*calls.lock().unwrap(), vec!["first", "second"]);
+
+        let mut stream = tagged
+            .new_stream(req("TagsStream"), false, true)
+            .await
+            .unwrap();
+        assert_eq!(stream.recv().await.unwrap(), b"first,second");
+
+        // The interceptors short-circuit the chain with an error.
+        calls.lock().unwrap().clear();
+        let err = rejected.request(req("Tags")).await.unwrap_err();
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::PERMISSION_DENIED));
+        let err = rejected
+            .new_stream(req("TagsStream"), false, true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::PERMISSION_DENIED));
+        assert_eq!(*calls.lock().unwrap(), vec!["deny", "deny"]);
+
+        server.shutdown().await.unwrap();
+    }
+}
@@ -6,6 +6,7 @@
 //! Server and client in async mode (alias r#async).
 
 mod client;
+mod interceptor;
 mod server;
 mod stream;
 #[macro_use]
@@ -21,8 +22,14 @@ pub use self::stream::{
     StreamSender,
 };
 #[doc(inline)]
-pub use crate::r#async::client::Client;
+pub use crate::r#async::client::{Client, ConnectionState, ReconnectPolicy};
 #[doc(inline)]
-pub use crate::r#async::server::{Server, Service};
+pub use crate::r#async::interceptor::{
+    ClientInterceptor, ClientNext, ClientStreamNext, MethodNext, ServerInterceptor, StreamNext,
+};
+#[doc(inline)]
+pub use crate::r#async::server::{
+    AbortedCall, DrainReport, Limits, ListenerId, Server, Service,
+};
 #[doc(inline)]
 pub use utils::{MethodHandler, StreamHandler, TtrpcContext};
@@ -9,34 +9,42 @@ use std::convert::TryFrom;
 #[cfg(unix)]
 use std::os::unix::io::RawFd;
 use std::result::Result as StdResult;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use futures::StreamExt as _;
+use protobuf::descriptor::FileDescriptorProto;
 use protobuf::Message as _;
 use tokio::{
     self, select, spawn,
     sync::mpsc::{channel, Sender},
+    sync::{Notify, OwnedSemaphorePermit, Semaphore},
     task,
-    time::timeout,
+    time::{sleep, timeout_at},
 };
 
 use crate::asynchronous::stream::SendingMessage;
 use crate::asynchronous::transport::{Listener, Socket};
 use crate::context;
 use crate::error::{get_status, Error, Result};
+use crate::metrics::{CallRecord, Side};
 use crate::proto::{
-    check_oversize, Code, Codec, GenMessage, Message, MessageHeader, Request, Response, Status,
+    check_oversize, Code, Codec, GenMessage, Message, MessageHeader, MessageSizeLimits, Request,
+    Response, SharedSizeLimits, Status,
     FLAG_NO_DATA, FLAG_REMOTE_CLOSED, MESSAGE_TYPE_DATA, MESSAGE_TYPE_REQUEST,
 };
+use crate::peer::PeerInfo;
 use crate::r#async::connection::*;
+use crate::r#async::interceptor::{MethodNext, ServerInterceptor, ServerInterceptors, StreamNext};
 use crate::r#async::shutdown;
 use crate::r#async::stream::{
     Kind, MessageReceiver, MessageSender, ResultReceiver, ResultSender, StreamInner,
 };
 use crate::r#async::utils;
 use crate::r#async::{MethodHandler, StreamHandler, TtrpcContext};
+use crate::reflection;
 
 const DEFAULT_CONN_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 const DEFAULT_SERVER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
@@ -56,22 +64,146 @@ impl Service {
     }
 }
 
+/// The services of a server, by name, which can be changed while it runs.
+type Services = Arc<RwLock<HashMap<String, Arc<Service>>>>;
+
+/// Limits of the resources used by the clients of a [`Server`], unlimited by
+/// default.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The maximum number of connections, the new connections are closed
+    /// once it is reached.
+    pub max_connections: Option<usize>,
+    /// The maximum number of unary requests handled concurrently on a
+    /// connection.
+    pub max_requests_per_connection: Option<usize>,
+    /// The maximum number of streams open concurrently on a connection.
+    pub max_streams_per_connection: Option<usize>,
+    /// Stops reading a connection at its limit of requests or streams until
+    /// one of them finishes, instead of answering `RESOURCE_EXHAUSTED`.
+    ///
+    /// The client must not wait for the answer of a request before sending
+    /// the messages of the streams it opened earlier, they are not read
+    /// meanwhile.
+    pub backpressure: bool,
+}
+
+/// A call still in flight when the deadline of [`Server::drain`] expired.
+#[derive(Clone, Debug)]
+pub struct AbortedCall {
+    pub service: String,
+    pub method: String,
+    pub stream_id: u32,
+    /// Whether the call is a stream rather than an unary request.
+    pub streaming: bool,
+    pub peer: PeerInfo,
+}
+
+/// What [`Server::drain`] did to the calls of the clients.
+#[derive(Clone, Debug, Default)]
+pub struct DrainReport {
+    /// The number of requests answered `UNAVAILABLE` while draining.
+    pub rejected: usize,
+    /// The calls which did not finish before the deadline.
+    pub aborted: Vec<AbortedCall>,
+}
+
+/// The calls in flight on the connections of a server, which rejects the
+/// new ones once it is draining.
+#[derive(Default)]
+struct Drain {
+    draining: AtomicBool,
+    rejected: AtomicUsize,
+    next_id: AtomicU64,
+    calls: Mutex<HashMap<u64, AbortedCall>>,
+    idle: Notify,
+}
+
+impl Drain {
+    /// Tracks a call until the returned guard is dropped, fails with
+    /// `UNAVAILABLE` if the server is draining.
+    fn track(self: &Arc<Self>, call: AbortedCall) -> StdResult<CallGuard, Status> {
+        // Checked under the lock of the calls, so the drain either sees the
+        // call or the call sees the drain.
+        let mut calls = self.calls.lock().unwrap();
+        if self.draining.load(Ordering::SeqCst) {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+            return Err(get_status(Code::UNAVAILABLE, "server is draining"));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        calls.insert(id, call);
+        Ok(CallGuard {
+            drain: self.clone(),
+            id,
+        })
+    }
+
+    fn set_draining(&self, draining: bool) {
+        let _calls = self.calls.lock().unwrap();
+        self.draining.store(draining, Ordering::SeqCst);
+    }
+
+    /// Waits for the calls in flight to finish.
+    async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            if self.calls.lock().unwrap().is_empty() {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+struct CallGuard {
+    drain: Arc<Drain>,
+    id: u64,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        let mut calls = self.drain.calls.lock().unwrap();
+        calls.remove(&self.id);
+        if calls.is_empty() {
+            self.drain.idle.notify_waiters();
+        }
+    }
+}
+
+/// Identifies a listener of a [`Server`], see [`Server::serve_listener`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ListenerId(u64);
+
 /// A ttrpc Server (async).
 pub struct Server {
-    listeners: Vec<Listener>,
-    services: Arc<HashMap<String, Service>>,
+    listeners: Vec<(ListenerId, Listener)>,
+    next_listener_id: u64,
+    services: Services,
+    interceptors: ServerInterceptors,
+    reflection: Option<Vec<FileDescriptorProto>>,
+    limits: Limits,
+    size_limits: MessageSizeLimits,
+    drain: Arc<Drain>,
 
     shutdown: shutdown::Notifier,
-    stop_listen_tx: Option<Sender<Sender<Listener>>>,
+    acceptor: Option<Acceptor>,
+    stop_listen_txs: HashMap<ListenerId, Sender<Sender<Listener>>>,
 }
 
 impl Default for Server {
     fn default() -> Self {
         Server {
             listeners: Vec::with_capacity(1),
-            services: Arc::new(HashMap::new()),
+            next_listener_id: 0,
+            services: Arc::new(RwLock::new(HashMap::new())),
+            interceptors: Arc::new(Vec::new()),
+            reflection: None,
+            limits: Limits::default(),
+            size_limits: MessageSizeLimits::default(),
+            drain: Arc::new(Drain::default()),
             shutdown: shutdown::with_timeout(DEFAULT_SERVER_SHUTDOWN_TIMEOUT).0,
-            stop_listen_tx: None,
+            acceptor: None,
+            stop_listen_txs: HashMap::new(),
         }
     }
 }
@@ -88,10 +220,35 @@ impl Server {
     }
 
     pub fn add_listener(mut self, listener: Listener) -> Server {
-        self.listeners.push(listener);
+        self.serve_listener(listener);
         self
     }
 
+    /// Adds a listener, which is served right away if the server is started,
+    /// or with the others when it starts.
+    pub fn serve_listener(&mut self, listener: Listener) -> ListenerId {
+        let id = ListenerId(self.next_listener_id);
+        self.next_listener_id += 1;
+        match &self.acceptor {
+            Some(acceptor) => {
+                let stop_listen_tx = acceptor.spawn(listener);
+                self.stop_listen_txs.insert(id, stop_listen_tx);
+            }
+            None => self.listeners.push((id, listener)),
+        }
+        id
+    }
+
+    /// Stops serving a listener and returns it, the connections it accepted
+    /// are kept.
+    pub async fn remove_listener(&mut self, id: ListenerId) -> Option<Listener> {
+        if let Some(tx) = self.stop_listen_txs.remove(&id) {
+            return Acceptor::stop(tx).await;
+        }
+        let index = self.listeners.iter().position(|(i, _)| *i == id)?;
+        Some(self.listeners.remove(index).1)
+    }
+
     #[cfg(unix)]
     /// # Safety
     /// The file descriptor must represent a unix listener.
@@ -119,30 +276,193 @@ impl Server {
         Ok(self.add_listener(listener))
     }
 
-    pub fn register_service(mut self, new: HashMap<String, Service>) -> Server {
-        let services = Arc::get_mut(&mut self.services).unwrap();
-        services.extend(new);
+    pub fn register_service(self, new: HashMap<String, Service>) -> Server {
+        for (name, service) in new {
+            self.add_service(name, service);
+        }
         self
     }
 
-    fn get_listener(&mut self) -> Result<Listener> {
-        self.listeners.pop().ok_or_else(|| {
-            Error::Others("ttrpc-rust server started with no bound listener".to_string())
-        })
+    /// Adds a service, or replaces the one of the same name, also while the
+    /// server is running.
+    pub fn add_service(&self, name: impl Into<String>, service: Service) {
+        let mut services = self.services.write().unwrap();
+        services.insert(name.into(), Arc::new(service));
     }
 
+    /// Removes a service while the server is running, returns `false` if
+    /// there is no such service. The calls in flight complete, the new ones
+    /// are answered `UNIMPLEMENTED`.
+    pub fn remove_service(&self, name: &str) -> bool {
+        self.services.write().unwrap().remove(name).is_some()
+    }
+
+    /// Registers the reflection service, which lists the services of the
+    /// server and returns the given file descriptors.
+    pub fn register_reflection(mut self, files: Vec<FileDescriptorProto>) -> Server {
+        self.reflection = Some(files);
+        self
+    }
+
+    /// Sets the limits of the resources used by the clients.
+    pub fn with_limits(mut self, limits: Limits) -> Server {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the maximum sizes of the messages sent and received.
+    pub fn with_message_size_limits(mut self, limits: MessageSizeLimits) -> Server {
+        self.size_limits = limits;
+        self
+    }
+
+    /// Appends an interceptor to the chain wrapping every method and stream handler.
+    ///
+    /// Interceptors run in the order they are added.
+    pub fn add_interceptor(mut self, interceptor: impl ServerInterceptor + 'static) -> Server {
+        let interceptors = Arc::get_mut(&mut self.interceptors).unwrap();
+        interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Serves all the listeners, concurrently.
     pub async fn start(&mut self) -> Result<()> {
-        let incoming = self.get_listener()?;
-        self.do_start(incoming).await
+        if self.listeners.is_empty() {
+            return Err(Error::Others(
+                "ttrpc-rust server started with no bound listener".to_string(),
+            ));
+        }
+        self.do_start();
+        for (id, listener) in std::mem::take(&mut self.listeners) {
+            let stop_listen_tx = self.acceptor.as_ref().unwrap().spawn(listener);
+            self.stop_listen_txs.insert(id, stop_listen_tx);
+        }
+        Ok(())
     }
 
-    async fn do_start(&mut self, mut incoming: Listener) -> Result<()> {
-        let services = self.services.clone();
+    fn do_start(&mut self) {
+        if let Some(files) = self.reflection.take() {
+            // Weak, the reflection service is itself one of the services.
+            let services = Arc::downgrade(&self.services);
+            let registry = reflection::Registry::new(
+                move || {
+                    let Some(services) = services.upgrade() else {
+                        return Vec::new();
+                    };
+                    let services = services.read().unwrap();
+                    services
+                        .iter()
+                        .flat_map(|(name, service)| {
+                            let methods = service.methods.keys().map(move |m| (name, m, false));
+                            let streams = service.streams.keys().map(move |m| (name, m, true));
+                            methods.chain(streams)
+                        })
+                        .map(|(name, m, streaming)| (name.clone(), m.clone(), streaming))
+                        .collect()
+                },
+                files,
+            );
+            let reflection = reflection::r#async::create_reflection(registry);
+            let reflection = reflection.into_iter().map(|(name, s)| (name, Arc::new(s)));
+            self.services.write().unwrap().extend(reflection);
+        }
 
-        let shutdown_waiter = self.shutdown.subscribe();
+        let limits = self.limits.clone();
+        self.drain.set_draining(false);
+        self.acceptor = Some(Acceptor {
+            services: self.services.clone(),
+            interceptors: self.interceptors.clone(),
+            connections: limits.max_connections.map(|n| Arc::new(Semaphore::new(n))),
+            limits,
+            size_limits: self.size_limits,
+            drain: self.drain.clone(),
+            shutdown_waiter: self.shutdown.subscribe(),
+        });
+    }
 
-        let (stop_listen_tx, mut stop_listen_rx) = channel(1);
-        self.stop_listen_tx = Some(stop_listen_tx);
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.stop_listen().await;
+        self.disconnect().await;
+        self.listeners.clear();
+        Ok(())
+    }
+
+    /// Shuts the server down gracefully: stops listening, answers the new
+    /// requests `UNAVAILABLE` and waits for the calls in flight until
+    /// `deadline`, then closes the connections, aborting the calls left.
+    pub async fn drain(&mut self, deadline: Instant) -> Result<DrainReport> {
+        self.stop_listen().await;
+        self.drain.set_draining(true);
+
+        let aborted = match timeout_at(deadline.into(), self.drain.wait_idle()).await {
+            Ok(()) => Vec::new(),
+            Err(_) => self.drain.calls.lock().unwrap().values().cloned().collect(),
+        };
+        for call in &aborted {
+            warn!(
+                "abort {}/{} of stream {} on drain",
+                call.service, call.method, call.stream_id
+            );
+        }
+
+        self.disconnect().await;
+        self.listeners.clear();
+        Ok(DrainReport {
+            rejected: self.drain.rejected.swap(0, Ordering::SeqCst),
+            aborted,
+        })
+    }
+
+    pub async fn disconnect(&mut self) {
+        self.shutdown.shutdown();
+
+        self.shutdown
+            .wait_all_exit()
+            .await
+            .map_err(|e| {
+                trace!("wait connection exit error: {}", e);
+            })
+            .ok();
+        trace!("wait connection exit.");
+    }
+
+    /// Stops serving all the listeners, which are served again on the next
+    /// start.
+    pub async fn stop_listen(&mut self) {
+        self.acceptor = None;
+        for (id, tx) in std::mem::take(&mut self.stop_listen_txs) {
+            if let Some(listener) = Acceptor::stop(tx).await {
+                self.listeners.push((id, listener));
+            }
+        }
+        self.listeners.sort_by_key(|(id, _)| *id);
+    }
+}
+
+/// Accepts the connections of the listeners of a started server.
+struct Acceptor {
+    services: Services,
+    interceptors: ServerInterceptors,
+    limits: Limits,
+    size_limits: MessageSizeLimits,
+    connections: Option<Arc<Semaphore>>,
+    drain: Arc<Drain>,
+    shutdown_waiter: shutdown::Waiter,
+}
+
+impl Acceptor {
+    /// Serves `incoming` until the listener is requested back through the
+    /// returned sender.
+    fn spawn(&self, mut incoming: Listener) -> Sender<Sender<Listener>> {
+        let services = self.services.clone();
+        let interceptors = self.interceptors.clone();
+        let limits = self.limits.clone();
+        let size_limits = self.size_limits;
+        let connections = self.connections.clone();
+        let drain = self.drain.clone();
+        let shutdown_waiter = self.shutdown_waiter.clone();
+
+        let (stop_listen_tx, mut stop_listen_rx) = channel::<Sender<Listener>>(1);
 
         spawn(async move {
             loop {
@@ -152,10 +472,23 @@ impl Server {
                             // Accept a new connection
                             match conn {
                                 Ok(conn) => {
+                                    let permit = match &connections {
+                                        Some(connections) => match connections.clone().try_acquire_owned() {
+                                            Ok(permit) => Some(permit),
+                                            Err(_) => {
+                                                warn!("too many connections, close the new one");
+                                                continue;
+                                            }
+                                        },
+                                        None => None,
+                                    };
                                     // spawn a connection handler, would not block
                                     spawn_connection_handler(
                                         conn,
                                         services.clone(),
+                                        interceptors.clone(),
+                                        ConnectionLimits::new(&limits, size_limits, permit),
+                                        drain.clone(),
                                         shutdown_waiter.clone(),
                                     ).await;
                                 }
@@ -177,52 +510,37 @@ impl Server {
                 }
             }
         });
-        Ok(())
-    }
-
-    pub async fn shutdown(&mut self) -> Result<()> {
-        self.stop_listen().await;
-        self.disconnect().await;
-        drop(self.listeners.pop());
-        Ok(())
-    }
-
-    pub async fn disconnect(&mut self) {
-        self.shutdown.shutdown();
-
-        self.shutdown
-            .wait_all_exit()
-            .await
-            .map_err(|e| {
-                trace!("wait connection exit error: {}", e);
-            })
-            .ok();
-        trace!("wait connection exit.");
+        stop_listen_tx
     }
 
-    pub async fn stop_listen(&mut self) {
-        if let Some(tx) = self.stop_listen_tx.take() {
-            let (fd_tx, mut fd_rx) = channel(1);
-            tx.send(fd_tx).await.unwrap();
-
-            let fd = fd_rx.recv().await.unwrap();
-            self.listeners.clear();
-            self.listeners.push(fd);
-        }
+    /// Stops serving a listener and returns it, unless it was exhausted.
+    async fn stop(stop_listen_tx: Sender<Sender<Listener>>) -> Option<Listener> {
+        let (fd_tx, mut fd_rx) = channel(1);
+        stop_listen_tx.send(fd_tx).await.ok()?;
+        fd_rx.recv().await
     }
 }
 
 async fn spawn_connection_handler(
     conn: Socket,
-    services: Arc<HashMap<String, Service>>,
+    services: Services,
+    interceptors: ServerInterceptors,
+    limits: ConnectionLimits,
+    drain: Arc<Drain>,
     shutdown_waiter: shutdown::Waiter,
 ) {
+    let size_limits = Arc::new(SharedSizeLimits::new(limits.size));
     let delegate = ServerBuilder {
         services,
+        interceptors,
+        limits,
+        peer: conn.peer().clone(),
+        drain,
         streams: Arc::new(Mutex::new(HashMap::new())),
+        cancels: Arc::new(Mutex::new(HashMap::new())),
         shutdown_waiter,
     };
-    let conn = Connection::new(conn, delegate);
+    let conn = Connection::new(conn, delegate, size_limits);
     spawn(async move {
         conn.run()
             .await
@@ -233,9 +551,65 @@ async fn spawn_connection_handler(
     });
 }
 
+/// The limits of a connection, it holds its permit of the server until it
+/// is dropped.
+#[derive(Clone)]
+struct ConnectionLimits {
+    requests: Option<Arc<Semaphore>>,
+    streams: Option<Arc<Semaphore>>,
+    backpressure: bool,
+    size: MessageSizeLimits,
+    _connection: Option<Arc<OwnedSemaphorePermit>>,
+}
+
+impl ConnectionLimits {
+    fn new(
+        limits: &Limits,
+        size: MessageSizeLimits,
+        connection: Option<OwnedSemaphorePermit>,
+    ) -> Self {
+        let semaphore = |max: Option<usize>| max.map(|n| Arc::new(Semaphore::new(n)));
+        ConnectionLimits {
+            requests: semaphore(limits.max_requests_per_connection),
+            streams: semaphore(limits.max_streams_per_connection),
+            backpressure: limits.backpressure,
+            size,
+            _connection: connection.map(Arc::new),
+        }
+    }
+
+    /// Acquires a permit of `semaphore`, waits for it with backpressure or
+    /// fails with `RESOURCE_EXHAUSTED`.
+    async fn acquire(
+        &self,
+        semaphore: &Option<Arc<Semaphore>>,
+        what: &str,
+    ) -> StdResult<Option<OwnedSemaphorePermit>, Status> {
+        let Some(semaphore) = semaphore else {
+            return Ok(None);
+        };
+        let permit = if self.backpressure {
+            semaphore.clone().acquire_owned().await.ok()
+        } else {
+            semaphore.clone().try_acquire_owned().ok()
+        };
+        permit.map(Some).ok_or_else(|| {
+            get_status(
+                Code::RESOURCE_EXHAUSTED,
+                format!("too many concurrent {what}"),
+            )
+        })
+    }
+}
+
 struct ServerBuilder {
-    services: Arc<HashMap<String, Service>>,
+    services: Services,
+    interceptors: ServerInterceptors,
+    limits: ConnectionLimits,
+    peer: PeerInfo,
+    drain: Arc<Drain>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    cancels: Cancels,
     shutdown_waiter: shutdown::Waiter,
 }
 
@@ -243,6 +617,8 @@ impl Builder for ServerBuilder {
     type Reader = ServerReader;
     type Writer = ServerWriter;
 
+    const SIDE: Side = Side::Server;
+
     fn build(&mut self) -> (Self::Reader, Self::Writer) {
         let (tx, rx): (MessageSender, MessageReceiver) = channel(100);
         let (disconnect_notifier, _disconnect_waiter) =
@@ -252,7 +628,12 @@ impl Builder for ServerBuilder {
             ServerReader {
                 tx,
                 services: self.services.clone(),
+                interceptors: self.interceptors.clone(),
+                limits: self.limits.clone(),
+                peer: self.peer.clone(),
+                drain: self.drain.clone(),
                 streams: self.streams.clone(),
+                cancels: self.cancels.clone(),
                 server_shutdown: self.shutdown_waiter.clone(),
                 handler_shutdown: disconnect_notifier,
             },
@@ -280,8 +661,13 @@ impl WriterDelegate for ServerWriter {
 
 struct ServerReader {
     tx: MessageSender,
-    services: Arc<HashMap<String, Service>>,
+    services: Services,
+    interceptors: ServerInterceptors,
+    limits: ConnectionLimits,
+    peer: PeerInfo,
+    drain: Arc<Drain>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    cancels: Cancels,
     server_shutdown: shutdown::Waiter,
     handler_shutdown: shutdown::Notifier,
 }
@@ -298,6 +684,9 @@ impl ReaderDelegate for ServerReader {
     }
 
     async fn exit(&self) {
+        // Cancel the pending requests/streams, the connection is gone or the
+        // server is shutting down.
+        self.cancels.lock().unwrap().clear();
         // TODO: Don't self.conn_shutdown.shutdown();
         // Wait pedding request/stream to exit.
         self.handler_shutdown
@@ -321,7 +710,11 @@ impl ReaderDelegate for ServerReader {
                     _ = handler_shutdown_waiter.wait_shutdown() => {}
                 }
             });
-            wait_rx.await.unwrap_or_default();
+            // The handler may wait for a permit, don't block the shutdown.
+            select! {
+                _ = wait_rx => {}
+                _ = self.server_shutdown.wait_shutdown() => {}
+            }
         }
     }
 
@@ -335,7 +728,12 @@ impl ServerReader {
         HandlerContext {
             tx: self.tx.clone(),
             services: self.services.clone(),
+            interceptors: self.interceptors.clone(),
+            limits: self.limits.clone(),
+            peer: self.peer.clone(),
+            drain: self.drain.clone(),
             streams: self.streams.clone(),
+            cancels: self.cancels.clone(),
             _handler_shutdown_waiter: self.handler_shutdown.subscribe(),
         }
     }
@@ -343,8 +741,13 @@ impl ServerReader {
 
 struct HandlerContext {
     tx: MessageSender,
-    services: Arc<HashMap<String, Service>>,
+    services: Services,
+    interceptors: ServerInterceptors,
+    limits: ConnectionLimits,
+    peer: PeerInfo,
+    drain: Arc<Drain>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    cancels: Cancels,
     // Used for waiting handler exit.
     _handler_shutdown_waiter: shutdown::Waiter,
 }
@@ -376,7 +779,11 @@ impl HandlerContext {
                 Ok(opt_msg) => match opt_msg {
                     Some(mut resp) => {
                         // Server: check size before sending to client
-                        if let Err(e) = check_oversize(resp.compute_size() as usize, true) {
+                        if let Err(e) = check_oversize(
+                            resp.compute_size() as usize,
+                            self.limits.size.max_send,
+                            true,
+                        ) {
                             resp = e.into();
                         }
 
@@ -437,6 +844,8 @@ impl HandlerContext {
                         )
                         .await;
                     }
+                } else if self.cancel_request(&msg.header) {
+                    debug!("Stream id {stream_id}: cancelled by the client");
                 } else {
                     Self::respond_with_status(
                         self.tx.clone(),
@@ -471,19 +880,64 @@ impl HandlerContext {
         let req = &req_msg.payload;
         trace!("Got Message request {} {}", req.service, req.method);
 
-        let srv = self.services.get(&req.service).ok_or_else(|| {
+        let srv = self.services.read().unwrap().get(&req.service).cloned();
+        let srv = srv.ok_or_else(|| {
             get_status(
-                Code::INVALID_ARGUMENT,
+                Code::UNIMPLEMENTED,
                 format!("{} service does not exist", &req.service),
             )
         })?;
 
+        // The unknown methods are not recorded, their names are up to the clients.
+        let streaming = srv.get_method(&req.method).is_none();
+        let record = if !streaming || srv.get_stream(&req.method).is_some() {
+            CallRecord::start(Side::Server, &req.service, &req.method, streaming)
+        } else {
+            None
+        };
+        let res = self.dispatch_request(srv, req_msg, wait_tx).await;
+        if let Some(record) = record {
+            record.finish(match &res {
+                Ok(Some(resp)) => resp.status().code(),
+                Ok(None) => Code::OK,
+                Err(status) => status.code(),
+            });
+        }
+        res
+    }
+
+    async fn dispatch_request(
+        &self,
+        srv: Arc<Service>,
+        req_msg: Message<Request>,
+        wait_tx: tokio::sync::oneshot::Sender<()>,
+    ) -> StdResult<Option<Response>, Status> {
+        let req = &req_msg.payload;
+        let call = |streaming| AbortedCall {
+            service: req.service.clone(),
+            method: req.method.clone(),
+            stream_id: req_msg.header.stream_id,
+            streaming,
+            peer: self.peer.clone(),
+        };
+
+        // Acquire the permit before releasing the reader, which then waits for
+        // it with backpressure.
         if let Some(method) = srv.get_method(&req.method) {
+            let _call = self.drain.track(call(false))?;
+            let _permit = self
+                .limits
+                .acquire(&self.limits.requests, "requests")
+                .await?;
+            let (_guard, cancel) = self.register_cancel(&req_msg, true);
             drop(wait_tx);
-            return self.handle_method(method, req_msg).await;
+            return self.handle_method(method, req_msg, cancel).await;
         }
         if let Some(stream) = srv.get_stream(&req.method) {
-            return self.handle_stream(stream, req_msg, wait_tx).await;
+            let _call = self.drain.track(call(true))?;
+            let _permit = self.limits.acquire(&self.limits.streams, "streams").await?;
+            let (_guard, cancel) = self.register_cancel(&req_msg, false);
+            return self.handle_stream(stream, req_msg, wait_tx, cancel).await;
         }
         Err(get_status(
             Code::UNIMPLEMENTED,
@@ -495,31 +949,29 @@ impl HandlerContext {
         &self,
         method: &(dyn MethodHandler + Send + Sync),
         req_msg: Message<Request>,
+        cancel: shutdown::Waiter,
     ) -> StdResult<Option<Response>, Status> {
         let req = req_msg.payload;
         let path = utils::get_path(&req.service, &req.method);
 
+        let deadline = context::deadline(req.timeout_nano);
         let ctx = TtrpcContext {
             mh: req_msg.header,
             metadata: context::from_pb(&req.metadata),
             timeout_nano: req.timeout_nano,
+            cancel,
+            deadline,
+            peer: self.peer.clone(),
         };
 
         let get_unknown_status_and_log_err = |e| {
             error!("method handle {} got error {:?}", path, &e);
-            get_status(Code::UNKNOWN, e)
+            // Keeps the status returned by an interceptor short-circuiting the chain.
+            Status::from(e)
         };
-        if req.timeout_nano == 0 {
-            method
-                .handler(ctx, req)
-                .await
-                .map_err(get_unknown_status_and_log_err)
-                .map(Some)
-        } else {
-            timeout(
-                Duration::from_nanos(req.timeout_nano as u64),
-                method.handler(ctx, req),
-            )
+        let next = MethodNext::new(&self.interceptors, method);
+        if let Some(deadline) = deadline {
+            timeout_at(deadline.into(), next.run(ctx, req))
             .await
             .map_err(|_| {
                 // Timed out
@@ -531,6 +983,11 @@ impl HandlerContext {
                 r.map_err(get_unknown_status_and_log_err)
             })
             .map(Some)
+        } else {
+            next.run(ctx, req)
+                .await
+                .map_err(get_unknown_status_and_log_err)
+                .map(Some)
         }
     }
 
@@ -539,9 +996,11 @@ impl HandlerContext {
         stream: Arc<dyn StreamHandler + Send + Sync>,
         req_msg: Message<Request>,
         wait_tx: tokio::sync::oneshot::Sender<()>,
+        cancel: shutdown::Waiter,
     ) -> StdResult<Option<Response>, Status> {
         let stream_id = req_msg.header.stream_id;
-        let req = req_msg.payload;
+        let mut req = req_msg.payload;
+        let payload = std::mem::take(&mut req.payload);
         let path = utils::get_path(&req.service, &req.method);
 
         let (tx, rx): (ResultSender, ResultReceiver) = channel(100);
@@ -560,21 +1019,30 @@ impl HandlerContext {
             true,
             Kind::Server,
             self.streams.clone(),
-        );
+        )
+        .with_max_send(self.limits.size.max_send);
 
         let ctx = TtrpcContext {
             mh: req_msg.header,
             metadata: context::from_pb(&req.metadata),
             timeout_nano: req.timeout_nano,
+            cancel,
+            deadline: context::deadline(req.timeout_nano),
+            peer: self.peer.clone(),
         };
 
-        let task = spawn(async move { stream.handler(ctx, si).await });
+        let interceptors = self.interceptors.clone();
+        let task = spawn(async move {
+            StreamNext::new(&interceptors, stream.as_ref())
+                .run(ctx, &req, si)
+                .await
+        });
 
         if !no_data {
             // Fake the first data message.
             let msg = GenMessage {
-                header: MessageHeader::new_data(stream_id, req.payload.len() as u32),
-                payload: req.payload,
+                header: MessageHeader::new_data(stream_id, payload.len() as u32),
+                payload,
             };
             stream_tx.send(Ok(msg)).await.map_err(|e| {
                 error!("send stream data {} got error {:?}", path, &e);
@@ -583,7 +1051,55 @@ impl HandlerContext {
         }
         task.await
             .unwrap_or_else(|e| Err(Error::Others(format!("stream {path} task got error {e:?}"))))
-            .map_err(|e| get_status(Code::UNKNOWN, e))
+            .map_err(Status::from)
+    }
+
+    /// Registers the cancellation of a request, which is fired when the returned
+    /// guard is dropped, the deadline of the request expires, the client cancels
+    /// the request or the connection exits.
+    fn register_cancel(
+        &self,
+        req_msg: &Message<Request>,
+        unary: bool,
+    ) -> (CancelGuard, shutdown::Waiter) {
+        let stream_id = req_msg.header.stream_id;
+        let (notifier, waiter) = shutdown::new();
+        self.cancels
+            .lock()
+            .unwrap()
+            .insert(stream_id, Cancel { notifier, unary });
+
+        let timer = (req_msg.payload.timeout_nano > 0).then(|| {
+            let cancels = self.cancels.clone();
+            let timeout = Duration::from_nanos(req_msg.payload.timeout_nano as u64);
+            spawn(async move {
+                sleep(timeout).await;
+                cancels.lock().unwrap().remove(&stream_id);
+            })
+        });
+
+        let guard = CancelGuard {
+            cancels: self.cancels.clone(),
+            stream_id,
+            timer,
+        };
+        (guard, waiter)
+    }
+
+    /// Cancels an unary request if the message is the client closing its stream,
+    /// returns `true` if there is such a request.
+    fn cancel_request(&self, header: &MessageHeader) -> bool {
+        if (header.flags & FLAG_REMOTE_CLOSED) != FLAG_REMOTE_CLOSED {
+            return false;
+        }
+        let mut cancels = self.cancels.lock().unwrap();
+        if !matches!(cancels.get(&header.stream_id), Some(cancel) if cancel.unary) {
+            return false;
+        }
+        if let Some(cancel) = cancels.remove(&header.stream_id) {
+            cancel.notifier.shutdown();
+        }
+        true
     }
 
     async fn respond(tx: MessageSender, stream_id: u32, resp: Response) -> Result<()> {
@@ -611,10 +1127,35 @@ impl HandlerContext {
     }
 }
 
+type Cancels = Arc<Mutex<HashMap<u32, Cancel>>>;
+
+/// The cancellation of a pending request, dropping it notifies the handler.
+struct Cancel {
+    notifier: shutdown::Notifier,
+    unary: bool,
+}
+
+/// Fires the cancellation of a request when its handling is finished.
+struct CancelGuard {
+    cancels: Cancels,
+    stream_id: u32,
+    timer: Option<task::JoinHandle<()>>,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.abort();
+        }
+        self.cancels.lock().unwrap().remove(&self.stream_id);
+    }
+}
+
 #[cfg(target_os = "linux")]
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::time::timeout;
 
     pub const SOCK_ADDR: &str = r"unix://@/tmp/ttrpc-server-unit-test";
 
@@ -642,4 +1183,425 @@ mod tests {
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         assert!(!is_socket_in_use(addr));
     }
+
+    struct WaitCancel {
+        cancelled: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    }
+
+    #[async_trait]
+    impl MethodHandler for WaitCancel {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            ctx.cancel.wait_shutdown().await;
+            if let Some(tx) = self.cancelled.lock().unwrap().take() {
+                tx.send(()).unwrap();
+            }
+            Ok(Response::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_cancel() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-cancel";
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert(
+            "Wait".to_string(),
+            Box::new(WaitCancel {
+                cancelled: Mutex::new(Some(tx)),
+            }),
+        );
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Wait".to_string(),
+            ..Default::default()
+        };
+        // Give up the request without a deadline, the client cancels it explicitly.
+        let res = timeout(Duration::from_millis(100), client.request(req)).await;
+        assert!(res.is_err());
+
+        timeout(Duration::from_secs(5), rx).await.unwrap().unwrap();
+    }
+
+    struct Sleep;
+
+    #[async_trait]
+    impl MethodHandler for Sleep {
+        async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            sleep(Duration::from_millis(200)).await;
+            Ok(Response::new())
+        }
+    }
+
+    async fn limited_server(addr: &str, limits: Limits) -> Server {
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Sleep".to_string(), Box::new(Sleep));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]))
+            .with_limits(limits);
+        server.start().await.unwrap();
+        server
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-limits";
+        let limits = Limits {
+            max_connections: Some(1),
+            max_requests_per_connection: Some(1),
+            ..Default::default()
+        };
+        let _server = limited_server(addr, limits).await;
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let (first, second) =
+            tokio::join!(client.request(req.clone()), client.request(req.clone()));
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::RpcStatus(s)) if s.code() == Code::RESOURCE_EXHAUSTED));
+
+        // The connection over the limit is closed.
+        let other = crate::r#async::Client::connect(addr).await.unwrap();
+        let res = timeout(Duration::from_secs(5), other.request(req.clone())).await;
+        assert!(res.unwrap().is_err());
+
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-backpressure";
+        let limits = Limits {
+            max_requests_per_connection: Some(1),
+            backpressure: true,
+            ..Default::default()
+        };
+        let _server = limited_server(addr, limits).await;
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let (first, second) =
+            tokio::join!(client.request(req.clone()), client.request(req.clone()));
+        assert!(first.is_ok() && second.is_ok());
+    }
+
+    struct Echo;
+
+    #[async_trait]
+    impl MethodHandler for Echo {
+        async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+            Ok(Response {
+                payload: req.payload,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_size_limits() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-message-size";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Echo".to_string(), Box::new(Echo));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let large = MessageSizeLimits {
+            max_send: 8 << 20,
+            max_recv: 8 << 20,
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]))
+            .with_message_size_limits(large);
+        server.start().await.unwrap();
+
+        let req = |len| Request {
+            service: "test".to_string(),
+            method: "Echo".to_string(),
+            payload: vec![0; len],
+            ..Default::default()
+        };
+
+        // The default limit of the client is below the size of the request.
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let res = client.request(req(5 << 20)).await;
+        assert!(matches!(res, Err(Error::Others(_))));
+
+        let client = client.with_message_size_limits(large);
+        let res = client.request(req(5 << 20)).await.unwrap();
+        assert_eq!(res.payload.len(), 5 << 20);
+
+        // The response over the receive limit is discarded.
+        let client = crate::r#async::Client::connect(addr)
+            .await
+            .unwrap()
+            .with_message_size_limits(MessageSizeLimits {
+                max_recv: 1024,
+                ..Default::default()
+            });
+        let res = client.request(req(2048)).await;
+        assert!(matches!(res, Err(Error::RpcStatus(s)) if s.code() == Code::INVALID_ARGUMENT));
+        client.request(req(512)).await.unwrap();
+    }
+
+    struct Peer;
+
+    #[async_trait]
+    impl MethodHandler for Peer {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            Ok(Response {
+                payload: format!("{:?}", ctx.peer).into_bytes(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-peer";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Peer".to_string(), Box::new(Peer));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Peer".to_string(),
+            ..Default::default()
+        };
+        let res = client.request(req).await.unwrap();
+        let peer = PeerInfo::Unix {
+            uid: nix::unistd::getuid().as_raw(),
+            gid: nix::unistd::getgid().as_raw(),
+            pid: Some(std::process::id() as i32),
+        };
+        assert_eq!(res.payload, format!("{:?}", peer).into_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-drain";
+        let mut server = limited_server(addr, Limits::default()).await;
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+
+        // The request in flight completes, the one sent while draining is
+        // rejected.
+        let in_flight = spawn({
+            let (client, req) = (client.clone(), req.clone());
+            async move { client.request(req).await }
+        });
+        sleep(Duration::from_millis(50)).await;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (report, rejected) = tokio::join!(server.drain(deadline), async {
+            sleep(Duration::from_millis(50)).await;
+            client.request(req).await
+        });
+        assert!(in_flight.await.unwrap().is_ok());
+        match rejected {
+            Err(Error::RpcStatus(status)) => assert_eq!(status.code(), Code::UNAVAILABLE),
+            res => panic!("unexpected response {:?}", res),
+        }
+        let report = report.unwrap();
+        assert_eq!(report.rejected, 1);
+        assert!(report.aborted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_deadline() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-drain-deadline";
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert(
+            "Wait".to_string(),
+            Box::new(WaitCancel {
+                cancelled: Mutex::new(Some(tx)),
+            }),
+        );
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Wait".to_string(),
+            ..Default::default()
+        };
+        let in_flight = spawn(async move { client.request(req).await });
+        sleep(Duration::from_millis(50)).await;
+
+        // The handler is cancelled once the deadline expires.
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let report = server.drain(deadline).await.unwrap();
+        timeout(Duration::from_secs(5), rx).await.unwrap().unwrap();
+        assert_eq!(report.aborted.len(), 1);
+        assert_eq!(report.aborted[0].method, "Wait");
+        assert!(!report.aborted[0].streaming);
+        in_flight.abort();
+    }
+
+    #[tokio::test]
+    async fn test_multiple_listeners() {
+        let unix = r"unix://@/tmp/ttrpc-server-unit-test-listeners";
+        let memory = "memory://server-unit-test-listeners";
+        let mut server = Server::new().bind(unix).unwrap();
+        let id = server.serve_listener(Listener::bind(memory).unwrap());
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Sleep".to_string(), Box::new(Sleep));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = server.register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+        let request = |addr: &'static str| {
+            let req = req.clone();
+            async move {
+                let client = crate::r#async::Client::connect(addr).await?;
+                client.request(req).await
+            }
+        };
+        assert!(request(unix).await.is_ok());
+        assert!(request(memory).await.is_ok());
+
+        // Listeners are added and removed while running.
+        let other = "memory://server-unit-test-listeners-other";
+        server.serve_listener(Listener::bind(other).unwrap());
+        assert!(request(other).await.is_ok());
+        assert!(server.remove_listener(id).await.is_some());
+        assert!(request(memory).await.is_err());
+
+        // All the listeners left are served again after a restart.
+        server.stop_listen().await;
+        server.start().await.unwrap();
+        assert!(request(unix).await.is_ok());
+        assert!(request(other).await.is_ok());
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remove_service() {
+        let addr = "memory://server-unit-test-services";
+        let mut server = Server::new().bind(addr).unwrap();
+        server.start().await.unwrap();
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+        let code = |res: Result<Response>| match res {
+            Err(Error::RpcStatus(status)) => status.code(),
+            res => panic!("unexpected response {:?}", res),
+        };
+        assert_eq!(code(client.request(req.clone()).await), Code::UNIMPLEMENTED);
+
+        let service = || {
+            let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> =
+                HashMap::new();
+            methods.insert("Sleep".to_string(), Box::new(Sleep));
+            Service {
+                methods,
+                streams: HashMap::new(),
+            }
+        };
+        server.add_service("test", service());
+        assert!(client.request(req.clone()).await.is_ok());
+
+        // The call in flight completes after the removal.
+        let in_flight = spawn({
+            let (client, req) = (client.clone(), req.clone());
+            async move { client.request(req).await }
+        });
+        sleep(Duration::from_millis(50)).await;
+        assert!(server.remove_service("test"));
+        assert!(!server.remove_service("test"));
+        assert!(in_flight.await.unwrap().is_ok());
+        assert_eq!(code(client.request(req).await), Code::UNIMPLEMENTED);
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let registry = crate::metrics::test_registry();
+        let addr = "memory://server-unit-test-metrics";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Sleep".to_string(), Box::new(Sleep));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("async-metrics".to_string(), service)]));
+        server.start().await.unwrap();
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let mut req = Request {
+            service: "async-metrics".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+        assert!(client.request(req.clone()).await.is_ok());
+        req.timeout_nano = Duration::from_millis(50).as_nanos() as i64;
+        assert!(client.request(req.clone()).await.is_err());
+        req.method = "Unknown".to_string();
+        assert!(client.request(req).await.is_err());
+        server.shutdown().await.unwrap();
+
+        let text = registry.render();
+        for line in [
+            r#"ttrpc_requests_total{side="server",method="/async-metrics/Sleep",code="OK"} 1"#,
+            r#"ttrpc_requests_total{side="server",method="/async-metrics/Sleep",code="DEADLINE_EXCEEDED"} 1"#,
+            r#"ttrpc_requests_total{side="client",method="/async-metrics/Sleep",code="OK"} 1"#,
+            r#"ttrpc_requests_total{side="client",method="/async-metrics/Unknown",code="UNIMPLEMENTED"} 1"#,
+            r#"ttrpc_requests_in_flight{side="server",method="/async-metrics/Sleep"} 0"#,
+            r#"ttrpc_request_duration_seconds_count{side="server",method="/async-metrics/Sleep"} 2"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+        }
+        assert!(!text.contains(r#"side="server",method="/async-metrics/Unknown""#));
+        assert!(text.contains(r#"ttrpc_sent_bytes_total{side="server"}"#));
+        assert!(text.contains(r#"ttrpc_received_bytes_total{side="client"}"#));
+    }
 }
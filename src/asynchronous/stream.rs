@@ -13,9 +13,10 @@ use tokio::sync::mpsc;
 
 use super::Client;
 use crate::error::{Error, Result};
+use crate::metrics::CallRecord;
 use crate::proto::{
-    Code, Codec, GenMessage, MessageHeader, Response, FLAG_NO_DATA, FLAG_REMOTE_CLOSED,
-    MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
+    check_oversize, Code, Codec, GenMessage, MessageHeader, Response, FLAG_NO_DATA, FLAG_REMOTE_CLOSED,
+    MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
 };
 
 pub type MessageSender = mpsc::Sender<SendingMessage>;
@@ -390,6 +391,7 @@ impl StreamInner {
                 sendable,
                 local_closed: Arc::new(AtomicBool::new(false)),
                 kind,
+                max_send: MESSAGE_LENGTH_MAX,
             },
             receiver: StreamReceiver {
                 rx,
@@ -398,10 +400,24 @@ impl StreamInner {
                 remote_closed: false,
                 kind,
                 streams,
+                call: None,
             },
         }
     }
 
+    /// Sets the maximum size of the messages sent on the stream.
+    pub(crate) fn with_max_send(mut self, max_send: usize) -> Self {
+        self.sender.max_send = max_send;
+        self
+    }
+
+    /// Sets the record of the call, finished once the stream is closed by
+    /// the server.
+    pub(crate) fn with_call(mut self, call: Option<CallRecord>) -> Self {
+        self.receiver.call = call;
+        self
+    }
+
     fn split(self) -> (StreamSender, StreamReceiver) {
         (self.sender, self.receiver)
     }
@@ -426,6 +442,7 @@ pub struct StreamSender {
     sendable: bool,
     local_closed: Arc<AtomicBool>,
     kind: Kind,
+    max_send: usize,
 }
 
 #[derive(Debug)]
@@ -436,6 +453,7 @@ pub struct StreamReceiver {
     remote_closed: bool,
     kind: Kind,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    call: Option<CallRecord>,
 }
 
 impl Drop for StreamReceiver {
@@ -451,14 +469,13 @@ impl StreamSender {
             debug_assert_eq!(self.kind, Kind::Client);
             return Err(Error::LocalClosed);
         }
+        check_oversize(buf.len(), self.max_send, true)?;
         let header = MessageHeader::new_data(self.stream_id, buf.len() as u32);
         let msg = GenMessage {
             header,
             payload: buf,
         };
 
-        msg.check()?;
-
         _send(&self.tx, msg).await?;
 
         Ok(())
@@ -495,6 +512,7 @@ impl StreamReceiver {
                 self.remote_closed = true;
                 let resp = Response::decode(&msg.payload)
                     .map_err(err_to_others_err!(e, "Decode message failed."))?;
+                self.finish_call(resp.status.as_ref().map_or(Code::OK, |s| s.code()));
                 if let Some(status) = resp.status.as_ref() {
                     if status.code() != Code::OK {
                         return Err(Error::RpcStatus((*status).clone()));
@@ -511,6 +529,7 @@ impl StreamReceiver {
                 }
                 if (msg.header.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED {
                     self.remote_closed = true;
+                    self.finish_call(Code::OK);
                     if (msg.header.flags & FLAG_NO_DATA) == FLAG_NO_DATA {
                         return Err(Error::Eof);
                     }
@@ -523,4 +542,10 @@ impl StreamReceiver {
         };
         Ok(payload)
     }
+
+    fn finish_call(&mut self, code: Code) {
+        if let Some(call) = self.call.take() {
+            call.finish(code);
+        }
+    }
 }
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Hybrid vsock, for the `hvsock://path:port` addresses.
+//!
+//! Firecracker and Cloud Hypervisor expose the vsock ports of the guest on
+//! the host through the unix socket `path`: the host connects to it and
+//! sends `CONNECT <port>\n`, the VMM answers `OK <host port>\n` and forwards
+//! the connection to `port` in the guest.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::UnixStream;
+
+use super::{Listener, Socket, Transport};
+use crate::common::{parse_hybrid_vsock, HybridVsockHandshake, HYBRID_VSOCK_HANDSHAKE_TIMEOUT};
+
+pub(super) struct HybridVsockTransport;
+
+#[async_trait]
+impl Transport for HybridVsockTransport {
+    fn bind(&self, _addr: &str) -> IoResult<Listener> {
+        Err(io_other!("hybrid vsock sockets are listened on by the VMM"))
+    }
+
+    async fn connect(&self, addr: &str) -> IoResult<Socket> {
+        Socket::connect_hybrid_vsock(addr).await
+    }
+}
+
+impl Socket {
+    /// Connects to the port of the guest behind the hybrid vsock socket of
+    /// `addr`, `path:port` with or without the `hvsock://` prefix.
+    pub async fn connect_hybrid_vsock(addr: impl AsRef<str>) -> IoResult<Self> {
+        connect(addr.as_ref(), HYBRID_VSOCK_HANDSHAKE_TIMEOUT).await
+    }
+}
+
+async fn connect(addr: &str, timeout: Duration) -> IoResult<Socket> {
+    let addr = addr.strip_prefix("hvsock://").unwrap_or(addr);
+    let (path, port) = parse_hybrid_vsock(addr).map_err(|e| io_other!("{e}"))?;
+
+    let mut socket = UnixStream::connect(unix_path(path)?).await?;
+    tokio::time::timeout(timeout, handshake(&mut socket, port))
+        .await
+        .map_err(|_| {
+            IoError::new(
+                ErrorKind::TimedOut,
+                format!("hybrid vsock handshake of port {port} timed out"),
+            )
+        })??;
+    Ok(Socket::from(socket))
+}
+
+/// The path of the unix socket `path` for tokio, which takes the abstract
+/// names with a leading NUL byte.
+fn unix_path(path: &str) -> IoResult<String> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(name) = path.strip_prefix('@') {
+        return Ok(format!("\0{name}"));
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    if path.starts_with('@') {
+        return Err(io_other!(
+            "Abstract unix domain socket is not support on this platform",
+        ));
+    }
+
+    Ok(path.to_string())
+}
+
+async fn handshake(socket: &mut UnixStream, port: u32) -> IoResult<()> {
+    let mut handshake = HybridVsockHandshake::new(port);
+    socket.write_all(handshake.request().as_bytes()).await?;
+
+    loop {
+        let byte = match socket.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                return Err(io_other!("{}", handshake.invalid()))
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(result) = handshake.push(byte) {
+            return result.map_err(|e| io_other!("{e}"));
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use futures::StreamExt as _;
+    use tokio::io::{AsyncBufReadExt as _, BufReader};
+
+    #[tokio::test]
+    async fn test_connect_hybrid_vsock() {
+        // Stands in for the VMM, which accepts the port 1024 only.
+        let mut listener = Listener::bind("unix://@/tmp/ttrpc-hvsock-test").unwrap();
+        let vmm = tokio::spawn(async move {
+            for _ in 0..2 {
+                let mut socket = BufReader::new(listener.next().await.unwrap().unwrap());
+                let mut line = String::new();
+                socket.read_line(&mut line).await.unwrap();
+                if line == "CONNECT 1024\n" {
+                    socket.write_all(b"OK 1073741824\nping").await.unwrap();
+                }
+            }
+        });
+
+        let mut socket = Socket::connect("hvsock://@/tmp/ttrpc-hvsock-test:1024")
+            .await
+            .unwrap();
+        let mut buf = [0; 4];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        assert!(Socket::connect("hvsock://@/tmp/ttrpc-hvsock-test:1025")
+            .await
+            .is_err());
+        assert!(Listener::bind("hvsock://@/tmp/ttrpc-hvsock-test:1024").is_err());
+        vmm.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_hybrid_vsock_timeout() {
+        // Stands in for a VMM which never answers.
+        let mut listener = Listener::bind("unix://@/tmp/ttrpc-hvsock-timeout-test").unwrap();
+        let vmm = tokio::spawn(async move { listener.next().await.unwrap().unwrap() });
+
+        let Err(e) = connect(
+            "hvsock://@/tmp/ttrpc-hvsock-timeout-test:1024",
+            Duration::from_millis(10),
+        )
+        .await
+        else {
+            panic!("connected to a VMM which never answers");
+        };
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        drop(vmm.await.unwrap());
+    }
+}
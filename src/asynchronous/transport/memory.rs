@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! In-process connections, for the `memory://name` addresses.
+//!
+//! The sockets are in-memory pipes, nothing is bound: the listener of a name
+//! is registered in the process until it is dropped.
+
+use std::collections::HashMap;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::sync::{Mutex, OnceLock};
+
+use async_stream::stream;
+use async_trait::async_trait;
+use tokio::io::duplex;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use super::{Listener, Socket, Transport};
+use crate::peer::PeerInfo;
+
+/// The capacity of each direction of the in-memory pipes.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+fn listeners() -> &'static Mutex<HashMap<String, UnboundedSender<Socket>>> {
+    static LISTENERS: OnceLock<Mutex<HashMap<String, UnboundedSender<Socket>>>> =
+        OnceLock::new();
+    LISTENERS.get_or_init(Default::default)
+}
+
+pub(super) struct MemoryTransport;
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    fn bind(&self, addr: &str) -> IoResult<Listener> {
+        Listener::bind_memory(addr)
+    }
+
+    async fn connect(&self, addr: &str) -> IoResult<Socket> {
+        Socket::connect_memory(addr)
+    }
+}
+
+impl Listener {
+    /// Listens on the in-process name `name`, until the listener is dropped.
+    pub fn bind_memory(name: impl AsRef<str>) -> IoResult<Self> {
+        let name = name.as_ref();
+        let mut listeners = listeners().lock().unwrap();
+        if listeners.get(name).is_some_and(|tx| !tx.is_closed()) {
+            return Err(IoError::new(
+                ErrorKind::AddrInUse,
+                format!("memory listener {name:?} is already bound"),
+            ));
+        }
+        let (tx, mut rx) = unbounded_channel();
+        listeners.insert(name.to_string(), tx);
+        Ok(Self::from_sockets(stream! {
+            while let Some(socket) = rx.recv().await {
+                yield Ok(socket);
+            }
+        }))
+    }
+}
+
+impl Socket {
+    /// Connects to the listener of the in-process name `name`.
+    pub fn connect_memory(name: impl AsRef<str>) -> IoResult<Self> {
+        let name = name.as_ref();
+        let mut listeners = listeners().lock().unwrap();
+        let (client, server) = duplex(BUFFER_SIZE);
+        let server = Socket::new(server).with_peer(PeerInfo::InProcess);
+        match listeners.get(name).map(|tx| tx.send(server)) {
+            Some(Ok(())) => Ok(Socket::new(client).with_peer(PeerInfo::InProcess)),
+            res => {
+                if res.is_some() {
+                    listeners.remove(name);
+                }
+                Err(IoError::new(
+                    ErrorKind::ConnectionRefused,
+                    format!("memory listener {name:?} is not bound"),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Request, Response};
+    use crate::r#async::{Client, MethodHandler, Server, Service, TtrpcContext};
+
+    struct Peer;
+
+    #[async_trait]
+    impl MethodHandler for Peer {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> crate::Result<Response> {
+            Ok(Response {
+                payload: vec![(ctx.peer == PeerInfo::InProcess) as u8],
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory() {
+        assert!(Client::connect("memory://async-test").await.is_err());
+
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Peer".to_string(), Box::new(Peer));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .bind("memory://async-test")
+            .unwrap()
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+        assert!(Listener::bind("memory://async-test").is_err());
+
+        let client = Client::connect("memory://async-test").await.unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Peer".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(client.request(req).await.unwrap().payload, vec![1]);
+
+        server.shutdown().await.unwrap();
+        assert!(Client::connect("memory://async-test").await.is_err());
+    }
+}
@@ -1,14 +1,19 @@
-use std::io::{Error as IoError, Result as IoResult};
+use std::io::Result as IoResult;
 use std::pin::Pin;
 
 use futures::stream::{BoxStream, Stream, StreamExt as _};
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use crate::peer::PeerInfo;
+
 trait AsyncReadWrite: AsyncRead + AsyncWrite {}
 impl<T: AsyncRead + AsyncWrite> AsyncReadWrite for T {}
 
 pub struct Listener(BoxStream<'static, IoResult<Socket>>);
-pub struct Socket(Pin<Box<dyn AsyncReadWrite + Send + Sync + 'static>>);
+pub struct Socket(
+    Pin<Box<dyn AsyncReadWrite + Send + Sync + 'static>>,
+    PeerInfo,
+);
 
 macro_rules! io_other {
     ($fmt_str:literal, $($args:expr),*) => {
@@ -19,81 +24,83 @@ macro_rules! io_other {
     };
 }
 
+mod memory;
+mod registry;
+
 #[cfg(unix)]
 mod unix;
 
 #[cfg(unix)]
 mod tcp;
 
+#[cfg(unix)]
+mod hvsock;
+
 #[cfg(any(target_os = "linux", target_os = "android"))]
 mod vsock;
 
 #[cfg(windows)]
 mod windows;
 
+#[cfg(feature = "tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+pub mod tls;
+
+pub use registry::{register_transport, Transport};
+
 impl Listener {
     pub fn new<S: AsyncRead + AsyncWrite + Send + Sync + 'static>(
         listener: impl Stream<Item = IoResult<S>> + Send + 'static,
     ) -> Self {
-        Self(listener.map(|s| s.map(Socket::new)).boxed())
+        Self::from_sockets(listener.map(|s| s.map(Socket::new)))
     }
 
+    /// Creates a listener from a stream of sockets, e.g. with their peer set.
+    pub fn from_sockets(listener: impl Stream<Item = IoResult<Socket>> + Send + 'static) -> Self {
+        Self(listener.boxed())
+    }
+
+    /// Listens on `addr`, with the transport registered for its scheme.
     pub fn bind(addr: impl AsRef<str>) -> std::io::Result<Self> {
         let addr = addr.as_ref();
 
-        #[cfg(unix)]
-        if let Some(addr) = addr.strip_prefix("unix://") {
-            return Self::bind_unix(addr);
-        }
-
-        #[cfg(unix)]
-        if let Some(addr) = addr.strip_prefix("tcp://") {
-            return Self::bind_tcp(addr);
-        }
-
-        #[cfg(any(target_os = "linux", target_os = "android"))]
-        if let Some(addr) = addr.strip_prefix("vsock://") {
-            return Self::bind_vsock(addr);
-        }
-
         #[cfg(windows)]
         if addr.starts_with(r"\\.\pipe\") {
             return Self::bind_named_pipe(addr);
         }
 
-        Err(io_other!("Scheme of {addr:?} is not supported"))
+        let (transport, addr) = registry::find_transport(addr)?;
+        transport.bind(addr)
     }
 }
 
 impl Socket {
     pub fn new(socket: impl AsyncRead + AsyncWrite + Send + Sync + 'static) -> Self {
-        Self(Box::pin(socket))
+        Self(Box::pin(socket), PeerInfo::Unknown)
     }
 
-    pub async fn connect(addr: impl AsRef<str>) -> IoResult<Self> {
-        let addr = addr.as_ref();
-
-        #[cfg(unix)]
-        if let Some(addr) = addr.strip_prefix("unix://") {
-            return Self::connect_unix(addr).await;
-        }
+    /// Sets the peer of the socket.
+    pub fn with_peer(mut self, peer: PeerInfo) -> Self {
+        self.1 = peer;
+        self
+    }
 
-        #[cfg(unix)]
-        if let Some(addr) = addr.strip_prefix("tcp://") {
-            return Self::connect_tcp(addr).await;
-        }
+    /// Returns the peer of the socket, captured when it was accepted.
+    pub fn peer(&self) -> &PeerInfo {
+        &self.1
+    }
 
-        #[cfg(any(target_os = "linux", target_os = "android"))]
-        if let Some(addr) = addr.strip_prefix("vsock://") {
-            return Self::connect_vsock(addr).await;
-        }
+    /// Connects to `addr`, with the transport registered for its scheme.
+    pub async fn connect(addr: impl AsRef<str>) -> IoResult<Self> {
+        let addr = addr.as_ref();
 
         #[cfg(windows)]
         if addr.starts_with(r"\\.\pipe\") {
             return Self::connect_named_pipe(addr).await;
         }
 
-        Err(io_other!("Scheme of {addr:?} is not supported"))
+        let (transport, addr) = registry::find_transport(addr)?;
+        transport.connect(addr).await
     }
 }
 
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The transports of the `scheme://address` addresses of the async server
+//! and client.
+//!
+//! The registry is not used by the sync server and client, which accept the
+//! built-in schemes only.
+
+use std::collections::HashMap;
+use std::io::{Error as IoError, Result as IoResult};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use async_trait::async_trait;
+
+use super::{Listener, Socket};
+
+/// Creates the listeners and the sockets of the addresses of a scheme.
+///
+/// The built-in transports are `unix`, `tcp`, `vsock`, `hvsock` and `memory`, a transport
+/// registered with [`register_transport`] replaces the built-in one of the
+/// same scheme.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Listens on `addr`, the address without the `scheme://` prefix.
+    fn bind(&self, addr: &str) -> IoResult<Listener>;
+
+    /// Connects to `addr`, the address without the `scheme://` prefix.
+    async fn connect(&self, addr: &str) -> IoResult<Socket>;
+}
+
+type Transports = RwLock<HashMap<String, Arc<dyn Transport>>>;
+
+fn transports() -> &'static Transports {
+    static TRANSPORTS: OnceLock<Transports> = OnceLock::new();
+    TRANSPORTS.get_or_init(|| {
+        let mut transports: HashMap<String, Arc<dyn Transport>> = HashMap::new();
+        transports.insert("memory".to_string(), Arc::new(super::memory::MemoryTransport));
+        #[cfg(unix)]
+        transports.insert("unix".to_string(), Arc::new(super::unix::UnixTransport));
+        #[cfg(unix)]
+        transports.insert("tcp".to_string(), Arc::new(super::tcp::TcpTransport));
+        #[cfg(unix)]
+        transports.insert("hvsock".to_string(), Arc::new(super::hvsock::HybridVsockTransport));
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        transports.insert("vsock".to_string(), Arc::new(super::vsock::VsockTransport));
+        RwLock::new(transports)
+    })
+}
+
+/// Registers the transport of the `scheme://` addresses, which are then
+/// accepted by the async [`Server::bind`](crate::asynchronous::Server::bind)
+/// and [`Client::connect`](crate::asynchronous::Client::connect).
+pub fn register_transport(scheme: impl Into<String>, transport: impl Transport + 'static) {
+    transports()
+        .write()
+        .unwrap()
+        .insert(scheme.into(), Arc::new(transport));
+}
+
+/// Returns the transport of the scheme of `addr` and the address without
+/// the scheme.
+pub(super) fn find_transport(addr: &str) -> IoResult<(Arc<dyn Transport>, &str)> {
+    let unsupported = || io_other!("Scheme of {addr:?} is not supported");
+    let (scheme, rest) = addr.split_once("://").ok_or_else(unsupported)?;
+    let transport = transports().read().unwrap().get(scheme).cloned();
+    Ok((transport.ok_or_else(unsupported)?, rest))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use futures::StreamExt as _;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    /// Prefixes the abstract unix socket names, as a transport tunneling
+    /// the connections in a unix socket would.
+    struct Prefixed;
+
+    #[async_trait]
+    impl Transport for Prefixed {
+        fn bind(&self, addr: &str) -> IoResult<Listener> {
+            Listener::bind_unix(format!("@ttrpc-prefixed-{addr}"))
+        }
+
+        async fn connect(&self, addr: &str) -> IoResult<Socket> {
+            Socket::connect_unix(format!("@ttrpc-prefixed-{addr}")).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_transport() {
+        assert!(Listener::bind("prefixed://registry").is_err());
+        register_transport("prefixed", Prefixed);
+
+        let mut listener = Listener::bind("prefixed://registry").unwrap();
+        let mut client = Socket::connect("prefixed://registry").await.unwrap();
+        let mut server = listener.next().await.unwrap().unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+}
@@ -6,9 +6,24 @@ use std::net::{
 };
 
 use async_stream::stream;
+use async_trait::async_trait;
 use tokio::net::{TcpListener, TcpStream};
 
-use super::{Listener, Socket};
+use super::{Listener, Socket, Transport};
+use crate::peer::PeerInfo;
+
+pub(super) struct TcpTransport;
+
+#[async_trait]
+impl Transport for TcpTransport {
+    fn bind(&self, addr: &str) -> IoResult<Listener> {
+        Listener::bind_tcp(addr)
+    }
+
+    async fn connect(&self, addr: &str) -> IoResult<Socket> {
+        Socket::connect_tcp(addr).await
+    }
+}
 
 impl Listener {
     pub fn bind_tcp(addr: impl AsRef<str>) -> IoResult<Self> {
@@ -42,9 +57,12 @@ impl Socket {
 
 impl From<TcpListener> for Listener {
     fn from(listener: TcpListener) -> Self {
-        Self::new(stream! {
+        Self::from_sockets(stream! {
             loop {
-                yield listener.accept().await.map(|(socket, _)| socket);
+                yield listener
+                    .accept()
+                    .await
+                    .map(|(socket, addr)| Socket::from(socket).with_peer(PeerInfo::Tcp(addr)));
             }
         })
     }
@@ -0,0 +1,327 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! TLS over TCP, for the `tls://host:port` addresses.
+//!
+//! The certificates and keys are PEM encoded. A server given the CA of its
+//! clients requires them to authenticate, the certificates they present are
+//! passed to the handlers in [`PeerInfo::Tls`].
+
+use std::convert::TryFrom;
+use std::io::{Error as IoError, Result as IoResult};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_stream::stream;
+use async_trait::async_trait;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_rustls::rustls::client::ClientConfig;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::{ServerConfig, WebPkiClientVerifier};
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use super::{Listener, Socket, Transport};
+use crate::peer::PeerInfo;
+
+pub use tokio_rustls::rustls;
+
+/// The time given to a client to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Creates the configuration of a server presenting `cert_chain`, signed
+/// with `key`. The clients must present a certificate signed by `client_ca`
+/// if it is given.
+pub fn server_config(
+    cert_chain: &[u8],
+    key: &[u8],
+    client_ca: Option<&[u8]>,
+) -> IoResult<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?;
+    let builder = match client_ca {
+        Some(ca) => {
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots(ca)?), provider())
+                    .build()
+                    .map_err(tls_error)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(certs(cert_chain)?, private_key(key)?)
+        .map_err(tls_error)?;
+    Ok(Arc::new(config))
+}
+
+/// Creates the configuration of a client trusting the servers signed by
+/// `ca`, which presents `identity`, a certificate chain and its key, if the
+/// server requires it.
+pub fn client_config(ca: &[u8], identity: Option<(&[u8], &[u8])>) -> IoResult<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?
+        .with_root_certificates(roots(ca)?);
+    let config = match identity {
+        Some((cert_chain, key)) => builder
+            .with_client_auth_cert(certs(cert_chain)?, private_key(key)?)
+            .map_err(tls_error)?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// The transport of the `tls://` addresses, which can be registered with
+/// [`register_transport`](super::register_transport) to use them in
+/// `Server::bind` and `Client::connect`.
+pub struct TlsTransport {
+    server: Option<Arc<ServerConfig>>,
+    client: Option<Arc<ClientConfig>>,
+}
+
+impl TlsTransport {
+    /// Creates the transport binding with `server` and connecting with
+    /// `client`, either is optional.
+    pub fn new(server: Option<Arc<ServerConfig>>, client: Option<Arc<ClientConfig>>) -> Self {
+        TlsTransport { server, client }
+    }
+}
+
+#[async_trait]
+impl Transport for TlsTransport {
+    fn bind(&self, addr: &str) -> IoResult<Listener> {
+        let config = self.server.clone();
+        Listener::bind_tls(addr, config.ok_or_else(|| io_other!("no TLS server config"))?)
+    }
+
+    async fn connect(&self, addr: &str) -> IoResult<Socket> {
+        let config = self.client.clone();
+        Socket::connect_tls(addr, config.ok_or_else(|| io_other!("no TLS client config"))?).await
+    }
+}
+
+impl Listener {
+    /// Listens on the TCP address `addr`, with or without the `tls://`
+    /// prefix, and completes the TLS handshake of the accepted connections.
+    pub fn bind_tls(addr: impl AsRef<str>, config: Arc<ServerConfig>) -> IoResult<Self> {
+        let addr = parse_tls_addr(addr.as_ref())?;
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::from((TcpListener::from_std(listener)?, config)))
+    }
+}
+
+impl Socket {
+    /// Connects to the TCP address `addr`, with or without the `tls://`
+    /// prefix. The certificate of the server must be valid for the host of
+    /// `addr`.
+    pub async fn connect_tls(addr: impl AsRef<str>, config: Arc<ClientConfig>) -> IoResult<Self> {
+        let addr = addr.as_ref();
+        let addr = addr.strip_prefix("tls://").unwrap_or(addr);
+        let (host, _) = addr
+            .rsplit_once(':')
+            .ok_or_else(|| io_other!("sockaddr {addr} is not right for tls"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| io_other!("invalid server name {host:?}: {e}"))?;
+
+        let socket = TcpStream::connect(addr).await?;
+        let socket = TlsConnector::from(config)
+            .connect(server_name, socket)
+            .await?;
+        Ok(Self::new(socket))
+    }
+}
+
+impl From<(TcpListener, Arc<ServerConfig>)> for Listener {
+    fn from((listener, config): (TcpListener, Arc<ServerConfig>)) -> Self {
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, mut rx) = mpsc::channel(16);
+        // The handshakes run concurrently, a slow client does not delay the
+        // others.
+        tokio::spawn(async move {
+            loop {
+                let (socket, addr) = tokio::select! {
+                    _ = tx.closed() => break,
+                    res = listener.accept() => match res {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            if tx.send(Err(e)).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    },
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+                        Ok(Ok(socket)) => {
+                            let peer = tls_peer(addr, socket.get_ref().1.peer_certificates());
+                            tx.send(Ok(Socket::new(socket).with_peer(peer))).await.ok();
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+        Self::from_sockets(stream! {
+            while let Some(socket) = rx.recv().await {
+                yield socket;
+            }
+        })
+    }
+}
+
+fn tls_peer(addr: SocketAddr, certificates: Option<&[CertificateDer]>) -> PeerInfo {
+    PeerInfo::Tls {
+        addr,
+        certificates: certificates
+            .unwrap_or_default()
+            .iter()
+            .map(|c| c.to_vec())
+            .collect(),
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn certs(pem: &[u8]) -> IoResult<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut &*pem).collect::<IoResult<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(io_other!("no certificate found"));
+    }
+    Ok(certs)
+}
+
+fn private_key(pem: &[u8]) -> IoResult<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut &*pem)?.ok_or_else(|| io_other!("no private key found"))
+}
+
+fn roots(pem: &[u8]) -> IoResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs(pem)? {
+        roots.add(cert).map_err(tls_error)?;
+    }
+    Ok(roots)
+}
+
+fn tls_error(e: impl std::fmt::Display) -> IoError {
+    io_other!("{}", e)
+}
+
+fn parse_tls_addr(addr: &str) -> IoResult<SocketAddr> {
+    let addr = addr.strip_prefix("tls://").unwrap_or(addr);
+    addr.parse::<SocketAddr>()
+        .map_err(|e| io_other!("Failed to parse TLS address '{}': {}", addr, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Request, Response};
+    use crate::r#async::transport::register_transport;
+    use crate::r#async::{Client, MethodHandler, Server, Service, TtrpcContext};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use std::collections::HashMap;
+
+    struct Peer;
+
+    #[async_trait::async_trait]
+    impl MethodHandler for Peer {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> crate::Result<Response> {
+            let PeerInfo::Tls { certificates, .. } = ctx.peer else {
+                return Err(crate::Error::Others("not a TLS peer".to_string()));
+            };
+            Ok(Response {
+                payload: certificates.concat(),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// A CA and a certificate for `name` signed by it, PEM encoded.
+    struct Pki {
+        ca: String,
+        cert: String,
+        key: String,
+        der: Vec<u8>,
+    }
+
+    fn pki(name: &str) -> Pki {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&ca_key).unwrap();
+
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_string()])
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        Pki {
+            ca: ca.pem(),
+            cert: cert.pem(),
+            key: key.serialize_pem(),
+            der: cert.der().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let server_pki = pki("localhost");
+        let client_pki = pki("client");
+
+        let config = server_config(
+            server_pki.cert.as_bytes(),
+            server_pki.key.as_bytes(),
+            Some(client_pki.ca.as_bytes()),
+        )
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("tls://localhost:{}", listener.local_addr().unwrap().port());
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Peer".to_string(), Box::new(Peer));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+        let mut server = Server::new()
+            .add_listener(Listener::from((listener, config)))
+            .register_service(HashMap::from([("test".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let req = Request {
+            service: "test".to_string(),
+            method: "Peer".to_string(),
+            ..Default::default()
+        };
+        let identity = (client_pki.cert.as_bytes(), client_pki.key.as_bytes());
+        let config = client_config(server_pki.ca.as_bytes(), Some(identity)).unwrap();
+        register_transport("tls", TlsTransport::new(None, Some(config)));
+        let client = Client::connect(&addr).await.unwrap();
+        let res = client.request(req.clone()).await.unwrap();
+        assert_eq!(res.payload, client_pki.der);
+
+        // The client without a certificate is rejected.
+        let config = client_config(server_pki.ca.as_bytes(), None).unwrap();
+        if let Ok(socket) = Socket::connect_tls(&addr, config).await {
+            let res = Client::new(socket).request(req.clone()).await;
+            assert!(res.is_err());
+        }
+
+        // The server is not trusted by a client of another CA.
+        let config = client_config(client_pki.ca.as_bytes(), None).unwrap();
+        assert!(Socket::connect_tls(&addr, config).await.is_err());
+    }
+}
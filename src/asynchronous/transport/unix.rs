@@ -1,14 +1,29 @@
 use std::convert::TryFrom;
 use std::io::{Error as IoError, Result as IoResult};
-use std::os::fd::{FromRawFd as _, RawFd};
+use std::os::fd::{AsRawFd as _, FromRawFd as _, RawFd};
 use std::os::unix::net::{
     SocketAddr, UnixListener as StdUnixListener, UnixStream as StdUnixStream,
 };
 
 use async_stream::stream;
+use async_trait::async_trait;
 use tokio::net::{UnixListener, UnixStream};
 
-use super::{Listener, Socket};
+use super::{Listener, Socket, Transport};
+use crate::peer::PeerInfo;
+
+pub(super) struct UnixTransport;
+
+#[async_trait]
+impl Transport for UnixTransport {
+    fn bind(&self, addr: &str) -> IoResult<Listener> {
+        Listener::bind_unix(addr)
+    }
+
+    async fn connect(&self, addr: &str) -> IoResult<Socket> {
+        Socket::connect_unix(addr).await
+    }
+}
 
 impl Listener {
     pub fn bind_unix(addr: impl AsRef<str>) -> IoResult<Self> {
@@ -42,9 +57,12 @@ impl Socket {
 
 impl From<UnixListener> for Listener {
     fn from(listener: UnixListener) -> Self {
-        Self::new(stream! {
+        Self::from_sockets(stream! {
             loop {
-                yield listener.accept().await.map(|(socket, _)| socket);
+                yield listener.accept().await.map(|(socket, _)| {
+                    let peer = PeerInfo::from_fd(socket.as_raw_fd());
+                    Socket::from(socket).with_peer(peer)
+                });
             }
         })
     }
@@ -72,7 +90,7 @@ impl TryFrom<StdUnixStream> for Socket {
     }
 }
 
-fn parse_unix_addr(addr: impl AsRef<str>) -> IoResult<SocketAddr> {
+pub(super) fn parse_unix_addr(addr: impl AsRef<str>) -> IoResult<SocketAddr> {
     let addr = addr.as_ref();
 
     #[cfg(any(target_os = "linux", target_os = "android"))]
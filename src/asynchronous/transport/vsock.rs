@@ -2,9 +2,24 @@ use std::io::{Error as IoError, Result as IoResult};
 use std::os::fd::{FromRawFd as _, RawFd};
 
 use async_stream::stream;
+use async_trait::async_trait;
 use tokio_vsock::{VsockAddr, VsockListener, VsockStream, VMADDR_CID_ANY};
 
-use super::{Listener, Socket};
+use super::{Listener, Socket, Transport};
+use crate::peer::PeerInfo;
+
+pub(super) struct VsockTransport;
+
+#[async_trait]
+impl Transport for VsockTransport {
+    fn bind(&self, addr: &str) -> IoResult<Listener> {
+        Listener::bind_vsock(addr)
+    }
+
+    async fn connect(&self, addr: &str) -> IoResult<Socket> {
+        Socket::connect_vsock(addr).await
+    }
+}
 
 impl Listener {
     pub fn bind_vsock(addr: impl AsRef<str>) -> IoResult<Self> {
@@ -29,9 +44,15 @@ impl Socket {
 
 impl From<VsockListener> for Listener {
     fn from(listener: VsockListener) -> Self {
-        Self::new(stream! {
+        Self::from_sockets(stream! {
             loop {
-                yield listener.accept().await.map(|(socket, _)| socket);
+                yield listener.accept().await.map(|(socket, addr)| {
+                    let peer = PeerInfo::Vsock {
+                        cid: addr.cid(),
+                        port: addr.port(),
+                    };
+                    Socket::from(socket).with_peer(peer)
+                });
             }
         })
     }
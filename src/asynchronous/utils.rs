@@ -32,17 +32,7 @@ macro_rules! async_request_handler {
                     .map_err(::ttrpc::err_to_others!(e, ""))?;
                 s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
             }
-            Err(x) => match x {
-                ::ttrpc::Error::RpcStatus(s) => {
-                    res.set_status(s);
-                }
-                _ => {
-                    res.set_status(::ttrpc::get_status(
-                        ::ttrpc::Code::UNKNOWN,
-                        format!("{:?}", x),
-                    ));
-                }
-            },
+            Err(x) => res.set_status(::ttrpc::IntoStatus::into_status(x)),
         }
 
         return Ok(res);
@@ -64,17 +54,7 @@ macro_rules! async_client_streamimg_handler {
                     .map_err(::ttrpc::err_to_others!(e, ""))?;
                 s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
             }
-            Err(x) => match x {
-                ::ttrpc::Error::RpcStatus(s) => {
-                    res.set_status(s);
-                }
-                _ => {
-                    res.set_status(::ttrpc::get_status(
-                        ::ttrpc::Code::UNKNOWN,
-                        format!("{:?}", x),
-                    ));
-                }
-            },
+            Err(x) => res.set_status(::ttrpc::IntoStatus::into_status(x)),
         }
         return Ok(Some(res));
     };
@@ -94,17 +74,7 @@ macro_rules! async_server_streamimg_handler {
             }
             Err(x) => {
                 let mut res = ::ttrpc::Response::new();
-                match x {
-                    ::ttrpc::Error::RpcStatus(s) => {
-                        res.set_status(s);
-                    }
-                    _ => {
-                        res.set_status(::ttrpc::get_status(
-                            ::ttrpc::Code::UNKNOWN,
-                            format!("{:?}", x),
-                        ));
-                    }
-                }
+                res.set_status(::ttrpc::IntoStatus::into_status(x));
                 return Ok(Some(res));
             }
         }
@@ -122,17 +92,7 @@ macro_rules! async_duplex_streamimg_handler {
             }
             Err(x) => {
                 let mut res = ::ttrpc::Response::new();
-                match x {
-                    ::ttrpc::Error::RpcStatus(s) => {
-                        res.set_status(s);
-                    }
-                    _ => {
-                        res.set_status(::ttrpc::get_status(
-                            ::ttrpc::Code::UNKNOWN,
-                            format!("{:?}", x),
-                        ));
-                    }
-                }
+                res.set_status(::ttrpc::IntoStatus::into_status(x));
                 return Ok(Some(res));
             }
         }
@@ -146,7 +106,7 @@ macro_rules! async_client_request {
         let mut creq = ttrpc::Request {
             service: $server.to_string(),
             method: $method.to_string(),
-            timeout_nano: $ctx.timeout_nano,
+            timeout_nano: $ctx.request_timeout_nano()?,
             metadata: ttrpc::context::to_pb($ctx.metadata),
             payload: Vec::with_capacity($req.compute_size() as usize),
             ..Default::default()
@@ -176,7 +136,7 @@ macro_rules! async_client_stream {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
         let md = ::ttrpc::context::to_pb($ctx.metadata);
         creq.set_metadata(md);
 
@@ -194,7 +154,7 @@ macro_rules! async_client_stream_send {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
         let md = ::ttrpc::context::to_pb($ctx.metadata);
         creq.set_metadata(md);
 
@@ -212,7 +172,7 @@ macro_rules! async_client_stream_receive {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
         let md = ::ttrpc::context::to_pb($ctx.metadata);
         creq.set_metadata(md);
         creq.payload.reserve($req.compute_size() as usize);
@@ -252,8 +212,32 @@ pub struct TtrpcContext {
     pub mh: MessageHeader,
     pub metadata: HashMap<String, Vec<String>>,
     pub timeout_nano: i64,
+    /// Notified when the request is cancelled, i.e. the client cancels it, its
+    /// deadline expires, the connection is closed or the server shuts down.
+    ///
+    /// Handlers can select on `cancel.wait_shutdown()` to stop early.
+    pub cancel: crate::r#async::shutdown::Waiter,
+    /// The deadline of the request, set from `timeout_nano` on receipt.
+    pub deadline: Option<std::time::Instant>,
+    /// The peer of the connection the request was received on.
+    pub peer: crate::peer::PeerInfo,
 }
 
-pub(crate) fn get_path(service: &str, method: &str) -> String {
-    format!("/{service}/{method}")
+impl TtrpcContext {
+    /// Returns the time left before the deadline of the request, or `None`
+    /// if it has no deadline.
+    pub fn remaining(&self) -> Option<std::time::Duration> {
+        crate::context::remaining(self.deadline)
+    }
+
+    /// Derives a context for the outgoing calls made while handling the
+    /// request, which carries the remaining time budget of the request.
+    pub fn child_context(&self) -> crate::context::Context {
+        crate::context::Context {
+            deadline: self.deadline,
+            ..Default::default()
+        }
+    }
 }
+
+pub(crate) use crate::proto::get_path;
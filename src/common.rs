@@ -8,7 +8,10 @@
 
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::sys::socket::*;
+#[cfg(feature = "sync")]
+use nix::sys::time::TimeVal;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{env, os::unix::io::RawFd};
 
 use crate::error::{Error, Result};
@@ -19,6 +22,7 @@ pub(crate) enum Domain {
     Tcp,
     #[cfg(any(target_os = "linux", target_os = "android"))]
     Vsock,
+    HybridVsock,
 }
 
 pub(crate) fn do_listen(listener: RawFd) -> Result<()> {
@@ -41,6 +45,10 @@ fn parse_sockaddr(addr: &str) -> Result<(Domain, &str)> {
         return Ok((Domain::Vsock, addr));
     }
 
+    if let Some(addr) = addr.strip_prefix("hvsock://") {
+        return Ok((Domain::HybridVsock, addr));
+    }
+
     if let Some(addr) = addr.strip_prefix("tcp://") {
         return Ok((Domain::Tcp, addr));
     }
@@ -59,6 +67,10 @@ fn parse_sockaddr(addr: &str) -> Result<(Domain, &str)> {
         return Ok((Domain::Unix, addr));
     }
 
+    if let Some(addr) = addr.strip_prefix("hvsock://") {
+        return Ok((Domain::HybridVsock, addr));
+    }
+
     if let Some(addr) = addr.strip_prefix("tcp://") {
         return Ok((Domain::Tcp, addr));
     }
@@ -86,7 +98,7 @@ pub(crate) const SOCK_CLOEXEC: SockFlag = SockFlag::empty();
 #[cfg(any(target_os = "linux", target_os = "android"))]
 fn make_addr(domain: Domain, sockaddr: &str) -> Result<UnixAddr> {
     match domain {
-        Domain::Unix => {
+        Domain::Unix | Domain::HybridVsock => {
             if let Some(sockaddr) = sockaddr.strip_prefix('@') {
                 UnixAddr::new_abstract(sockaddr.as_bytes()).map_err(err_to_others_err!(e, ""))
             } else {
@@ -137,6 +149,131 @@ fn parse_vscok(addr: &str) -> Result<(u32, u32)> {
     Ok((cid, port))
 }
 
+// addr: path:port
+// return (path, port)
+pub(crate) fn parse_hybrid_vsock(addr: &str) -> Result<(&str, u32)> {
+    // hvsock:///run/vm.vsock:1024
+    let (path, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| Error::Others(format!("sockaddr {addr} is not right for hvsock")))?;
+    let port = port.parse().map_err(|e| {
+        Error::Others(format!(
+            "failed to parse port from {:?} error: {:?}",
+            port, e
+        ))
+    })?;
+    Ok((path, port))
+}
+
+/// How long the VMM has to answer `CONNECT`.
+pub(crate) const HYBRID_VSOCK_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The handshake asking the VMM listening on a hybrid vsock socket to
+/// forward the connection to `port` of the guest: it answers `OK <host port>`
+/// to `CONNECT <port>`.
+///
+/// The response is read byte by byte, what follows the line belongs to the
+/// connection.
+pub(crate) struct HybridVsockHandshake {
+    port: u32,
+    response: Vec<u8>,
+}
+
+impl HybridVsockHandshake {
+    /// The longest line a VMM answers to `CONNECT`.
+    const RESPONSE_MAX: usize = 32;
+
+    pub(crate) fn new(port: u32) -> Self {
+        Self {
+            port,
+            response: Vec::new(),
+        }
+    }
+
+    pub(crate) fn request(&self) -> String {
+        format!("CONNECT {}\n", self.port)
+    }
+
+    /// Adds a byte of the response, returns the result of the handshake once
+    /// the line is complete or too long.
+    pub(crate) fn push(&mut self, byte: u8) -> Option<Result<()>> {
+        if byte != b'\n' {
+            self.response.push(byte);
+            return (self.response.len() >= Self::RESPONSE_MAX).then(|| Err(self.invalid()));
+        }
+
+        let response = String::from_utf8_lossy(&self.response);
+        if response.starts_with("OK ") {
+            return Some(Ok(()));
+        }
+        Some(Err(Error::Others(format!(
+            "hybrid vsock connection to port {} refused: {response:?}",
+            self.port
+        ))))
+    }
+
+    /// The error of a response cut short or too long.
+    pub(crate) fn invalid(&self) -> Error {
+        Error::Others(format!(
+            "invalid hybrid vsock response to CONNECT {}: {:?}",
+            self.port,
+            String::from_utf8_lossy(&self.response)
+        ))
+    }
+}
+
+/// Runs the handshake of the hybrid vsock socket `fd`, the VMM has `timeout`
+/// to answer.
+#[cfg(feature = "sync")]
+fn hybrid_vsock_connect(fd: RawFd, port: u32, timeout: Duration) -> Result<()> {
+    let timeout = TimeVal::new(
+        timeout.as_secs() as libc::time_t,
+        timeout.subsec_micros() as libc::suseconds_t,
+    );
+    setsockopt(fd, sockopt::SendTimeout, &timeout)?;
+    setsockopt(fd, sockopt::ReceiveTimeout, &timeout)?;
+
+    let mut handshake = HybridVsockHandshake::new(port);
+    let request = handshake.request();
+    let mut buf = request.as_bytes();
+    while !buf.is_empty() {
+        match nix::unistd::write(fd, buf) {
+            Ok(n) => buf = &buf[n..],
+            Err(nix::Error::EINTR) => continue,
+            Err(e) => return Err(hybrid_vsock_error(port, e)),
+        }
+    }
+
+    let mut byte = [0u8];
+    loop {
+        match nix::unistd::read(fd, &mut byte) {
+            Ok(0) => return Err(handshake.invalid()),
+            Ok(_) => {
+                if let Some(result) = handshake.push(byte[0]) {
+                    result?;
+                    break;
+                }
+            }
+            Err(nix::Error::EINTR) => continue,
+            Err(e) => return Err(hybrid_vsock_error(port, e)),
+        }
+    }
+
+    // The connection blocks again without timeout once established.
+    let timeout = TimeVal::new(0, 0);
+    setsockopt(fd, sockopt::SendTimeout, &timeout)?;
+    setsockopt(fd, sockopt::ReceiveTimeout, &timeout)?;
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+fn hybrid_vsock_error(port: u32, e: nix::Error) -> Error {
+    if e == nix::Error::EAGAIN {
+        return Error::Socket(format!("hybrid vsock handshake of port {port} timed out"));
+    }
+    Error::Socket(e.to_string())
+}
+
 fn make_socket(sockaddr: &str) -> Result<(RawFd, Domain, Box<dyn SockaddrLike>)> {
     let (domain, sockaddrv) = parse_sockaddr(sockaddr)?;
 
@@ -164,6 +301,7 @@ fn make_socket(sockaddr: &str) -> Result<(RawFd, Domain, Box<dyn SockaddrLike>)>
 
     let (fd, sockaddr): (i32, Box<dyn SockaddrLike>) = match domain {
         Domain::Unix => get_unix_addr(domain, sockaddrv)?,
+        Domain::HybridVsock => get_unix_addr(domain, parse_hybrid_vsock(sockaddrv)?.0)?,
         Domain::Tcp => get_tcp_addr(sockaddrv)?,
         #[cfg(any(target_os = "linux", target_os = "android"))]
         Domain::Vsock => {
@@ -206,6 +344,12 @@ fn set_socket_opts(fd: RawFd, domain: Domain, is_bind: bool) -> Result<()> {
 
 pub(crate) fn do_bind(sockaddr: &str) -> Result<(RawFd, Domain)> {
     let (fd, domain, sockaddr) = make_socket(sockaddr)?;
+    if domain == Domain::HybridVsock {
+        let _ = nix::unistd::close(fd);
+        return Err(Error::Others(
+            "hybrid vsock sockets are listened on by the VMM".to_string(),
+        ));
+    }
 
     set_socket_opts(fd, domain, true)?;
     bind(fd, sockaddr.as_ref()).map_err(err_to_others_err!(e, ""))?;
@@ -214,11 +358,20 @@ pub(crate) fn do_bind(sockaddr: &str) -> Result<(RawFd, Domain)> {
 }
 
 /// Creates a unix socket for client.
+#[cfg(feature = "sync")]
 pub(crate) unsafe fn client_connect(sockaddr: &str) -> Result<RawFd> {
-    let (fd, domain, sockaddr) = make_socket(sockaddr)?;
+    let (fd, domain, addr) = make_socket(sockaddr)?;
 
     set_socket_opts(fd, domain, false)?;
-    connect(fd, sockaddr.as_ref())?;
+    connect(fd, addr.as_ref())?;
+
+    if domain == Domain::HybridVsock {
+        let (_, port) = parse_hybrid_vsock(parse_sockaddr(sockaddr)?.1)?;
+        if let Err(e) = hybrid_vsock_connect(fd, port, HYBRID_VSOCK_HANDSHAKE_TIMEOUT) {
+            let _ = nix::unistd::close(fd);
+            return Err(e);
+        }
+    }
 
     Ok(fd)
 }
@@ -252,6 +405,12 @@ mod tests {
                 "127.0.0.1:65500",
                 true,
             ),
+            (
+                "hvsock:///run/vm.vsock:1024",
+                Some(Domain::HybridVsock),
+                "/run/vm.vsock:1024",
+                true,
+            ),
         ] {
             let (input, domain, addr, success) = (i.0, i.1, i.2, i.3);
             let r = parse_sockaddr(input);
@@ -285,6 +444,12 @@ mod tests {
                 "127.0.0.1:65500",
                 true,
             ),
+            (
+                "hvsock:///run/vm.vsock:1024",
+                Some(Domain::HybridVsock),
+                "/run/vm.vsock:1024",
+                true,
+            ),
         ] {
             let (input, domain, addr, success) = (i.0, i.1, i.2, i.3);
             let r = parse_sockaddr(input);
@@ -313,4 +478,62 @@ mod tests {
             assert_eq!(r.unwrap(), (cid, port), "parse {:?} failed", i);
         }
     }
+
+    #[test]
+    fn test_parse_hybrid_vsock() {
+        assert_eq!(
+            parse_hybrid_vsock("/run/vm.vsock:1024").unwrap(),
+            ("/run/vm.vsock", 1024)
+        );
+        assert!(parse_hybrid_vsock("/run/vm.vsock").is_err());
+        assert!(parse_hybrid_vsock("/run/vm.vsock:-1").is_err());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_hybrid_vsock_connect() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::os::unix::io::FromRawFd;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = env::temp_dir().join(format!("ttrpc-hvsock-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // Stands in for the VMM, which accepts the port 1024 only.
+        let vmm = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut line = String::new();
+                BufReader::new(&mut socket).read_line(&mut line).unwrap();
+                if line == "CONNECT 1024\n" {
+                    socket.write_all(b"OK 1073741824\nping").unwrap();
+                }
+            }
+        });
+
+        let addr = format!("hvsock://{}", path.display());
+        let fd = unsafe { client_connect(&format!("{addr}:1024")).unwrap() };
+        let mut socket = unsafe { UnixStream::from_raw_fd(fd) };
+        let mut buf = [0; 4];
+        socket.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        assert!(unsafe { client_connect(&format!("{addr}:1025")) }.is_err());
+        assert!(do_bind(&format!("{addr}:1024")).is_err());
+        vmm.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_hybrid_vsock_connect_timeout() {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::net::UnixStream;
+
+        // Stands in for a VMM which never answers.
+        let (socket, _vmm) = UnixStream::pair().unwrap();
+        let e =
+            hybrid_vsock_connect(socket.as_raw_fd(), 1024, Duration::from_millis(10)).unwrap_err();
+        assert!(e.to_string().contains("timed out"), "{}", e);
+    }
 }
@@ -3,13 +3,18 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use crate::proto::KeyValue;
+use crate::error::{get_rpc_status, Result};
+use crate::proto::{Code, KeyValue, Request};
 use core::time::Duration;
 use std::collections::HashMap;
+use std::time::Instant;
 #[derive(Clone, Default, Debug)]
 pub struct Context {
     pub metadata: HashMap<String, Vec<String>>,
     pub timeout_nano: i64,
+    /// The absolute deadline of the request, it is sent as the time left
+    /// when the request is issued.
+    pub deadline: Option<Instant>,
 }
 
 pub fn with_timeout(i: i64) -> Context {
@@ -24,6 +29,15 @@ pub fn with_duration(du: Duration) -> Context {
     with_timeout(du.as_nanos() as i64)
 }
 
+/// Creates a context which expires at the given deadline, e.g. the deadline of
+/// the request being handled by the server.
+pub fn with_deadline(deadline: Instant) -> Context {
+    Context {
+        deadline: Some(deadline),
+        ..Default::default()
+    }
+}
+
 pub fn with_metadata(md: HashMap<String, Vec<String>>) -> Context {
     Context {
         metadata: md,
@@ -51,6 +65,59 @@ impl Context {
             self.metadata.insert(key.to_lowercase(), value);
         }
     }
+
+    /// Returns the time left for the request, or `None` if it is unlimited.
+    pub fn remaining(&self) -> Option<Duration> {
+        let timeout =
+            (self.timeout_nano > 0).then(|| Duration::from_nanos(self.timeout_nano as u64));
+        match (remaining(self.deadline), timeout) {
+            (Some(left), Some(timeout)) => Some(left.min(timeout)),
+            (left, timeout) => left.or(timeout),
+        }
+    }
+
+    /// Returns the `timeout_nano` of the request to send, the deadline is
+    /// converted to the time left.
+    ///
+    /// It fails with `DEADLINE_EXCEEDED` if the deadline has already passed.
+    pub fn request_timeout_nano(&self) -> Result<i64> {
+        match self.remaining() {
+            None => Ok(0),
+            Some(left) if left.is_zero() => Err(get_rpc_status(
+                Code::DEADLINE_EXCEEDED,
+                "context deadline exceeded",
+            )),
+            Some(left) => Ok(left.as_nanos().min(i64::MAX as u128) as i64),
+        }
+    }
+}
+
+/// Builds a request of `service`.`method` carrying the deadline and the
+/// metadata of the context.
+pub(crate) fn new_request(
+    ctx: Context,
+    service: &str,
+    method: &str,
+    payload: Vec<u8>,
+) -> Result<Request> {
+    Ok(Request {
+        service: service.to_string(),
+        method: method.to_string(),
+        timeout_nano: ctx.request_timeout_nano()?,
+        metadata: to_pb(ctx.metadata),
+        payload,
+        ..Default::default()
+    })
+}
+
+/// Returns the deadline of a request received with the given `timeout_nano`.
+pub(crate) fn deadline(timeout_nano: i64) -> Option<Instant> {
+    (timeout_nano > 0).then(|| Instant::now() + Duration::from_nanos(timeout_nano as u64))
+}
+
+/// Returns the time left before the deadline, if any.
+pub(crate) fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|d| d.saturating_duration_since(Instant::now()))
 }
 
 pub fn from_pb(kvs: &Vec<KeyValue>) -> HashMap<String, Vec<String>> {
@@ -84,8 +151,11 @@ pub fn to_pb(kvs: HashMap<String, Vec<String>>) -> Vec<KeyValue> {
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
+
     use crate::context;
-    use crate::proto::KeyValue;
+    use crate::error::Error;
+    use crate::proto::{Code, KeyValue};
 
     #[test]
     fn test_metadata() {
@@ -162,4 +232,25 @@ mod tests {
         assert_eq!(ctx.metadata.len(), 1);
         assert_eq!(ctx.metadata.get("key1"), None);
     }
+
+    #[test]
+    fn test_deadline() {
+        let ctx: context::Context = Default::default();
+        assert_eq!(ctx.remaining(), None);
+        assert_eq!(ctx.request_timeout_nano(), Ok(0));
+
+        let ctx = context::with_deadline(Instant::now() + Duration::from_secs(10));
+        let left = ctx.request_timeout_nano().unwrap();
+        assert!(left > 0 && left <= Duration::from_secs(10).as_nanos() as i64);
+
+        // The shorter one of the timeout and the deadline is used.
+        let mut ctx = context::with_duration(Duration::from_millis(1));
+        ctx.deadline = Some(Instant::now() + Duration::from_secs(10));
+        assert_eq!(ctx.remaining(), Some(Duration::from_millis(1)));
+
+        let ctx = context::with_deadline(Instant::now());
+        assert_eq!(ctx.remaining(), Some(Duration::ZERO));
+        let err = ctx.request_timeout_nano().unwrap_err();
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::DEADLINE_EXCEEDED));
+    }
 }
@@ -48,20 +48,77 @@ pub enum Error {
     Others(String),
 }
 
-impl From<Error> for Response {
+/// The status of an error answered to a client, the errors other than
+/// [`Error::RpcStatus`] are `UNKNOWN`.
+impl From<Error> for Status {
     fn from(e: Error) -> Self {
-        let status = if let Error::RpcStatus(stat) = e {
-            stat
-        } else {
-            get_status(Code::UNKNOWN, e)
-        };
+        match e {
+            Error::RpcStatus(status) => status,
+            e => get_status(Code::UNKNOWN, e),
+        }
+    }
+}
 
+impl From<Error> for Response {
+    fn from(e: Error) -> Self {
         let mut res = Response::new();
-        res.set_status(status);
+        res.set_status(e.into());
         res
     }
 }
 
+/// Converts the errors returned by the services into the status answered to
+/// the clients, the generated services return them when the codegen option
+/// `error_type` is set.
+///
+/// The status has the [`code`](IntoStatus::code) of the error and the
+/// displayed error as message, unless `into_status` is overridden. The
+/// [`enum@Error`]s other than [`Error::RpcStatus`] keep their `Debug` form as
+/// message, as the handlers always answered them.
+pub trait IntoStatus: std::fmt::Display + Sized {
+    /// The code of the error, `UNKNOWN` by default.
+    fn code(&self) -> Code {
+        Code::UNKNOWN
+    }
+
+    fn into_status(self) -> Status {
+        get_status(self.code(), self)
+    }
+}
+
+impl IntoStatus for Error {
+    fn code(&self) -> Code {
+        match self {
+            Error::RpcStatus(status) => status.code(),
+            _ => Code::UNKNOWN,
+        }
+    }
+
+    fn into_status(self) -> Status {
+        match self {
+            Error::RpcStatus(status) => status,
+            e => get_status(Code::UNKNOWN, format!("{e:?}")),
+        }
+    }
+}
+
+impl IntoStatus for std::io::Error {
+    fn code(&self) -> Code {
+        use std::io::ErrorKind;
+
+        match self.kind() {
+            ErrorKind::NotFound => Code::NOT_FOUND,
+            ErrorKind::PermissionDenied => Code::PERMISSION_DENIED,
+            ErrorKind::AlreadyExists => Code::ALREADY_EXISTS,
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => Code::INVALID_ARGUMENT,
+            ErrorKind::TimedOut => Code::DEADLINE_EXCEEDED,
+            ErrorKind::Unsupported => Code::UNIMPLEMENTED,
+            ErrorKind::OutOfMemory => Code::RESOURCE_EXHAUSTED,
+            _ => Code::UNKNOWN,
+        }
+    }
+}
+
 /// A specialized Result type for ttrpc.
 pub type Result<T> = result::Result<T, Error>;
 
@@ -100,3 +157,22 @@ macro_rules! err_to_others {
         |$e| ::ttrpc::Error::Others($s.to_string() + &$e.to_string())
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_status() {
+        let e = std::io::Error::new(std::io::ErrorKind::NotFound, "no such sandbox");
+        let status = e.into_status();
+        assert_eq!(status.code(), Code::NOT_FOUND);
+        assert_eq!(status.message, "no such sandbox");
+
+        let status = get_status(Code::ABORTED, "aborted");
+        assert_eq!(Error::RpcStatus(status.clone()).into_status(), status);
+        let status = Error::Others("boom".to_string()).into_status();
+        assert_eq!(status.code(), Code::UNKNOWN);
+        assert_eq!(status.message, "Others(\"boom\")");
+    }
+}
@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Typed details of the errors, compatible with the `google.rpc` rich error
+//! model.
+//!
+//! The details are protobuf messages packed in the `details` of a [`Status`],
+//! such as the [`ErrorInfo`], [`RetryInfo`] and [`BadRequest`] defined here.
+//! The handlers return a [`RichError`], which converts into
+//! [`Error::RpcStatus`], and the clients read it back from their errors:
+//!
+//! ```
+//! use std::convert::TryFrom;
+//!
+//! use ttrpc::error_details::{ErrorInfo, RichError};
+//! use ttrpc::{Code, Error};
+//!
+//! let info = ErrorInfo {
+//!     reason: "QUOTA_EXCEEDED".to_string(),
+//!     ..Default::default()
+//! };
+//! let err: Error = RichError::new(Code::RESOURCE_EXHAUSTED, "no more sandboxes")
+//!     .with_detail(&info)
+//!     .into();
+//!
+//! let err = RichError::try_from(err).unwrap();
+//! assert_eq!(err.code(), Code::RESOURCE_EXHAUSTED);
+//! assert_eq!(err.detail::<ErrorInfo>(), Some(info));
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use protobuf::MessageFull;
+
+use crate::error::{Error, IntoStatus, Result};
+use crate::proto::{Any, Code, Status};
+
+#[doc(inline)]
+pub use crate::proto::compiled::error_details::*;
+
+/// The prefix of the type URLs of the packed messages.
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+impl Any {
+    /// Packs `message`, its type URL names its full protobuf name.
+    pub fn pack<M: MessageFull>(message: &M) -> Result<Any> {
+        let value = message
+            .write_to_bytes()
+            .map_err(err_to_others_err!(e, "pack message failed: "))?;
+        Ok(Any {
+            type_url: format!("{TYPE_URL_PREFIX}{}", M::descriptor().full_name()),
+            value,
+            ..Default::default()
+        })
+    }
+
+    /// Returns `true` if the packed message is a `M`.
+    pub fn is<M: MessageFull>(&self) -> bool {
+        let name = self.type_url.rsplit('/').next().unwrap_or_default();
+        name == M::descriptor().full_name()
+    }
+
+    /// Unpacks the message, returns `None` if it is not a `M`.
+    pub fn unpack<M: MessageFull>(&self) -> Result<Option<M>> {
+        if !self.is::<M>() {
+            return Ok(None);
+        }
+        M::parse_from_bytes(&self.value)
+            .map(Some)
+            .map_err(err_to_others_err!(e, "unpack message failed: "))
+    }
+}
+
+impl Status {
+    /// Packs `detail` in the details.
+    pub fn add_detail<M: MessageFull>(&mut self, detail: &M) -> Result<()> {
+        self.details.push(Any::pack(detail)?);
+        Ok(())
+    }
+
+    /// Returns the first detail which is a `M`.
+    pub fn detail<M: MessageFull>(&self) -> Option<M> {
+        self.details.iter().find_map(|d| d.unpack().ok().flatten())
+    }
+
+    /// Returns the details which are `M`s.
+    pub fn details_of<M: MessageFull>(&self) -> Vec<M> {
+        self.details
+            .iter()
+            .filter_map(|d| d.unpack().ok().flatten())
+            .collect()
+    }
+}
+
+/// An error status with typed details.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RichError {
+    status: Status,
+}
+
+impl RichError {
+    pub fn new(code: Code, message: impl ToString) -> Self {
+        Self {
+            status: crate::error::get_status(code, message),
+        }
+    }
+
+    /// Adds the detail `detail`, which is left out if it fails to encode,
+    /// i.e. if a required field is missing.
+    pub fn with_detail<M: MessageFull>(mut self, detail: &M) -> Self {
+        if let Err(e) = self.status.add_detail(detail) {
+            warn!("failed to add the error detail: {}", e);
+        }
+        self
+    }
+
+    pub fn code(&self) -> Code {
+        self.status.code()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.status.message
+    }
+
+    /// Returns the first detail which is a `M`.
+    pub fn detail<M: MessageFull>(&self) -> Option<M> {
+        self.status.detail()
+    }
+
+    /// Returns the details which are `M`s.
+    pub fn details<M: MessageFull>(&self) -> Vec<M> {
+        self.status.details_of()
+    }
+
+    pub fn error_info(&self) -> Option<ErrorInfo> {
+        self.detail()
+    }
+
+    pub fn retry_info(&self) -> Option<RetryInfo> {
+        self.detail()
+    }
+
+    pub fn bad_request(&self) -> Option<BadRequest> {
+        self.detail()
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+}
+
+impl fmt::Display for RichError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for RichError {}
+
+impl IntoStatus for RichError {
+    fn code(&self) -> Code {
+        self.status.code()
+    }
+
+    fn into_status(self) -> Status {
+        self.status
+    }
+}
+
+impl From<Status> for RichError {
+    fn from(status: Status) -> Self {
+        Self { status }
+    }
+}
+
+impl From<RichError> for Status {
+    fn from(e: RichError) -> Self {
+        e.status
+    }
+}
+
+impl From<RichError> for Error {
+    fn from(e: RichError) -> Self {
+        Error::RpcStatus(e.status)
+    }
+}
+
+/// Reads the status of an [`Error::RpcStatus`], gives the other errors back.
+impl TryFrom<Error> for RichError {
+    type Error = Error;
+
+    fn try_from(e: Error) -> Result<Self> {
+        match e {
+            Error::RpcStatus(status) => Ok(status.into()),
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::Response;
+    use protobuf::well_known_types::duration::Duration;
+    use protobuf::Message;
+
+    #[test]
+    fn test_rich_error() {
+        let retry = RetryInfo {
+            retry_delay: Some(Duration {
+                seconds: 3,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        };
+        let mut bad_request = BadRequest::new();
+        for field in ["id", "name"] {
+            bad_request
+                .field_violations
+                .push(bad_request::FieldViolation {
+                    field: field.to_string(),
+                    ..Default::default()
+                });
+        }
+
+        let err: Error = RichError::new(Code::INVALID_ARGUMENT, "bad request")
+            .with_detail(&retry)
+            .with_detail(&bad_request)
+            .with_detail(&bad_request)
+            .into();
+        // As the client gets it.
+        let res = Response::from(err);
+        let res = Response::parse_from_bytes(&res.write_to_bytes().unwrap()).unwrap();
+        let err = RichError::try_from(Error::RpcStatus(res.status.unwrap())).unwrap();
+
+        assert_eq!(err.code(), Code::INVALID_ARGUMENT);
+        assert_eq!(err.message(), "bad request");
+        assert_eq!(err.retry_info(), Some(retry));
+        assert_eq!(err.details::<BadRequest>().len(), 2);
+        assert_eq!(err.bad_request(), Some(bad_request));
+        assert_eq!(err.error_info(), None);
+        assert_eq!(
+            err.status().details[0].type_url,
+            "type.googleapis.com/google.rpc.RetryInfo"
+        );
+
+        assert!(RichError::try_from(Error::Eof).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Health checking service and client (async).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use super::{HealthCheckRequest, HealthCheckResponse, HealthReporter, SERVICE_NAME};
+use crate::context::{self, Context};
+use crate::error::{get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+use crate::r#async::{
+    Client, ClientStreamReceiver, MethodHandler, ServerStreamSender, Service, StreamHandler,
+    StreamInner, TtrpcContext,
+};
+
+struct CheckMethod {
+    reporter: HealthReporter,
+}
+
+#[async_trait]
+impl MethodHandler for CheckMethod {
+    async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+        let req = HealthCheckRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        let rep = self.reporter.check(&req)?;
+        Ok(Response {
+            status: Some(get_status(Code::OK, "")).into(),
+            payload: rep.encode().map_err(err_to_others_err!(e, ""))?,
+            ..Default::default()
+        })
+    }
+}
+
+struct WatchMethod {
+    reporter: HealthReporter,
+}
+
+#[async_trait]
+impl StreamHandler for WatchMethod {
+    async fn handler(&self, ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+        let req_buf = inner.recv().await?;
+        let req = HealthCheckRequest::decode(req_buf).map_err(err_to_others_err!(e, ""))?;
+        let stream = ServerStreamSender::new(inner);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        // The watcher is removed once the stream ends.
+        let (mut rep, _guard) = self.reporter.watch(
+            &req.service,
+            Box::new(move |status| tx.send(status).is_ok()),
+        );
+        loop {
+            stream.send(&rep).await?;
+            // Stop watching once the stream is cancelled.
+            tokio::select! {
+                status = rx.recv() => match status {
+                    Some(status) => rep = super::response(status),
+                    None => return Ok(None),
+                },
+                _ = ctx.cancel.wait_shutdown() => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Creates the health service, to be registered by `Server::register_service`.
+pub fn create_health(reporter: HealthReporter) -> HashMap<String, Service> {
+    let mut methods = HashMap::new();
+    let mut streams = HashMap::new();
+
+    methods.insert(
+        "Check".to_string(),
+        Box::new(CheckMethod {
+            reporter: reporter.clone(),
+        }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    streams.insert(
+        "Watch".to_string(),
+        Arc::new(WatchMethod { reporter }) as Arc<dyn StreamHandler + Send + Sync>,
+    );
+
+    let mut ret = HashMap::new();
+    ret.insert(SERVICE_NAME.to_string(), Service { methods, streams });
+    ret
+}
+
+/// The client of the health service (async).
+#[derive(Clone)]
+pub struct HealthClient {
+    client: Client,
+}
+
+impl HealthClient {
+    pub fn new(client: Client) -> Self {
+        HealthClient { client }
+    }
+
+    pub async fn check(
+        &self,
+        ctx: Context,
+        req: &HealthCheckRequest,
+    ) -> Result<HealthCheckResponse> {
+        let res = self.client.request(new_request(ctx, "Check", req)?).await?;
+        HealthCheckResponse::decode(res.payload).map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    pub async fn watch(
+        &self,
+        ctx: Context,
+        req: &HealthCheckRequest,
+    ) -> Result<ClientStreamReceiver<HealthCheckResponse>> {
+        let inner = self
+            .client
+            .new_stream(new_request(ctx, "Watch", req)?, false, true)
+            .await?;
+        Ok(ClientStreamReceiver::new(inner, self.client.clone()))
+    }
+}
+
+fn new_request(ctx: Context, method: &str, req: &HealthCheckRequest) -> Result<Request> {
+    let payload = req.encode().map_err(err_to_others_err!(e, ""))?;
+    context::new_request(ctx, SERVICE_NAME, method, payload)
+}
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::ServingStatus;
+    use crate::r#async::Server;
+
+    #[tokio::test]
+    async fn test_health_service() {
+        let addr = r"unix://@/tmp/ttrpc-health-unit-test";
+        let reporter = HealthReporter::new();
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(create_health(reporter.clone()));
+        server.start().await.unwrap();
+
+        let client = HealthClient::new(Client::connect(addr).await.unwrap());
+        let req = HealthCheckRequest {
+            service: "foo".to_string(),
+            ..Default::default()
+        };
+        let res = client.check(Context::default(), &req).await;
+        assert!(matches!(res, Err(Error::RpcStatus(s)) if s.code() == Code::NOT_FOUND));
+
+        let mut stream = client.watch(Context::default(), &req).await.unwrap();
+        for status in [
+            ServingStatus::SERVICE_UNKNOWN,
+            ServingStatus::SERVING,
+            ServingStatus::NOT_SERVING,
+        ] {
+            if status != ServingStatus::SERVICE_UNKNOWN {
+                reporter.set_serving_status("foo", status);
+            }
+            let rep = stream.recv().await.unwrap().unwrap();
+            assert_eq!(rep.status.enum_value_or_default(), status);
+        }
+
+        let res = client.check(Context::default(), &req).await.unwrap();
+        assert_eq!(
+            res.status.enum_value_or_default(),
+            ServingStatus::NOT_SERVING
+        );
+
+        // The watcher is removed once the connection is closed, even if the
+        // status never changes again.
+        drop(stream);
+        drop(client);
+        for _ in 0..100 {
+            if reporter.registry.lock().unwrap().watchers.is_empty() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("the watcher is not removed");
+    }
+}
@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Health checking service compatible with `grpc.health.v1.Health`.
+//!
+//! The serving status of the services is set through a [`HealthReporter`],
+//! the service created from it by `sync::create_health` or
+//! `asynchronous::create_health` answers `Check` and streams the changes of
+//! the status to `Watch`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{get_rpc_status, Result};
+use crate::proto::Code;
+
+#[doc(inline)]
+pub use crate::proto::compiled::health::health_check_response::ServingStatus;
+#[doc(inline)]
+pub use crate::proto::compiled::health::{HealthCheckRequest, HealthCheckResponse};
+
+cfg_sync! {
+    pub mod sync;
+}
+
+cfg_async! {
+    pub mod asynchronous;
+    #[doc(hidden)]
+    pub use asynchronous as r#async;
+}
+
+/// The name of the health service.
+pub const SERVICE_NAME: &str = "grpc.health.v1.Health";
+
+/// Notified of the new status of a service, returns `false` once it is gone.
+type Watcher = Box<dyn Fn(ServingStatus) -> bool + Send>;
+
+#[derive(Default)]
+struct Registry {
+    statuses: HashMap<String, ServingStatus>,
+    watchers: HashMap<String, Vec<(u64, Watcher)>>,
+    next_watcher: u64,
+}
+
+/// Sets the serving status reported by the health service.
+///
+/// The empty service name stands for the whole server, which is `SERVING`
+/// once the reporter is created.
+#[derive(Clone)]
+pub struct HealthReporter {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Default for HealthReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthReporter {
+    pub fn new() -> HealthReporter {
+        let mut registry = Registry::default();
+        registry
+            .statuses
+            .insert(String::new(), ServingStatus::SERVING);
+        HealthReporter {
+            registry: Arc::new(Mutex::new(registry)),
+        }
+    }
+
+    /// Sets the serving status of `service`, and notifies its watchers if the
+    /// status changes.
+    pub fn set_serving_status(&self, service: &str, status: ServingStatus) {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.statuses.insert(service.to_string(), status) == Some(status) {
+            return;
+        }
+        if let Some(watchers) = registry.watchers.get_mut(service) {
+            watchers.retain(|(_, watcher)| watcher(status));
+        }
+    }
+
+    /// Sets all the services `NOT_SERVING`, e.g. before the server shuts down.
+    pub fn shutdown(&self) {
+        let services: Vec<String> = self
+            .registry
+            .lock()
+            .unwrap()
+            .statuses
+            .keys()
+            .cloned()
+            .collect();
+        for service in services {
+            self.set_serving_status(&service, ServingStatus::NOT_SERVING);
+        }
+    }
+
+    /// Returns the serving status of `service`, `None` if it is never set.
+    pub fn serving_status(&self, service: &str) -> Option<ServingStatus> {
+        self.registry.lock().unwrap().statuses.get(service).copied()
+    }
+
+    /// Answers `Check`, which fails with `NOT_FOUND` for an unknown service.
+    pub(crate) fn check(&self, req: &HealthCheckRequest) -> Result<HealthCheckResponse> {
+        match self.serving_status(&req.service) {
+            Some(status) => Ok(response(status)),
+            None => Err(get_rpc_status(
+                Code::NOT_FOUND,
+                format!("unknown service {}", req.service),
+            )),
+        }
+    }
+
+    /// Registers a watcher of `service` and returns its current status, which
+    /// is `SERVICE_UNKNOWN` for an unknown service. The watcher is removed
+    /// once the returned guard is dropped.
+    pub(crate) fn watch(
+        &self,
+        service: &str,
+        watcher: Watcher,
+    ) -> (HealthCheckResponse, WatchGuard) {
+        let mut registry = self.registry.lock().unwrap();
+        let status = registry
+            .statuses
+            .get(service)
+            .copied()
+            .unwrap_or(ServingStatus::SERVICE_UNKNOWN);
+        let id = registry.next_watcher;
+        registry.next_watcher += 1;
+        registry
+            .watchers
+            .entry(service.to_string())
+            .or_default()
+            .push((id, watcher));
+        let guard = WatchGuard {
+            registry: self.registry.clone(),
+            service: service.to_string(),
+            id,
+        };
+        (response(status), guard)
+    }
+}
+
+/// Removes a watcher when dropped, e.g. when its stream ends.
+pub(crate) struct WatchGuard {
+    registry: Arc<Mutex<Registry>>,
+    service: String,
+    id: u64,
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(watchers) = registry.watchers.get_mut(&self.service) {
+            watchers.retain(|(id, _)| *id != self.id);
+            if watchers.is_empty() {
+                registry.watchers.remove(&self.service);
+            }
+        }
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status.into(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_health_reporter() {
+        let reporter = HealthReporter::new();
+        let check = |service: &str| {
+            let req = HealthCheckRequest {
+                service: service.to_string(),
+                ..Default::default()
+            };
+            reporter
+                .check(&req)
+                .map(|res| res.status.enum_value_or_default())
+        };
+        assert_eq!(check(""), Ok(ServingStatus::SERVING));
+        assert!(check("foo").is_err());
+
+        let (tx, rx) = channel();
+        let (res, guard) = reporter.watch("foo", Box::new(move |status| tx.send(status).is_ok()));
+        assert_eq!(
+            res.status.enum_value_or_default(),
+            ServingStatus::SERVICE_UNKNOWN
+        );
+
+        reporter.set_serving_status("foo", ServingStatus::SERVING);
+        // Setting the same status again does not notify the watchers.
+        reporter.set_serving_status("foo", ServingStatus::SERVING);
+        assert_eq!(check("foo"), Ok(ServingStatus::SERVING));
+
+        reporter.shutdown();
+        assert_eq!(check(""), Ok(ServingStatus::NOT_SERVING));
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![ServingStatus::SERVING, ServingStatus::NOT_SERVING]
+        );
+
+        // The watcher is dropped once its receiver is gone.
+        drop(rx);
+        reporter.set_serving_status("foo", ServingStatus::SERVING);
+        assert!(reporter.registry.lock().unwrap().watchers["foo"].is_empty());
+        drop(guard);
+
+        // Or once its guard is dropped, even if the status never changes.
+        let (_, guard) = reporter.watch("foo", Box::new(|_| true));
+        let (_, other) = reporter.watch("foo", Box::new(|_| true));
+        drop(guard);
+        assert_eq!(reporter.registry.lock().unwrap().watchers["foo"].len(), 1);
+        drop(other);
+        assert!(reporter.registry.lock().unwrap().watchers.is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Health checking service and client (sync).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{HealthCheckRequest, HealthCheckResponse, HealthReporter, SERVICE_NAME};
+use crate::context::{self, Context};
+use crate::error::{get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+use crate::sync::{
+    response_to_channel, Client, ClientStreamReceiver, MethodHandler, ServerStreamSender,
+    StreamHandler, StreamInner, TtrpcContext,
+};
+
+struct CheckMethod {
+    reporter: HealthReporter,
+}
+
+impl MethodHandler for CheckMethod {
+    fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()> {
+        let req = HealthCheckRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        let res = match self.reporter.check(&req) {
+            Ok(rep) => Response {
+                status: Some(get_status(Code::OK, "")).into(),
+                payload: rep.encode().map_err(err_to_others_err!(e, ""))?,
+                ..Default::default()
+            },
+            Err(e) => e.into(),
+        };
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+}
+
+struct WatchMethod {
+    reporter: HealthReporter,
+}
+
+impl StreamHandler for WatchMethod {
+    fn handler(&self, ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+        let req_buf = inner.recv()?;
+        let req = HealthCheckRequest::decode(req_buf).map_err(err_to_others_err!(e, ""))?;
+        let stream = ServerStreamSender::new(inner);
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        // The watcher is removed once the stream ends.
+        let (mut rep, _guard) = self.reporter.watch(
+            &req.service,
+            Box::new(move |status| tx.send(status).is_ok()),
+        );
+        loop {
+            stream.send(&rep)?;
+            // Stop watching once the connection is closed.
+            crossbeam::channel::select! {
+                recv(rx) -> status => match status {
+                    Ok(status) => rep = super::response(status),
+                    Err(_) => return Ok(None),
+                },
+                recv(ctx.cancel_rx) -> _ => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Creates the `Check` method of the health service, to be registered by
+/// `Server::register_service`.
+pub fn create_health(
+    reporter: HealthReporter,
+) -> HashMap<String, Box<dyn MethodHandler + Send + Sync>> {
+    let mut methods = HashMap::new();
+    methods.insert(
+        format!("/{SERVICE_NAME}/Check"),
+        Box::new(CheckMethod { reporter }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods
+}
+
+/// Creates the `Watch` stream of the health service, to be registered by
+/// `Server::register_stream_service`.
+pub fn create_health_streams(
+    reporter: HealthReporter,
+) -> HashMap<String, Arc<dyn StreamHandler + Send + Sync>> {
+    let mut streams = HashMap::new();
+    streams.insert(
+        format!("/{SERVICE_NAME}/Watch"),
+        Arc::new(WatchMethod { reporter }) as Arc<dyn StreamHandler + Send + Sync>,
+    );
+    streams
+}
+
+/// The client of the health service (sync).
+#[derive(Clone)]
+pub struct HealthClient {
+    client: Client,
+}
+
+impl HealthClient {
+    pub fn new(client: Client) -> Self {
+        HealthClient { client }
+    }
+
+    pub fn check(&self, ctx: Context, req: &HealthCheckRequest) -> Result<HealthCheckResponse> {
+        let res = self.client.request(new_request(ctx, "Check", req)?)?;
+        HealthCheckResponse::decode(res.payload).map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    pub fn watch(
+        &self,
+        ctx: Context,
+        req: &HealthCheckRequest,
+    ) -> Result<ClientStreamReceiver<HealthCheckResponse>> {
+        let inner = self
+            .client
+            .new_stream(new_request(ctx, "Watch", req)?, false, true)?;
+        Ok(ClientStreamReceiver::new(inner))
+    }
+}
+
+fn new_request(ctx: Context, method: &str, req: &HealthCheckRequest) -> Result<Request> {
+    let payload = req.encode().map_err(err_to_others_err!(e, ""))?;
+    context::new_request(ctx, SERVICE_NAME, method, payload)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::health::ServingStatus;
+    use crate::sync::Server;
+
+    #[test]
+    fn test_health_service() {
+        let addr = "memory://sync-health-unit-test";
+        let reporter = HealthReporter::new();
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(create_health(reporter.clone()))
+            .register_stream_service(create_health_streams(reporter.clone()));
+        server.start().unwrap();
+
+        let client = HealthClient::new(Client::connect(addr).unwrap());
+        let req = HealthCheckRequest {
+            service: "foo".to_string(),
+            ..Default::default()
+        };
+        let res = client.check(Context::default(), &req);
+        assert!(matches!(res, Err(Error::RpcStatus(s)) if s.code() == Code::NOT_FOUND));
+
+        let mut stream = client.watch(Context::default(), &req).unwrap();
+        for status in [
+            ServingStatus::SERVICE_UNKNOWN,
+            ServingStatus::SERVING,
+            ServingStatus::NOT_SERVING,
+        ] {
+            if status != ServingStatus::SERVICE_UNKNOWN {
+                reporter.set_serving_status("foo", status);
+            }
+            let rep = stream.recv().unwrap().unwrap();
+            assert_eq!(rep.status.enum_value_or_default(), status);
+        }
+
+        let res = client.check(Context::default(), &req).unwrap();
+        assert_eq!(
+            res.status.enum_value_or_default(),
+            ServingStatus::NOT_SERVING
+        );
+
+        // The watcher is removed once the connection is closed, even if the
+        // status never changes again.
+        drop(stream);
+        drop(client);
+        for _ in 0..100 {
+            if reporter.registry.lock().unwrap().watchers.is_empty() {
+                server.shutdown();
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the watcher is not removed");
+    }
+}
@@ -52,20 +52,26 @@ mod common;
 mod macros;
 
 pub mod context;
+pub mod error_details;
+pub mod health;
+pub mod metrics;
+pub mod peer;
+pub mod reflection;
+pub mod retry;
 
 pub mod proto;
 #[doc(inline)]
-pub use self::proto::{Code, MessageHeader, Request, Response, Status};
+pub use self::proto::{Code, MessageHeader, MessageSizeLimits, Request, Response, Status};
 
 #[doc(inline)]
-pub use crate::error::{get_status, Error, Result};
+pub use crate::error::{get_status, Error, IntoStatus, Result};
 
 cfg_sync! {
     pub mod sync;
     #[doc(hidden)]
     pub use sync::response_to_channel;
     #[doc(inline)]
-    pub use sync::{MethodHandler, TtrpcContext};
+    pub use sync::{MethodHandler, StreamHandler, TtrpcContext};
     pub use sync::Client;
     #[doc(inline)]
     pub use sync::Server;
@@ -0,0 +1,451 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Metrics of the servers and the clients.
+//!
+//! The servers and the clients of the async and the sync stacks report their
+//! calls, messages and connections to the [`Recorder`] set by
+//! [`set_recorder`], nothing is recorded until then. The [`Registry`] keeps
+//! them in memory and renders them in the Prometheus text format:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use ttrpc::metrics::{set_recorder, Registry};
+//!
+//! let registry = Arc::new(Registry::default());
+//! set_recorder(registry.clone());
+//! // Served on the `/metrics` endpoint of the application.
+//! let text = registry.render();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proto::Code;
+
+/// The side of a connection the metrics are recorded on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+impl Side {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Client => "client",
+            Side::Server => "server",
+        }
+    }
+}
+
+/// Records the metrics, all the methods do nothing by default.
+///
+/// The methods are called on the paths of the requests and the messages,
+/// they must not block.
+pub trait Recorder: Send + Sync {
+    /// A call of `method`, `/service/method`, started. The streaming calls
+    /// last as long as their stream.
+    fn call_started(&self, _side: Side, _method: &str, _streaming: bool) {}
+
+    /// A call finished with `code` after `latency`, `CANCELLED` if it was
+    /// abandoned before its status was known.
+    fn call_finished(
+        &self,
+        _side: Side,
+        _method: &str,
+        _streaming: bool,
+        _code: Code,
+        _latency: Duration,
+    ) {
+    }
+
+    /// A message of `len` bytes, its header included, was sent.
+    fn message_sent(&self, _side: Side, _len: usize) {}
+
+    /// A message of `len` bytes, its header included, was received.
+    fn message_received(&self, _side: Side, _len: usize) {}
+
+    fn connection_opened(&self, _side: Side) {}
+
+    fn connection_closed(&self, _side: Side) {}
+}
+
+static RECORDER: RwLock<Option<Arc<dyn Recorder>>> = RwLock::new(None);
+
+/// Sets the recorder of the process, replacing the previous one.
+pub fn set_recorder(recorder: Arc<dyn Recorder>) {
+    *RECORDER.write().unwrap() = Some(recorder);
+}
+
+/// Removes the recorder of the process.
+pub fn clear_recorder() {
+    *RECORDER.write().unwrap() = None;
+}
+
+fn recorder() -> Option<Arc<dyn Recorder>> {
+    RECORDER.read().unwrap().clone()
+}
+
+pub(crate) fn message_sent(side: Side, len: usize) {
+    if let Some(recorder) = recorder() {
+        recorder.message_sent(side, len);
+    }
+}
+
+pub(crate) fn message_received(side: Side, len: usize) {
+    if let Some(recorder) = recorder() {
+        recorder.message_received(side, len);
+    }
+}
+
+/// Reports a connection open until it is dropped.
+pub(crate) struct ConnectionRecord {
+    recorder: Option<Arc<dyn Recorder>>,
+    side: Side,
+}
+
+impl ConnectionRecord {
+    pub(crate) fn open(side: Side) -> Self {
+        let recorder = recorder();
+        if let Some(recorder) = &recorder {
+            recorder.connection_opened(side);
+        }
+        Self { recorder, side }
+    }
+}
+
+impl Drop for ConnectionRecord {
+    fn drop(&mut self) {
+        if let Some(recorder) = &self.recorder {
+            recorder.connection_closed(self.side);
+        }
+    }
+}
+
+/// Reports a call until it finishes, or is dropped as `CANCELLED`.
+pub(crate) struct CallRecord {
+    recorder: Arc<dyn Recorder>,
+    side: Side,
+    method: String,
+    streaming: bool,
+    start: Instant,
+    code: Code,
+}
+
+impl CallRecord {
+    /// Starts recording a call, returns `None` without a recorder.
+    pub(crate) fn start(side: Side, service: &str, method: &str, streaming: bool) -> Option<Self> {
+        let recorder = recorder()?;
+        let method = format!("/{service}/{method}");
+        recorder.call_started(side, &method, streaming);
+        Some(Self {
+            recorder,
+            side,
+            method,
+            streaming,
+            start: Instant::now(),
+            code: Code::CANCELLED,
+        })
+    }
+
+    pub(crate) fn finish(mut self, code: Code) {
+        self.code = code;
+    }
+}
+
+impl fmt::Debug for CallRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallRecord")
+            .field("side", &self.side)
+            .field("method", &self.method)
+            .field("streaming", &self.streaming)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for CallRecord {
+    fn drop(&mut self) {
+        self.recorder.call_finished(
+            self.side,
+            &self.method,
+            self.streaming,
+            self.code,
+            self.start.elapsed(),
+        );
+    }
+}
+
+/// The upper bounds of the buckets of the latencies, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // The count of each bucket, the last one is `+Inf`.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        let i = BUCKETS.partition_point(|le| *le < value);
+        self.counts[i] += 1;
+        self.sum += value;
+    }
+}
+
+type MethodKey = (Side, String);
+
+#[derive(Default)]
+struct Metrics {
+    requests: BTreeMap<(Side, String, String), u64>,
+    durations: BTreeMap<MethodKey, Histogram>,
+    in_flight: BTreeMap<MethodKey, i64>,
+    streams: BTreeMap<MethodKey, i64>,
+    sent_bytes: BTreeMap<Side, u64>,
+    received_bytes: BTreeMap<Side, u64>,
+    connections: BTreeMap<Side, i64>,
+}
+
+/// A [`Recorder`] keeping the metrics in memory, rendered in the Prometheus
+/// text format by [`Registry::render`].
+#[derive(Default)]
+pub struct Registry {
+    metrics: Mutex<Metrics>,
+}
+
+impl Recorder for Registry {
+    fn call_started(&self, side: Side, method: &str, streaming: bool) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let gauges = if streaming {
+            &mut metrics.streams
+        } else {
+            &mut metrics.in_flight
+        };
+        *gauges.entry((side, method.to_string())).or_default() += 1;
+    }
+
+    fn call_finished(
+        &self,
+        side: Side,
+        method: &str,
+        streaming: bool,
+        code: Code,
+        latency: Duration,
+    ) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let key = (side, method.to_string());
+        let gauges = if streaming {
+            &mut metrics.streams
+        } else {
+            &mut metrics.in_flight
+        };
+        *gauges.entry(key.clone()).or_default() -= 1;
+        let code = format!("{code:?}");
+        *metrics
+            .requests
+            .entry((side, method.to_string(), code))
+            .or_default() += 1;
+        metrics
+            .durations
+            .entry(key)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    fn message_sent(&self, side: Side, len: usize) {
+        let mut metrics = self.metrics.lock().unwrap();
+        *metrics.sent_bytes.entry(side).or_default() += len as u64;
+    }
+
+    fn message_received(&self, side: Side, len: usize) {
+        let mut metrics = self.metrics.lock().unwrap();
+        *metrics.received_bytes.entry(side).or_default() += len as u64;
+    }
+
+    fn connection_opened(&self, side: Side) {
+        *self
+            .metrics
+            .lock()
+            .unwrap()
+            .connections
+            .entry(side)
+            .or_default() += 1;
+    }
+
+    fn connection_closed(&self, side: Side) {
+        *self
+            .metrics
+            .lock()
+            .unwrap()
+            .connections
+            .entry(side)
+            .or_default() -= 1;
+    }
+}
+
+impl Registry {
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "ttrpc_requests_total",
+            "counter",
+            "Requests finished, by status code.",
+        );
+        for ((side, method, code), n) in &metrics.requests {
+            let labels = method_labels(*side, method);
+            writeln!(out, "ttrpc_requests_total{{{labels},code=\"{code}\"}} {n}").unwrap();
+        }
+
+        header(
+            &mut out,
+            "ttrpc_request_duration_seconds",
+            "histogram",
+            "Durations of the requests, of the streams for the streaming ones.",
+        );
+        for ((side, method), histogram) in &metrics.durations {
+            let labels = method_labels(*side, method);
+            let mut count = 0;
+            for (i, n) in histogram.counts.iter().enumerate() {
+                count += n;
+                let le = BUCKETS
+                    .get(i)
+                    .map_or("+Inf".to_string(), |le| le.to_string());
+                writeln!(
+                    out,
+                    "ttrpc_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {count}"
+                )
+                .unwrap();
+            }
+            let sum = histogram.sum;
+            writeln!(out, "ttrpc_request_duration_seconds_sum{{{labels}}} {sum}").unwrap();
+            writeln!(
+                out,
+                "ttrpc_request_duration_seconds_count{{{labels}}} {count}"
+            )
+            .unwrap();
+        }
+
+        for (name, help, gauges) in [
+            (
+                "ttrpc_requests_in_flight",
+                "Unary requests in flight.",
+                &metrics.in_flight,
+            ),
+            ("ttrpc_streams_active", "Streams open.", &metrics.streams),
+        ] {
+            header(&mut out, name, "gauge", help);
+            for ((side, method), n) in gauges {
+                let labels = method_labels(*side, method);
+                writeln!(out, "{name}{{{labels}}} {n}").unwrap();
+            }
+        }
+
+        for (name, help, counters) in [
+            (
+                "ttrpc_sent_bytes_total",
+                "Bytes of the messages sent.",
+                &metrics.sent_bytes,
+            ),
+            (
+                "ttrpc_received_bytes_total",
+                "Bytes of the messages received.",
+                &metrics.received_bytes,
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            for (side, n) in counters {
+                writeln!(out, "{name}{{side=\"{}\"}} {n}", side.as_str()).unwrap();
+            }
+        }
+
+        header(
+            &mut out,
+            "ttrpc_connections_active",
+            "gauge",
+            "Connections open.",
+        );
+        for (side, n) in &metrics.connections {
+            let side = side.as_str();
+            writeln!(out, "ttrpc_connections_active{{side=\"{side}\"}} {n}").unwrap();
+        }
+
+        out
+    }
+}
+
+/// The registry recording the metrics of the tests, shared since the
+/// recorder is global.
+#[cfg(test)]
+pub(crate) fn test_registry() -> Arc<Registry> {
+    static REGISTRY: std::sync::OnceLock<Arc<Registry>> = std::sync::OnceLock::new();
+    REGISTRY
+        .get_or_init(|| {
+            let registry = Arc::new(Registry::default());
+            set_recorder(registry.clone());
+            registry
+        })
+        .clone()
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+fn method_labels(side: Side, method: &str) -> String {
+    let method = method
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("side=\"{}\",method=\"{method}\"", side.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = Registry::default();
+        registry.connection_opened(Side::Server);
+        registry.call_started(Side::Server, "/test/Ping", false);
+        registry.call_started(Side::Server, "/test/Ping", false);
+        registry.call_finished(
+            Side::Server,
+            "/test/Ping",
+            false,
+            Code::NOT_FOUND,
+            Duration::from_millis(20),
+        );
+        registry.call_started(Side::Client, "/test/\"Watch\"", true);
+        registry.message_sent(Side::Server, 10);
+        registry.message_sent(Side::Server, 32);
+        registry.message_received(Side::Client, 7);
+
+        let text = registry.render();
+        for line in [
+            "# TYPE ttrpc_requests_total counter",
+            "ttrpc_requests_total{side=\"server\",method=\"/test/Ping\",code=\"NOT_FOUND\"} 1",
+            "ttrpc_request_duration_seconds_bucket{side=\"server\",method=\"/test/Ping\",le=\"0.01\"} 0",
+            "ttrpc_request_duration_seconds_bucket{side=\"server\",method=\"/test/Ping\",le=\"0.025\"} 1",
+            "ttrpc_request_duration_seconds_bucket{side=\"server\",method=\"/test/Ping\",le=\"+Inf\"} 1",
+            "ttrpc_request_duration_seconds_count{side=\"server\",method=\"/test/Ping\"} 1",
+            "ttrpc_requests_in_flight{side=\"server\",method=\"/test/Ping\"} 1",
+            "ttrpc_streams_active{side=\"client\",method=\"/test/\\\"Watch\\\"\"} 1",
+            "ttrpc_sent_bytes_total{side=\"server\"} 42",
+            "ttrpc_received_bytes_total{side=\"client\"} 7",
+            "ttrpc_connections_active{side=\"server\"} 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+        }
+    }
+}
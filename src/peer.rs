@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Information about the peer of a connection, captured when the server
+//! accepts it and exposed to the handlers through their `TtrpcContext`.
+
+use std::net::SocketAddr;
+
+/// The peer of a connection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PeerInfo {
+    /// A unix socket, with the credentials of the peer process. The pid is
+    /// not available on every platform.
+    Unix {
+        uid: u32,
+        gid: u32,
+        pid: Option<i32>,
+    },
+    /// A vsock socket.
+    Vsock { cid: u32, port: u32 },
+    /// A TCP socket.
+    Tcp(SocketAddr),
+    /// A TLS connection, with the DER encoded certificate chain presented by
+    /// the client, which is empty if the server does not require one.
+    Tls {
+        addr: SocketAddr,
+        certificates: Vec<Vec<u8>>,
+    },
+    /// A connection of the `memory://` transport, within the process.
+    InProcess,
+    /// The peer could not be identified, e.g. on a named pipe.
+    #[default]
+    Unknown,
+}
+
+#[cfg(unix)]
+impl PeerInfo {
+    /// Identifies the peer of the connected socket `fd`.
+    pub(crate) fn from_fd(fd: std::os::unix::io::RawFd) -> PeerInfo {
+        use nix::sys::socket::{getpeername, AddressFamily, SockaddrLike, SockaddrStorage};
+
+        let addr = match getpeername::<SockaddrStorage>(fd) {
+            Ok(addr) => addr,
+            Err(e) => {
+                debug!("failed to get the peer of fd {}: {:?}", fd, e);
+                return PeerInfo::Unknown;
+            }
+        };
+        match addr.family() {
+            Some(AddressFamily::Unix) => unix_credentials(fd).unwrap_or_else(|e| {
+                debug!("failed to get the peer credentials of fd {}: {:?}", fd, e);
+                PeerInfo::Unknown
+            }),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Some(AddressFamily::Vsock) => match addr.as_vsock_addr() {
+                Some(addr) => PeerInfo::Vsock {
+                    cid: addr.cid(),
+                    port: addr.port(),
+                },
+                None => PeerInfo::Unknown,
+            },
+            Some(AddressFamily::Inet) => match addr.as_sockaddr_in() {
+                Some(addr) => PeerInfo::Tcp(std::net::SocketAddrV4::from(*addr).into()),
+                None => PeerInfo::Unknown,
+            },
+            Some(AddressFamily::Inet6) => match addr.as_sockaddr_in6() {
+                Some(addr) => PeerInfo::Tcp(std::net::SocketAddrV6::from(*addr).into()),
+                None => PeerInfo::Unknown,
+            },
+            _ => PeerInfo::Unknown,
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn unix_credentials(fd: std::os::unix::io::RawFd) -> nix::Result<PeerInfo> {
+    use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+
+    let cred = getsockopt(fd, PeerCredentials)?;
+    Ok(PeerInfo::Unix {
+        uid: cred.uid(),
+        gid: cred.gid(),
+        pid: Some(cred.pid()),
+    })
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn unix_credentials(fd: std::os::unix::io::RawFd) -> nix::Result<PeerInfo> {
+    let (uid, gid) = nix::unistd::getpeereid(fd)?;
+    Ok(PeerInfo::Unix {
+        uid: uid.as_raw(),
+        gid: gid.as_raw(),
+        pid: None,
+    })
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_from_fd() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        assert_eq!(
+            PeerInfo::from_fd(a.as_raw_fd()),
+            PeerInfo::Unix {
+                uid: nix::unistd::getuid().as_raw(),
+                gid: nix::unistd::getgid().as_raw(),
+                pid: Some(std::process::id() as i32),
+            }
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        assert_eq!(
+            PeerInfo::from_fd(server.as_raw_fd()),
+            PeerInfo::Tcp(client.local_addr().unwrap())
+        );
+    }
+}
@@ -5,11 +5,13 @@
 //
 
 #[allow(soft_unstable, clippy::type_complexity, clippy::too_many_arguments)]
-mod compiled {
+pub(crate) mod compiled {
     include!(concat!(env!("OUT_DIR"), "/mod.rs"));
 }
 pub use compiled::ttrpc::*;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use byteorder::{BigEndian, ByteOrder};
 use protobuf::{CodedInputStream, CodedOutputStream};
 
@@ -27,11 +29,72 @@ pub const FLAG_REMOTE_CLOSED: u8 = 0x1;
 pub const FLAG_REMOTE_OPEN: u8 = 0x2;
 pub const FLAG_NO_DATA: u8 = 0x4;
 
-pub(crate) fn check_oversize(len: usize, return_rpc_error: bool) -> TtResult<()> {
-    if len > MESSAGE_LENGTH_MAX {
+/// The maximum sizes of the messages sent and received on a connection.
+///
+/// A message received over `max_recv` is discarded and answered with an
+/// `INVALID_ARGUMENT` status, a message over `max_send` is not sent. Both
+/// default to [`MESSAGE_LENGTH_MAX`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageSizeLimits {
+    pub max_send: usize,
+    pub max_recv: usize,
+}
+
+impl Default for MessageSizeLimits {
+    fn default() -> Self {
+        Self {
+            max_send: MESSAGE_LENGTH_MAX,
+            max_recv: MESSAGE_LENGTH_MAX,
+        }
+    }
+}
+
+/// The [`MessageSizeLimits`] of a connection, which can be changed while it
+/// is running.
+#[derive(Debug)]
+pub(crate) struct SharedSizeLimits {
+    max_send: AtomicUsize,
+    max_recv: AtomicUsize,
+}
+
+impl SharedSizeLimits {
+    pub(crate) fn new(limits: MessageSizeLimits) -> Self {
+        Self {
+            max_send: AtomicUsize::new(limits.max_send),
+            max_recv: AtomicUsize::new(limits.max_recv),
+        }
+    }
+
+    pub(crate) fn set(&self, limits: MessageSizeLimits) {
+        self.max_send.store(limits.max_send, Ordering::Relaxed);
+        self.max_recv.store(limits.max_recv, Ordering::Relaxed);
+    }
+
+    pub(crate) fn max_send(&self) -> usize {
+        self.max_send.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn max_recv(&self) -> usize {
+        self.max_recv.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for SharedSizeLimits {
+    fn default() -> Self {
+        Self::new(MessageSizeLimits::default())
+    }
+}
+
+/// Returns the `/service/method` path of a method.
+pub(crate) fn get_path(service: &str, method: &str) -> String {
+    format!("/{service}/{method}")
+}
+
+pub(crate) fn check_oversize(len: usize, max_len: usize, return_rpc_error: bool) -> TtResult<()> {
+    if len > max_len {
         let msg = format!(
             "message length {} exceed maximum message size of {}",
-            len, MESSAGE_LENGTH_MAX
+            len, max_len
         );
         let e = if return_rpc_error {
             get_rpc_status(Code::INVALID_ARGUMENT, msg)
@@ -227,13 +290,22 @@ impl GenMessage {
 
     /// Decodes a MessageHeader from reader.
     pub async fn read_from(
+        reader: impl tokio::io::AsyncReadExt + Unpin,
+    ) -> std::result::Result<Self, GenMessageError> {
+        Self::read_from_with_limit(reader, MESSAGE_LENGTH_MAX).await
+    }
+
+    /// Decodes a MessageHeader from reader, the body of a message longer than
+    /// `max_len` is discarded and a [`GenMessageError::ReturnError`] returned.
+    pub async fn read_from_with_limit(
         mut reader: impl tokio::io::AsyncReadExt + Unpin,
+        max_len: usize,
     ) -> std::result::Result<Self, GenMessageError> {
         let header = MessageHeader::read_from(&mut reader)
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
 
-        if let Err(e) = check_oversize(header.length as usize, true) {
+        if let Err(e) = check_oversize(header.length as usize, max_len, true) {
             discard_message_body(reader, &header).await?;
             return Err(GenMessageError::ReturnError(header, e));
         }
@@ -251,7 +323,7 @@ impl GenMessage {
     }
 
     pub fn check(&self) -> TtResult<()> {
-        check_oversize(self.header.length as usize, true)
+        check_oversize(self.header.length as usize, MESSAGE_LENGTH_MAX, true)
     }
 }
 
@@ -323,7 +395,15 @@ where
 
 impl<C: Codec> Message<C> {
     pub fn new_request(stream_id: u32, message: C) -> TtResult<Self> {
-        check_oversize(message.size() as usize, false)?;
+        Self::new_request_with_limit(stream_id, message, MESSAGE_LENGTH_MAX)
+    }
+
+    pub(crate) fn new_request_with_limit(
+        stream_id: u32,
+        message: C,
+        max_len: usize,
+    ) -> TtResult<Self> {
+        check_oversize(message.size() as usize, max_len, false)?;
 
         Ok(Self {
             header: MessageHeader::new_request(stream_id, message.size()),
@@ -364,7 +444,7 @@ where
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
 
-        if check_oversize(header.length as usize, true).is_err() {
+        if check_oversize(header.length as usize, MESSAGE_LENGTH_MAX, true).is_err() {
             discard_message_body(reader, &header).await?;
             return Ok(Self {
                 header,
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Reflection service and client (async).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::{
+    FileDescriptorsRequest, FileDescriptorsResponse, ListServicesRequest, ListServicesResponse,
+    Registry, SERVICE_NAME,
+};
+use crate::context::{self, Context};
+use crate::error::{get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+use crate::r#async::{Client, MethodHandler, Service, TtrpcContext};
+
+struct ListServicesMethod {
+    registry: Arc<Registry>,
+}
+
+#[async_trait]
+impl MethodHandler for ListServicesMethod {
+    async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+        response(self.registry.list_services())
+    }
+}
+
+struct FileDescriptorsMethod {
+    registry: Arc<Registry>,
+}
+
+#[async_trait]
+impl MethodHandler for FileDescriptorsMethod {
+    async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+        let req =
+            FileDescriptorsRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        response(self.registry.file_descriptors(&req)?)
+    }
+}
+
+fn response(rep: impl Codec<E = protobuf::Error>) -> Result<Response> {
+    Ok(Response {
+        status: Some(get_status(Code::OK, "")).into(),
+        payload: rep.encode().map_err(err_to_others_err!(e, ""))?,
+        ..Default::default()
+    })
+}
+
+/// Creates the reflection service, which is registered by the server itself.
+pub(crate) fn create_reflection(registry: Registry) -> HashMap<String, Service> {
+    let registry = Arc::new(registry);
+    let mut methods = HashMap::new();
+    methods.insert(
+        "ListServices".to_string(),
+        Box::new(ListServicesMethod {
+            registry: registry.clone(),
+        }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods.insert(
+        "FileDescriptors".to_string(),
+        Box::new(FileDescriptorsMethod { registry }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+
+    let mut ret = HashMap::new();
+    ret.insert(
+        SERVICE_NAME.to_string(),
+        Service {
+            methods,
+            streams: HashMap::new(),
+        },
+    );
+    ret
+}
+
+/// The client of the reflection service (async).
+#[derive(Clone)]
+pub struct ReflectionClient {
+    client: Client,
+}
+
+impl ReflectionClient {
+    pub fn new(client: Client) -> Self {
+        ReflectionClient { client }
+    }
+
+    pub async fn list_services(&self, ctx: Context) -> Result<ListServicesResponse> {
+        let req = new_request(ctx, "ListServices", &ListServicesRequest::new())?;
+        let res = self.client.request(req).await?;
+        ListServicesResponse::decode(res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    pub async fn file_descriptors(
+        &self,
+        ctx: Context,
+        req: &FileDescriptorsRequest,
+    ) -> Result<FileDescriptorsResponse> {
+        let res = self
+            .client
+            .request(new_request(ctx, "FileDescriptors", req)?)
+            .await?;
+        FileDescriptorsResponse::decode(res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+}
+
+fn new_request(
+    ctx: Context,
+    method: &str,
+    req: &impl Codec<E = protobuf::Error>,
+) -> Result<Request> {
+    let payload = req.encode().map_err(err_to_others_err!(e, ""))?;
+    context::new_request(ctx, SERVICE_NAME, method, payload)
+}
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{self, HealthReporter};
+    use crate::r#async::Server;
+
+    #[tokio::test]
+    async fn test_reflection_service() {
+        let addr = r"unix://@/tmp/ttrpc-reflection-unit-test";
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(health::r#async::create_health(HealthReporter::new()))
+            .register_reflection(vec![]);
+        server.start().await.unwrap();
+
+        let client = ReflectionClient::new(Client::connect(addr).await.unwrap());
+        let list_services = || async {
+            let res = client.list_services(Context::default()).await.unwrap();
+            let services: Vec<_> = res
+                .services
+                .into_iter()
+                .map(|s| {
+                    let methods: Vec<_> = s
+                        .methods
+                        .into_iter()
+                        .map(|m| (m.name, m.streaming))
+                        .collect();
+                    (s.name, methods)
+                })
+                .collect();
+            services
+        };
+        let reflection = (
+            SERVICE_NAME.to_string(),
+            vec![
+                ("FileDescriptors".to_string(), false),
+                ("ListServices".to_string(), false),
+            ],
+        );
+        let health = (
+            health::SERVICE_NAME.to_string(),
+            vec![("Check".to_string(), false), ("Watch".to_string(), true)],
+        );
+        assert_eq!(
+            list_services().await,
+            vec![health.clone(), reflection.clone()]
+        );
+
+        // The services removed and added while the server runs are listed.
+        assert!(server.remove_service(health::SERVICE_NAME));
+        assert_eq!(list_services().await, vec![reflection.clone()]);
+        for (name, service) in health::r#async::create_health(HealthReporter::new()) {
+            server.add_service(name, service);
+        }
+        assert_eq!(list_services().await, vec![health, reflection]);
+
+        let req = FileDescriptorsRequest {
+            service: health::SERVICE_NAME.to_string(),
+            ..Default::default()
+        };
+        let res = client.file_descriptors(Context::default(), &req).await;
+        assert!(matches!(res, Err(Error::RpcStatus(s)) if s.code() == Code::NOT_FOUND));
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Reflection service listing the services exposed by a server.
+//!
+//! It is enabled by `Server::register_reflection`, which lists the services
+//! of the server, also the ones added or removed while it runs. The file
+//! descriptors passed to it, e.g. the `file_descriptor_proto()` of the
+//! modules generated by protobuf, are returned to the clients which need to
+//! decode the messages.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use protobuf::descriptor::FileDescriptorProto;
+use protobuf::Message;
+
+use crate::error::{get_rpc_status, Error, Result};
+use crate::proto::Code;
+
+#[doc(inline)]
+pub use crate::proto::compiled::reflection::{
+    FileDescriptorsRequest, FileDescriptorsResponse, ListServicesRequest, ListServicesResponse,
+    MethodInfo, ServiceInfo,
+};
+
+cfg_sync! {
+    pub mod sync;
+}
+
+cfg_async! {
+    pub mod asynchronous;
+    #[doc(hidden)]
+    pub use asynchronous as r#async;
+}
+
+/// The name of the reflection service.
+pub const SERVICE_NAME: &str = "ttrpc.reflection.v1.Reflection";
+
+/// The `(service, method, streaming)` of the handlers of a server.
+type Methods = Box<dyn Fn() -> Vec<(String, String, bool)> + Send + Sync>;
+
+/// The services and the files known by the reflection service.
+pub(crate) struct Registry {
+    methods: Methods,
+    files: Vec<FileDescriptorProto>,
+}
+
+impl Registry {
+    /// Creates the registry listing the services from the handlers returned
+    /// by `methods`, which is called on each request so the services added
+    /// or removed while the server runs are listed.
+    pub(crate) fn new(
+        methods: impl Fn() -> Vec<(String, String, bool)> + Send + Sync + 'static,
+        files: Vec<FileDescriptorProto>,
+    ) -> Registry {
+        Registry {
+            methods: Box::new(methods),
+            files,
+        }
+    }
+
+    pub(crate) fn list_services(&self) -> ListServicesResponse {
+        let mut services: BTreeMap<String, Vec<MethodInfo>> = BTreeMap::new();
+        for (service, name, streaming) in (self.methods)() {
+            services.entry(service).or_default().push(MethodInfo {
+                name,
+                streaming,
+                ..Default::default()
+            });
+        }
+
+        let services = services
+            .into_iter()
+            .map(|(name, mut methods)| {
+                methods.sort_by(|a, b| a.name.cmp(&b.name));
+                ServiceInfo {
+                    name,
+                    methods,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        ListServicesResponse {
+            services,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the files defining the service and their dependencies, fails
+    /// with `NOT_FOUND` if none of the files defines the service.
+    pub(crate) fn file_descriptors(
+        &self,
+        req: &FileDescriptorsRequest,
+    ) -> Result<FileDescriptorsResponse> {
+        let mut pending: VecDeque<&FileDescriptorProto> = self
+            .files
+            .iter()
+            .filter(|file| req.service.is_empty() || defines(file, &req.service))
+            .collect();
+        if pending.is_empty() {
+            return Err(get_rpc_status(
+                Code::NOT_FOUND,
+                format!("no file descriptor of service {}", req.service),
+            ));
+        }
+
+        let mut res = FileDescriptorsResponse::new();
+        let mut seen = HashSet::new();
+        while let Some(file) = pending.pop_front() {
+            if !seen.insert(file.name()) {
+                continue;
+            }
+            res.file_descriptor_proto
+                .push(file.write_to_bytes().map_err(err_to_others_err!(e, ""))?);
+            for dep in &file.dependency {
+                pending.extend(self.files.iter().filter(|f| f.name() == dep));
+            }
+        }
+        Ok(res)
+    }
+}
+
+fn defines(file: &FileDescriptorProto, service: &str) -> bool {
+    file.service.iter().any(|s| {
+        if file.package().is_empty() {
+            s.name() == service
+        } else {
+            format!("{}.{}", file.package(), s.name()) == service
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protobuf::descriptor::ServiceDescriptorProto;
+
+    fn file(name: &str, package: &str, services: &[&str], deps: &[&str]) -> FileDescriptorProto {
+        let mut file = FileDescriptorProto::new();
+        file.set_name(name.to_string());
+        file.set_package(package.to_string());
+        for service in services {
+            let mut s = ServiceDescriptorProto::new();
+            s.set_name(service.to_string());
+            file.service.push(s);
+        }
+        file.dependency = deps.iter().map(|d| d.to_string()).collect();
+        file
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = Registry::new(
+            || {
+                vec![
+                    ("test.Echo".to_string(), "Stream".to_string(), true),
+                    ("test.Echo".to_string(), "Echo".to_string(), false),
+                    (SERVICE_NAME.to_string(), "ListServices".to_string(), false),
+                ]
+            },
+            vec![
+                file("echo.proto", "test", &["Echo"], &["empty.proto"]),
+                file("empty.proto", "test", &[], &[]),
+                file("other.proto", "other", &["Other"], &[]),
+            ],
+        );
+
+        let services = registry.list_services().services;
+        let names: Vec<_> = services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["test.Echo", SERVICE_NAME]);
+        let methods: Vec<_> = services[0]
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.streaming))
+            .collect();
+        assert_eq!(methods, vec![("Echo", false), ("Stream", true)]);
+
+        let req = |service: &str| FileDescriptorsRequest {
+            service: service.to_string(),
+            ..Default::default()
+        };
+        let res = registry.file_descriptors(&req("test.Echo")).unwrap();
+        let names: Vec<_> = res
+            .file_descriptor_proto
+            .iter()
+            .map(|buf| {
+                FileDescriptorProto::parse_from_bytes(buf)
+                    .unwrap()
+                    .name()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, vec!["echo.proto", "empty.proto"]);
+
+        let res = registry.file_descriptors(&req("")).unwrap();
+        assert_eq!(res.file_descriptor_proto.len(), 3);
+        assert!(registry.file_descriptors(&req("test.Unknown")).is_err());
+    }
+}
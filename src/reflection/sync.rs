@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Reflection service and client (sync).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{
+    FileDescriptorsRequest, FileDescriptorsResponse, ListServicesRequest, ListServicesResponse,
+    Registry, SERVICE_NAME,
+};
+use crate::context::{self, Context};
+use crate::error::{get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+use crate::sync::{response_to_channel, Client, MethodHandler, TtrpcContext};
+
+struct ListServicesMethod {
+    registry: Arc<Registry>,
+}
+
+impl MethodHandler for ListServicesMethod {
+    fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<()> {
+        let res = response(Ok(self.registry.list_services()))?;
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+}
+
+struct FileDescriptorsMethod {
+    registry: Arc<Registry>,
+}
+
+impl MethodHandler for FileDescriptorsMethod {
+    fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()> {
+        let req =
+            FileDescriptorsRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        let res = response(self.registry.file_descriptors(&req))?;
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+}
+
+fn response(rep: Result<impl Codec<E = protobuf::Error>>) -> Result<Response> {
+    match rep {
+        Ok(rep) => Ok(Response {
+            status: Some(get_status(Code::OK, "")).into(),
+            payload: rep.encode().map_err(err_to_others_err!(e, ""))?,
+            ..Default::default()
+        }),
+        Err(e) => Ok(e.into()),
+    }
+}
+
+/// Creates the reflection service, which is registered by the server itself.
+pub(crate) fn create_reflection(
+    registry: Registry,
+) -> HashMap<String, Box<dyn MethodHandler + Send + Sync>> {
+    let registry = Arc::new(registry);
+    let mut methods = HashMap::new();
+    methods.insert(
+        format!("/{SERVICE_NAME}/ListServices"),
+        Box::new(ListServicesMethod {
+            registry: registry.clone(),
+        }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods.insert(
+        format!("/{SERVICE_NAME}/FileDescriptors"),
+        Box::new(FileDescriptorsMethod { registry }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods
+}
+
+/// The client of the reflection service (sync).
+#[derive(Clone)]
+pub struct ReflectionClient {
+    client: Client,
+}
+
+impl ReflectionClient {
+    pub fn new(client: Client) -> Self {
+        ReflectionClient { client }
+    }
+
+    pub fn list_services(&self, ctx: Context) -> Result<ListServicesResponse> {
+        let req = new_request(ctx, "ListServices", &ListServicesRequest::new())?;
+        let res = self.client.request(req)?;
+        ListServicesResponse::decode(res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    pub fn file_descriptors(
+        &self,
+        ctx: Context,
+        req: &FileDescriptorsRequest,
+    ) -> Result<FileDescriptorsResponse> {
+        let res = self
+            .client
+            .request(new_request(ctx, "FileDescriptors", req)?)?;
+        FileDescriptorsResponse::decode(res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+}
+
+fn new_request(
+    ctx: Context,
+    method: &str,
+    req: &impl Codec<E = protobuf::Error>,
+) -> Result<Request> {
+    let payload = req.encode().map_err(err_to_others_err!(e, ""))?;
+    context::new_request(ctx, SERVICE_NAME, method, payload)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::health::{self, HealthReporter};
+    use crate::sync::Server;
+
+    #[test]
+    fn test_reflection_service() {
+        let addr = "memory://sync-reflection-unit-test";
+        let reporter = HealthReporter::new();
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(health::sync::create_health(reporter.clone()))
+            .register_stream_service(health::sync::create_health_streams(reporter.clone()))
+            .register_reflection(vec![]);
+        server.start().unwrap();
+
+        let client = ReflectionClient::new(Client::connect(addr).unwrap());
+        let list_services = || {
+            let res = client.list_services(Context::default()).unwrap();
+            let services: Vec<_> = res
+                .services
+                .into_iter()
+                .map(|s| {
+                    let methods: Vec<_> = s
+                        .methods
+                        .into_iter()
+                        .map(|m| (m.name, m.streaming))
+                        .collect();
+                    (s.name, methods)
+                })
+                .collect();
+            services
+        };
+        let reflection = (
+            SERVICE_NAME.to_string(),
+            vec![
+                ("FileDescriptors".to_string(), false),
+                ("ListServices".to_string(), false),
+            ],
+        );
+        let health = (
+            health::SERVICE_NAME.to_string(),
+            vec![("Check".to_string(), false), ("Watch".to_string(), true)],
+        );
+        assert_eq!(list_services(), vec![health.clone(), reflection.clone()]);
+
+        // The services removed and added while the server runs are listed.
+        assert!(server.remove_service(health::SERVICE_NAME));
+        assert_eq!(list_services(), vec![reflection.clone()]);
+        server.add_service(health::sync::create_health(reporter.clone()));
+        server.add_stream_service(health::sync::create_health_streams(reporter));
+        assert_eq!(list_services(), vec![health, reflection]);
+
+        server.shutdown();
+    }
+}
@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Retry policy of the unary requests sent by clients.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::context;
+use crate::error::Error;
+use crate::proto::Code;
+
+/// Declares how a client retries the unary requests which fail.
+///
+/// Only set it for idempotent methods, a request which failed may have been
+/// handled by the server. Transport errors are retried as `UNAVAILABLE`. The
+/// retries never exceed the timeout of the request.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    pub retryable_codes: Vec<Code>,
+    /// The policies overriding this one, keyed by the `/service/method` path.
+    pub methods: HashMap<String, RetryPolicy>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            retryable_codes: vec![Code::UNAVAILABLE],
+            methods: HashMap::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Overrides the policy of the method at `path`, e.g. a policy with
+    /// `max_attempts` set to 1 disables retrying a non-idempotent method.
+    pub fn with_method(mut self, path: impl Into<String>, policy: RetryPolicy) -> Self {
+        self.methods.insert(path.into(), policy);
+        self
+    }
+
+    /// Returns the policy of the method at `path`.
+    pub(crate) fn get(&self, path: &str) -> &RetryPolicy {
+        self.methods.get(path).unwrap_or(self)
+    }
+
+    fn is_retryable(&self, e: &Error) -> bool {
+        let code = match e {
+            Error::RpcStatus(s) => s.code(),
+            Error::Socket(_) | Error::LocalClosed | Error::RemoteClosed => Code::UNAVAILABLE,
+            _ => return false,
+        };
+        self.retryable_codes.contains(&code)
+    }
+}
+
+/// Tracks the attempts of a request following a [`RetryPolicy`].
+pub(crate) struct Retry<'a> {
+    policy: &'a RetryPolicy,
+    attempts: u32,
+    backoff: Duration,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Retry<'a> {
+    pub(crate) fn new(policy: &'a RetryPolicy, timeout_nano: i64) -> Self {
+        Retry {
+            policy,
+            attempts: 0,
+            backoff: policy.initial_backoff,
+            deadline: context::deadline(timeout_nano),
+        }
+    }
+
+    /// Returns the delay before retrying the request which failed with `e`,
+    /// or `None` if it should not be retried.
+    pub(crate) fn backoff(&mut self, e: &Error) -> Option<Duration> {
+        self.attempts += 1;
+        if self.attempts >= self.policy.max_attempts || !self.policy.is_retryable(e) {
+            return None;
+        }
+
+        let delay = self.backoff;
+        if matches!(context::remaining(self.deadline), Some(left) if left <= delay) {
+            return None;
+        }
+        // A multiplier which is negative or NaN, or a backoff which overflows,
+        // backs off for `max_backoff`.
+        let backoff = delay.as_secs_f64() * self.policy.multiplier;
+        self.backoff = Duration::try_from_secs_f64(backoff)
+            .unwrap_or(self.policy.max_backoff)
+            .min(self.policy.max_backoff);
+        Some(delay)
+    }
+
+    /// Returns the `timeout_nano` of the next attempt, i.e. the time left.
+    pub(crate) fn timeout_nano(&self) -> i64 {
+        context::remaining(self.deadline).map_or(0, |left| left.as_nanos().max(1) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::get_rpc_status;
+
+    #[test]
+    fn test_retry() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(3),
+            max_attempts: 4,
+            ..Default::default()
+        }
+        .with_method(
+            "/test/Once",
+            RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            },
+        );
+
+        let unavailable = get_rpc_status(Code::UNAVAILABLE, "");
+        let mut retry = Retry::new(policy.get("/test/Echo"), 0);
+        assert_eq!(retry.backoff(&unavailable), Some(Duration::from_millis(1)));
+        assert_eq!(
+            retry.backoff(&Error::RemoteClosed),
+            Some(Duration::from_millis(2))
+        );
+        assert_eq!(retry.backoff(&unavailable), Some(Duration::from_millis(3)));
+        assert_eq!(retry.backoff(&unavailable), None);
+        assert_eq!(retry.timeout_nano(), 0);
+
+        let mut retry = Retry::new(policy.get("/test/Echo"), 0);
+        let not_found = get_rpc_status(Code::NOT_FOUND, "");
+        assert_eq!(retry.backoff(&not_found), None);
+
+        let mut retry = Retry::new(policy.get("/test/Once"), 0);
+        assert_eq!(retry.backoff(&unavailable), None);
+
+        // Not enough time left to retry.
+        let mut retry = Retry::new(policy.get("/test/Echo"), 1);
+        assert_eq!(retry.backoff(&unavailable), None);
+
+        for multiplier in [-1.0, f64::NAN, f64::INFINITY, f64::MAX] {
+            let policy = RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(3),
+                multiplier,
+                ..Default::default()
+            };
+            let mut retry = Retry::new(&policy, 0);
+            assert_eq!(retry.backoff(&unavailable), Some(Duration::from_millis(1)));
+            assert_eq!(retry.backoff(&unavailable), Some(Duration::from_millis(3)));
+        }
+    }
+}
@@ -93,12 +93,17 @@ fn read_message_header(conn: &PipeConnection) -> Result<MessageHeader> {
     Ok(mh)
 }
 
-pub fn read_message(conn: &PipeConnection) -> Result<(MessageHeader, Result<Vec<u8>>)> {
+/// Reads a message, the body of a message longer than `max_len` is discarded
+/// and an error returned in its place.
+pub fn read_message(
+    conn: &PipeConnection,
+    max_len: usize,
+) -> Result<(MessageHeader, Result<Vec<u8>>)> {
     let mh = read_message_header(conn)?;
     trace!("Got Message header {:?}", mh);
 
     let mh_len = mh.length as usize;
-    if let Err(e) = check_oversize(mh_len, true) {
+    if let Err(e) = check_oversize(mh_len, max_len, true) {
         discard_count(conn, mh_len)?;
         return Ok((mh, Err(e)));
     }
@@ -19,33 +19,50 @@ use std::os::unix::io::RawFd;
 
 use protobuf::Message;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use crate::error::{Error, Result};
+use crate::error::{get_rpc_status, Error, IntoStatus, Result};
+use crate::metrics::{self, CallRecord, ConnectionRecord, Side};
 use crate::proto::{
-    check_oversize, Code, Codec, MessageHeader, Request, Response, MESSAGE_TYPE_RESPONSE,
+    check_oversize, get_path, Code, Codec, GenMessage, MessageHeader, MessageSizeLimits, Request,
+    Response, SharedSizeLimits, FLAG_NO_DATA, FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN,
+    MESSAGE_HEADER_LENGTH, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
 };
+use crate::retry::{Retry, RetryPolicy};
 use crate::sync::channel::{read_message, write_message};
+use crate::sync::interceptor::{
+    ClientInterceptor, ClientInterceptors, ClientNext, ClientStreamNext,
+};
+use crate::sync::stream::{
+    Kind, MessageSender, ResultReceiver, ResultSender, StreamInner, StreamMap,
+};
 use crate::sync::sys::ClientConnection;
 
 #[cfg(windows)]
 use super::sys::PipeConnection;
 
-type Sender = mpsc::Sender<(Vec<u8>, mpsc::SyncSender<Result<Vec<u8>>>)>;
-type Receiver = mpsc::Receiver<(Vec<u8>, mpsc::SyncSender<Result<Vec<u8>>>)>;
-type ReciverMap = Arc<Mutex<HashMap<u32, mpsc::SyncSender<Result<Vec<u8>>>>>>;
+type Receiver = mpsc::Receiver<(MessageHeader, Vec<u8>)>;
 
 /// A ttrpc Client (sync).
 #[derive(Clone)]
 pub struct Client {
     _connection: Arc<ClientConnection>,
-    sender_tx: Sender,
+    sender_tx: MessageSender,
+    next_stream_id: Arc<AtomicU32>,
+    streams: StreamMap,
+    interceptors: ClientInterceptors,
+    retry_policy: Option<Arc<RetryPolicy>>,
+    size_limits: Arc<SharedSizeLimits>,
 }
 
 impl Client {
+    /// Connects to `sockaddr`, an address of one of the built-in schemes: the
+    /// transports registered with `r#async::transport::register_transport`
+    /// are used by the async client only.
     pub fn connect(sockaddr: &str) -> Result<Client> {
         let conn = ClientConnection::client_connect(sockaddr)?;
 
@@ -64,36 +81,32 @@ impl Client {
     fn new_client(pipe_client: ClientConnection) -> Result<Client> {
         let client = Arc::new(pipe_client);
         let weak_client = Arc::downgrade(&client);
-        let (sender_tx, rx): (Sender, Receiver) = mpsc::channel();
-        let recver_map_orig = Arc::new(Mutex::new(HashMap::new()));
+        let (sender_tx, rx): (MessageSender, Receiver) = mpsc::channel();
+        let recver_map_orig: StreamMap = Arc::new(Mutex::new(HashMap::new()));
 
+        let streams = recver_map_orig.clone();
         let receiver_map = recver_map_orig.clone();
         let connection = Arc::new(client.get_pipe_connection()?);
         let sender_client = connection.clone();
+        let size_limits = Arc::new(SharedSizeLimits::default());
+        let receiver_limits = size_limits.clone();
 
         //Sender
         thread::spawn(move || {
-            let mut stream_id: u32 = 1;
-            for (buf, recver_tx) in rx.iter() {
-                let current_stream_id = stream_id;
-                stream_id += 2;
-                //Put current_stream_id and recver_tx to recver_map
-                {
-                    let mut map = receiver_map.lock().unwrap();
-                    map.insert(current_stream_id, recver_tx.clone());
-                }
-                let mut mh = MessageHeader::new_request(0, buf.len() as u32);
-                mh.set_stream_id(current_stream_id);
-
-                if let Err(e) = write_message(&sender_client, mh, buf) {
-                    //Remove current_stream_id and recver_tx to recver_map
-                    {
-                        let mut map = receiver_map.lock().unwrap();
-                        map.remove(&current_stream_id);
+            for (mh, buf) in rx.iter() {
+                let stream_id = mh.stream_id;
+                let len = MESSAGE_HEADER_LENGTH + buf.len();
+                match write_message(&sender_client, mh, buf) {
+                    Ok(()) => metrics::message_sent(Side::Client, len),
+                    Err(e) => {
+                        //Remove current_stream_id and recver_tx to recver_map
+                        let recver_tx = receiver_map.lock().unwrap().remove(&stream_id);
+                        if let Some(recver_tx) = recver_tx {
+                            recver_tx
+                                .send(Err(e))
+                                .unwrap_or_else(|_e| error!("The request has returned"));
+                        }
                     }
-                    recver_tx
-                        .send(Err(e))
-                        .unwrap_or_else(|_e| error!("The request has returned"));
                 }
             }
             trace!("Sender quit");
@@ -105,7 +118,10 @@ impl Client {
         //ClientConnection's drop will be not call until the thread finished. It means if all the external references are finished,
         //this thread should be release.
         let receiver_client = weak_client.clone();
+        // The connection is open as long as its receiver runs.
+        let record = ConnectionRecord::open(Side::Client);
         thread::spawn(move || {
+            let _record = record;
             loop {
                 //The count of ClientConnection's Arc will be add one , and back to original value when this code ends. 
                 if let Some(receiver_client) = receiver_client.upgrade(){
@@ -123,8 +139,10 @@ impl Client {
                     break;
                 }
 
-                match read_message(&receiver_connection) {
+                match read_message(&receiver_connection, receiver_limits.max_recv()) {
                     Ok((mh, buf)) => {
+                        let len = MESSAGE_HEADER_LENGTH + mh.length as usize;
+                        metrics::message_received(Side::Client, len);
                         trans_resp(recver_map_orig.clone(), mh, buf);
                     }
                     Err(x) => match x {
@@ -155,35 +173,107 @@ impl Client {
         Ok(Client {
             _connection: client,
             sender_tx,
+            next_stream_id: Arc::new(AtomicU32::new(1)),
+            streams,
+            interceptors: Arc::new(Vec::new()),
+            retry_policy: None,
+            size_limits,
         })
     }
-    pub fn request(&self, req: Request) -> Result<Response> {
-        check_oversize(req.compute_size() as usize, false)?;
+
+    /// Appends an interceptor to the chain wrapping every request and stream.
+    ///
+    /// Interceptors run in the order they are added. The chain is owned by this
+    /// [`Client`] and the clones created afterwards.
+    pub fn add_interceptor(mut self, interceptor: impl ClientInterceptor + 'static) -> Client {
+        Arc::make_mut(&mut self.interceptors).push(Arc::new(interceptor));
+        self
+    }
+
+    /// Sets the policy retrying the unary requests which fail.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Client {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Sets the maximum sizes of the messages sent and received.
+    ///
+    /// The limits apply to the connection, they are shared by all the clones
+    /// of this [`Client`].
+    pub fn with_message_size_limits(self, limits: MessageSizeLimits) -> Client {
+        self.size_limits.set(limits);
+        self
+    }
+
+    pub fn request(&self, mut req: Request) -> Result<Response> {
+        let Some(policy) = self.retry_policy.as_deref() else {
+            return ClientNext::new(&self.interceptors, self).run(req);
+        };
+
+        let path = get_path(&req.service, &req.method);
+        let mut retry = Retry::new(policy.get(&path), req.timeout_nano);
+        loop {
+            let e = match ClientNext::new(&self.interceptors, self).run(req.clone()) {
+                Err(e) => e,
+                res => return res,
+            };
+            let Some(delay) = retry.backoff(&e) else {
+                return Err(e);
+            };
+            debug!("Retry request {} in {:?} on error {:?}", path, delay, e);
+            thread::sleep(delay);
+            req.timeout_nano = retry.timeout_nano();
+        }
+    }
+
+    pub(crate) fn do_request(&self, req: Request) -> Result<Response> {
+        let record = CallRecord::start(Side::Client, &req.service, &req.method, false);
+        let res = self.send_request(req);
+        if let Some(record) = record {
+            record.finish(res.as_ref().map_or_else(IntoStatus::code, |_| Code::OK));
+        }
+        res
+    }
+
+    fn send_request(&self, req: Request) -> Result<Response> {
+        check_oversize(
+            req.compute_size() as usize,
+            self.size_limits.max_send(),
+            false,
+        )?;
 
         let buf = req.encode().map_err(err_to_others_err!(e, ""))?;
         // Notice: pure client problem can't be rpc error
 
-        let (tx, rx) = mpsc::sync_channel(0);
+        let stream_id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
+        let (tx, rx): (ResultSender, ResultReceiver) = mpsc::channel();
+        self.streams.lock().unwrap().insert(stream_id, tx);
 
-        self.sender_tx
-            .send((buf, tx))
-            .map_err(err_to_others_err!(e, "Send packet to sender error "))?;
+        let mh = MessageHeader::new_request(stream_id, buf.len() as u32);
+        if let Err(e) = self.sender_tx.send((mh, buf)) {
+            self.streams.lock().unwrap().remove(&stream_id);
+            return Err(Error::Others(format!("Send packet to sender error {e:?}")));
+        }
 
         let result = if req.timeout_nano == 0 {
             rx.recv().map_err(err_to_others_err!(
                 e,
                 "Receive packet from Receiver error: "
-            ))?
+            ))
         } else {
             rx.recv_timeout(Duration::from_nanos(req.timeout_nano as u64))
                 .map_err(err_to_others_err!(
                     e,
                     "Receive packet from Receiver timeout: "
-                ))?
+                ))
         };
+        if result.is_err() {
+            self.streams.lock().unwrap().remove(&stream_id);
+        }
 
-        let buf = result?;
-        let res = Response::decode(buf).map_err(err_to_others_err!(e, "Unpack response error "))?;
+        let msg = result??;
+        let res = Response::decode(msg.payload)
+            .map_err(err_to_others_err!(e, "Unpack response error "))?;
 
         let status = res.status();
         if status.code() != Code::OK {
@@ -192,6 +282,91 @@ impl Client {
 
         Ok(res)
     }
+
+    /// Creates a StreamInner instance.
+    pub fn new_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        ClientStreamNext::new(
+            &self.interceptors,
+            self,
+            streaming_client,
+            streaming_server,
+        )
+        .run(req)
+    }
+
+    pub(crate) fn do_new_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        let record = CallRecord::start(Side::Client, &req.service, &req.method, true);
+        match self.open_stream(req, streaming_client, streaming_server) {
+            Ok(stream) => Ok(stream.with_call(record)),
+            Err(e) => {
+                if let Some(record) = record {
+                    record.finish(e.code());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn open_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        let stream_id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
+        let is_req_payload_empty = req.payload.is_empty();
+
+        check_oversize(
+            req.compute_size() as usize,
+            self.size_limits.max_send(),
+            false,
+        )?;
+        let buf = req.encode().map_err(err_to_others_err!(e, ""))?;
+
+        let mut mh = MessageHeader::new_request(stream_id, buf.len() as u32);
+        if streaming_client {
+            if !is_req_payload_empty {
+                return Err(get_rpc_status(
+                    Code::INVALID_ARGUMENT,
+                    "Creating a ClientStream and sending payload at the same time is not allowed",
+                ));
+            }
+            mh.add_flags(FLAG_REMOTE_OPEN | FLAG_NO_DATA);
+        } else {
+            mh.add_flags(FLAG_REMOTE_CLOSED);
+        }
+
+        let (tx, rx): (ResultSender, ResultReceiver) = mpsc::channel();
+        self.streams.lock().unwrap().insert(stream_id, tx);
+
+        let inner = StreamInner::new(
+            stream_id,
+            self.sender_tx.clone(),
+            rx,
+            streaming_client,
+            streaming_server,
+            Kind::Client,
+            self.streams.clone(),
+            Some(self._connection.clone()),
+        )
+        .with_max_send(self.size_limits.max_send());
+
+        self.sender_tx
+            .send((mh, buf))
+            .map_err(|e| Error::Others(format!("Send packet to sender error {e:?}")))?;
+
+        Ok(inner)
+    }
 }
 
 impl Drop for ClientConnection {
@@ -214,16 +389,25 @@ impl Drop for PipeConnection {
 }
 
 /// Transfer the response
-fn trans_resp(recver_map_orig: ReciverMap, mh: MessageHeader, buf: Result<Vec<u8>>) {
-    let mut map = recver_map_orig.lock().unwrap();
-    let recver_tx = match map.get(&mh.stream_id) {
+fn trans_resp(recver_map_orig: StreamMap, mh: MessageHeader, buf: Result<Vec<u8>>) {
+    let recver_tx = {
+        let mut map = recver_map_orig.lock().unwrap();
+        match mh.type_ {
+            MESSAGE_TYPE_RESPONSE => map.remove(&mh.stream_id),
+            MESSAGE_TYPE_DATA if (mh.flags & FLAG_REMOTE_CLOSED) != FLAG_REMOTE_CLOSED => {
+                map.get(&mh.stream_id).cloned()
+            }
+            _ => map.remove(&mh.stream_id),
+        }
+    };
+    let recver_tx = match recver_tx {
         Some(tx) => tx,
         None => {
             debug!("Recver got unknown packet {:?} {:?}", mh, buf);
             return;
         }
     };
-    if mh.type_ != MESSAGE_TYPE_RESPONSE {
+    if mh.type_ != MESSAGE_TYPE_RESPONSE && mh.type_ != MESSAGE_TYPE_DATA {
         recver_tx
             .send(Err(Error::Others(format!(
                 "Recver got malformed packet {:?} {:?}",
@@ -234,8 +418,9 @@ fn trans_resp(recver_map_orig: ReciverMap, mh: MessageHeader, buf: Result<Vec<u8
     }
 
     recver_tx
-        .send(buf)
+        .send(buf.map(|payload| GenMessage {
+            header: mh,
+            payload,
+        }))
         .unwrap_or_else(|_e| error!("The request has returned"));
-
-    map.remove(&mh.stream_id);
 }
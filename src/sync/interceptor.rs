@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Interceptors wrapping every request and stream of the client (sync).
+
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::proto::{Request, Response};
+use crate::sync::{Client, StreamInner};
+
+/// Client side interceptor, registered by [`Client::add_interceptor`].
+///
+/// Interceptors are called in the order they were added. They may rewrite the
+/// outgoing request, observe the response and map the returned error.
+pub trait ClientInterceptor: Send + Sync {
+    /// Intercepts an unary request.
+    fn intercept(&self, req: Request, next: ClientNext<'_>) -> Result<Response> {
+        next.run(req)
+    }
+
+    /// Intercepts the creation of a stream.
+    fn intercept_stream(&self, req: Request, next: ClientStreamNext<'_>) -> Result<StreamInner> {
+        next.run(req)
+    }
+}
+
+pub(crate) type ClientInterceptors = Arc<Vec<Arc<dyn ClientInterceptor>>>;
+
+/// The remaining part of the client unary interceptor chain.
+pub struct ClientNext<'a> {
+    interceptors: &'a [Arc<dyn ClientInterceptor>],
+    client: &'a Client,
+}
+
+impl<'a> ClientNext<'a> {
+    pub(crate) fn new(interceptors: &'a [Arc<dyn ClientInterceptor>], client: &'a Client) -> Self {
+        Self {
+            interceptors,
+            client,
+        }
+    }
+
+    /// Calls the next interceptor, or sends the request at the end of the chain.
+    pub fn run(self, req: Request) -> Result<Response> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor.intercept(req, ClientNext::new(rest, self.client))
+            }
+            None => self.client.do_request(req),
+        }
+    }
+}
+
+/// The remaining part of the client streaming interceptor chain.
+pub struct ClientStreamNext<'a> {
+    interceptors: &'a [Arc<dyn ClientInterceptor>],
+    client: &'a Client,
+    streaming_client: bool,
+    streaming_server: bool,
+}
+
+impl<'a> ClientStreamNext<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn ClientInterceptor>],
+        client: &'a Client,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Self {
+        Self {
+            interceptors,
+            client,
+            streaming_client,
+            streaming_server,
+        }
+    }
+
+    /// Returns `true` if the client sends a stream of messages.
+    pub fn streaming_client(&self) -> bool {
+        self.streaming_client
+    }
+
+    /// Returns `true` if the server sends a stream of messages.
+    pub fn streaming_server(&self) -> bool {
+        self.streaming_server
+    }
+
+    /// Calls the next interceptor, or creates the stream at the end of the chain.
+    pub fn run(self, req: Request) -> Result<StreamInner> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                let next = ClientStreamNext::new(
+                    rest,
+                    self.client,
+                    self.streaming_client,
+                    self.streaming_server,
+                );
+                interceptor.intercept_stream(req, next)
+            }
+            None => self
+                .client
+                .do_new_stream(req, self.streaming_client, self.streaming_server),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::error::{get_rpc_status, Error};
+    use crate::proto::{Code, KeyValue};
+    use crate::sync::{response_to_channel, MethodHandler, Server, StreamHandler, TtrpcContext};
+
+    /// The tags added to the metadata by the client interceptors.
+    fn tags(ctx: &TtrpcContext) -> String {
+        ctx.metadata
+            .get("tag")
+            .map_or(String::new(), |t| t.join(","))
+    }
+
+    struct Tags;
+
+    impl MethodHandler for Tags {
+        fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()> {
+            let mut resp = Response::new();
+            resp.payload = req.payload;
+            resp.payload.extend(format!("|{}", tags(&ctx)).into_bytes());
+            response_to_channel(ctx.mh.stream_id, resp, ctx.res_tx)
+        }
+    }
+
+    struct TagsStream;
+
+    impl StreamHandler for TagsStream {
+        fn handler(&self, ctx: TtrpcContext, inner: StreamInner) -> Result<Option<Response>> {
+            inner.send(tags(&ctx).into_bytes())?;
+            Ok(None)
+        }
+    }
+
+    /// Tags the requests, or rejects them.
+    struct Tag {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        reject: bool,
+    }
+
+    impl Tag {
+        fn tag(&self, req: &mut Request) -> Result<()> {
+            self.calls.lock().unwrap().push(self.name);
+            if self.reject {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, self.name));
+            }
+            req.payload.extend(format!("{}.", self.name).into_bytes());
+            req.metadata.push(KeyValue {
+                key: "tag".to_string(),
+                value: self.name.to_string(),
+                ..Default::default()
+            });
+            Ok(())
+        }
+    }
+
+    impl ClientInterceptor for Tag {
+        fn intercept(&self, mut req: Request, next: ClientNext<'_>) -> Result<Response> {
+            self.tag(&mut req)?;
+            next.run(req)
+        }
+
+        fn intercept_stream(
+            &self,
+            mut req: Request,
+            next: ClientStreamNext<'_>,
+        ) -> Result<StreamInner> {
+            assert!(!next.streaming_client());
+            assert!(next.streaming_server());
+            self.tag(&mut req)?;
+            next.run(req)
+        }
+    }
+
+    #[test]
+    fn test_client_interceptor_chain() {
+        let addr = "memory://sync-interceptor-unit-test-client";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Tags".to_string(), Box::new(Tags));
+        let mut streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>> = HashMap::new();
+        streams.insert("/test/TagsStream".to_string(), Arc::new(TagsStream));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(methods)
+            .register_stream_service(streams);
+        server.start().unwrap();
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let tag = |name, reject| Tag {
+            name,
+            calls: calls.clone(),
+            reject,
+        };
+        let client = Client::connect(addr).unwrap();
+        let tagged = client
+            .clone()
+            .add_interceptor(tag("first", false))
+            .add_interceptor(tag("second", false));
+        let rejected = client
+            .add_interceptor(tag("deny", true))
+            .add_interceptor(tag("never", false));
+        let req = |method: &str| Request {
+            service: "test".to_string(),
+            method: method.to_string(),
+            ..Default::default()
+        };
+
+        // The interceptors run in order, and change the request and its
+        // metadata.
+        let resp = tagged.request(req("Tags")).unwrap();
+        assert_eq!(resp.payload, b"first.second.|first,second");
+        assert_eq!(*calls.lock().unwrap(), vec!["first", "second"]);
+
+        let mut stream = tagged.new_stream(req("TagsStream"), false, true).unwrap();
+        assert_eq!(stream.recv().unwrap(), b"first,second");
+
+        // The interceptors short-circuit the chain with an error.
+        calls.lock().unwrap().clear();
+        let err = rejected.request(req("Tags")).unwrap_err();
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::PERMISSION_DENIED));
+        let Err(err) = rejected.new_stream(req("TagsStream"), false, true) else {
+            panic!("the stream is not rejected");
+        };
+        assert!(matches!(err, Error::RpcStatus(s) if s.code() == Code::PERMISSION_DENIED));
+        assert_eq!(*calls.lock().unwrap(), vec!["deny", "deny"]);
+
+        server.shutdown();
+    }
+}
@@ -7,15 +7,24 @@
 
 mod channel;
 mod client;
+mod interceptor;
+mod pool;
 mod server;
+mod stream;
 mod sys;
 
 #[macro_use]
 mod utils;
 
 pub use client::Client;
-pub use server::Server;
+pub use interceptor::{ClientInterceptor, ClientNext, ClientStreamNext};
+pub use server::{Server, ShutdownHandle};
+pub use stream::{
+    CSReceiver, CSSender, ClientStream, ClientStreamReceiver, ClientStreamSender, Kind, SSReceiver,
+    SSSender, ServerStream, ServerStreamReceiver, ServerStreamSender, StreamInner, StreamReceiver,
+    StreamSender,
+};
 
 #[doc(hidden)]
 pub use utils::response_to_channel;
-pub use utils::{MethodHandler, TtrpcContext};
+pub use utils::{MethodHandler, StreamHandler, TtrpcContext};
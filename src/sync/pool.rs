@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! A pool of worker threads shared by the connections of a server.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam::channel::{bounded, Sender};
+
+use crate::error::{Error, Result};
+
+/// Handles the items sent to the pool on `size` threads, up to `queue` items
+/// wait while all of them are busy. The threads exit once the pool is
+/// dropped and the items queued are handled.
+pub(crate) struct WorkerPool<T> {
+    tx: Sender<T>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    pub(crate) fn new(
+        size: usize,
+        queue: usize,
+        handler: impl Fn(T) + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let (tx, rx) = bounded::<T>(queue);
+        let handler = Arc::new(handler);
+        for i in 0..size {
+            let rx = rx.clone();
+            let handler = handler.clone();
+            thread::Builder::new()
+                .name(format!("worker_{i}"))
+                .spawn(move || {
+                    for item in rx.iter() {
+                        // A panicking handler must not shrink the pool.
+                        if catch_unwind(AssertUnwindSafe(|| handler(item))).is_err() {
+                            error!("worker handler panicked");
+                        }
+                    }
+                })
+                .map_err(err_to_others_err!(e, "spawn worker thread failed "))?;
+        }
+        Ok(WorkerPool { tx })
+    }
+
+    /// Queues `item`, gives it back if the queue is full.
+    pub(crate) fn try_execute(&self, item: T) -> std::result::Result<(), T> {
+        self.tx.try_send(item).map_err(|e| e.into_inner())
+    }
+}
+
+/// Counts the threads spawned beside a pool, e.g. the ones of the streams
+/// which would starve it, up to `max` at once.
+pub(crate) struct ThreadSlots {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl ThreadSlots {
+    pub(crate) fn new(max: usize) -> Self {
+        ThreadSlots {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes a slot until the returned guard is dropped, `None` if all of
+    /// them are taken.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<SlotGuard> {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .ok()?;
+        Some(SlotGuard(self.clone()))
+    }
+}
+
+pub(crate) struct SlotGuard(Arc<ThreadSlots>);
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        self.0.used.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_worker_pool() {
+        let (tx, rx) = channel();
+        let pool = WorkerPool::new(2, 1, move |d: u64| {
+            thread::sleep(Duration::from_millis(d));
+            tx.send(d).unwrap();
+        })
+        .unwrap();
+
+        // Two busy workers and one queued item, the next one is rejected.
+        for d in [200, 200, 0] {
+            assert!(pool.try_execute(d).is_ok());
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(pool.try_execute(1), Err(1));
+
+        let mut handled: Vec<u64> = rx.iter().take(3).collect();
+        handled.sort();
+        assert_eq!(handled, vec![0, 200, 200]);
+        assert!(pool.try_execute(0).is_ok());
+        drop(pool);
+        assert_eq!(rx.recv().unwrap(), 0);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_thread_slots() {
+        let slots = Arc::new(ThreadSlots::new(2));
+        let first = slots.try_acquire().unwrap();
+        let second = slots.try_acquire().unwrap();
+        assert!(slots.try_acquire().is_none());
+
+        drop(first);
+        let third = slots.try_acquire().unwrap();
+        assert!(slots.try_acquire().is_none());
+        drop((second, third));
+        assert_eq!(slots.used.load(Ordering::SeqCst), 0);
+    }
+}
@@ -19,21 +19,30 @@
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::time::Duration;
 
+use protobuf::descriptor::FileDescriptorProto;
 use protobuf::{CodedInputStream, Message};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
 use std::thread::JoinHandle;
 
+use super::pool::{ThreadSlots, WorkerPool};
 use super::utils::{response_error_to_channel, response_to_channel};
 use crate::context;
 use crate::error::{get_status, Error, Result};
-use crate::proto::{Code, MessageHeader, Request, Response, MESSAGE_TYPE_REQUEST};
+use crate::metrics::{self, CallRecord, ConnectionRecord, Side};
+use crate::proto::{
+    check_oversize, Code, GenMessage, MessageHeader, MessageSizeLimits, Request, Response,
+    FLAG_NO_DATA, FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN, MESSAGE_TYPE_DATA, MESSAGE_TYPE_REQUEST,
+    MESSAGE_HEADER_LENGTH, MESSAGE_TYPE_RESPONSE, Status,
+};
+use crate::reflection;
 use crate::sync::channel::{read_message, write_message};
+use crate::sync::stream::{Kind, ResultReceiver, ResultSender, StreamInner, StreamMap};
 use crate::sync::sys::{PipeConnection, PipeListener};
-use crate::{MethodHandler, TtrpcContext};
+use crate::{MethodHandler, StreamHandler, TtrpcContext};
 
 // poll_queue will create WAIT_THREAD_COUNT_DEFAULT threads in begin.
 // If wait thread count < WAIT_THREAD_COUNT_MIN, create number to WAIT_THREAD_COUNT_DEFAULT.
@@ -45,21 +54,33 @@ const DEFAULT_ACCEPT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
 
 type MessageSender = Sender<(MessageHeader, Vec<u8>)>;
 type MessageReceiver = Receiver<(MessageHeader, Vec<u8>)>;
-type WorkloadSender = crossbeam::channel::Sender<(MessageHeader, Result<Vec<u8>>)>;
-type WorkloadReceiver = crossbeam::channel::Receiver<(MessageHeader, Result<Vec<u8>>)>;
+// The receiver is set when the stream had been opened by the reader thread.
+type Workload = (MessageHeader, Result<Vec<u8>>, Option<ResultReceiver>);
+type WorkloadSender = crossbeam::channel::Sender<Workload>;
+type WorkloadReceiver = crossbeam::channel::Receiver<Workload>;
+// The handlers by path, which can be changed while the server runs.
+type MethodHandlers = Arc<RwLock<HashMap<String, Arc<dyn MethodHandler + Send + Sync>>>>;
+type StreamHandlers = Arc<RwLock<HashMap<String, Arc<dyn StreamHandler + Send + Sync>>>>;
+// The calls of a connection waiting for their last message, by stream id.
+type CallRecords = Arc<Mutex<HashMap<u32, CallRecord>>>;
 
 /// A ttrpc Server (sync).
 pub struct Server {
     listeners: Vec<Arc<PipeListener>>,
-    listener_quit_flag: Arc<AtomicBool>,
-    connections: Arc<Mutex<HashMap<i32, Connection>>>,
-    methods: Arc<HashMap<String, Box<dyn MethodHandler + Send + Sync>>>,
+    lifecycle: Arc<Lifecycle>,
+    methods: MethodHandlers,
+    streams: StreamHandlers,
+    reflection: Option<Vec<FileDescriptorProto>>,
     handler: Option<JoinHandle<()>>,
     reaper: Option<(Sender<i32>, JoinHandle<()>)>,
     thread_count_default: usize,
     thread_count_min: usize,
     thread_count_max: usize,
+    // The size, the queue limit and the stream limit of the worker pool, if
+    // any.
+    worker_pool: Option<(usize, usize, usize)>,
     accept_retry_interval: Duration,
+    size_limits: MessageSizeLimits,
 }
 
 struct Connection {
@@ -81,35 +102,297 @@ impl Connection {
     }
 }
 
+/// The state of a [`Server`] shared with its [`ShutdownHandle`]s.
+struct Lifecycle {
+    listener_quit_flag: AtomicBool,
+    /// The listener being served, taken when it stops.
+    listener: Mutex<Option<Arc<PipeListener>>>,
+    connections: Arc<Mutex<HashMap<i32, Connection>>>,
+    draining: AtomicBool,
+    in_flight: Mutex<usize>,
+    idle: Condvar,
+    shutdown: Mutex<bool>,
+    shutdown_cv: Condvar,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Lifecycle {
+            listener_quit_flag: AtomicBool::new(false),
+            listener: Mutex::new(None),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            draining: AtomicBool::new(false),
+            in_flight: Mutex::new(0),
+            idle: Condvar::new(),
+            shutdown: Mutex::new(false),
+            shutdown_cv: Condvar::new(),
+        }
+    }
+}
+
+impl Lifecycle {
+    fn stop_listen(&self) {
+        if let Some(listener) = self.listener.lock().unwrap().take() {
+            self.listener_quit_flag.store(true, Ordering::SeqCst);
+            listener
+                .close()
+                .unwrap_or_else(|e| warn!("failed to close connection with error: {}", e));
+            info!("close monitor");
+        }
+    }
+
+    fn disconnect(&self) {
+        info!("begin to shutdown connection");
+        for (_fd, c) in self.connections.lock().unwrap().iter() {
+            c.shutdown();
+        }
+        info!("connections closed");
+    }
+
+    fn notify_shutdown(&self) {
+        *self.shutdown.lock().unwrap() = true;
+        self.shutdown_cv.notify_all();
+    }
+
+    fn wait_shutdown(&self) {
+        let shutdown = self.shutdown.lock().unwrap();
+        drop(self.shutdown_cv.wait_while(shutdown, |s| !*s).unwrap());
+    }
+
+    /// Tracks a call until the returned guard is dropped, returns `None` if
+    /// the server is draining.
+    fn track(self: &Arc<Self>) -> Option<CallGuard> {
+        // Checked under the lock of the count, so the drain either waits for
+        // the call or the call sees the drain.
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if self.draining.load(Ordering::SeqCst) {
+            return None;
+        }
+        *in_flight += 1;
+        Some(CallGuard(self.clone()))
+    }
+
+    fn set_draining(&self, draining: bool) {
+        let _in_flight = self.in_flight.lock().unwrap();
+        self.draining.store(draining, Ordering::SeqCst);
+    }
+
+    /// Waits for the calls in flight to finish, returns `false` on timeout.
+    fn wait_idle(&self, timeout: Duration) -> bool {
+        let in_flight = self.in_flight.lock().unwrap();
+        let (in_flight, res) = self
+            .idle
+            .wait_timeout_while(in_flight, timeout, |n| *n > 0)
+            .unwrap();
+        drop(in_flight);
+        !res.timed_out()
+    }
+}
+
+struct CallGuard(Arc<Lifecycle>);
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.0.in_flight.lock().unwrap();
+        *in_flight -= 1;
+        if *in_flight == 0 {
+            self.0.idle.notify_all();
+        }
+    }
+}
+
+/// Shuts a [`Server`] down from any thread, e.g. one handling the signals,
+/// while [`Server::wait`] waits for it to exit.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl ShutdownHandle {
+    /// Stops listening and closes the connections.
+    pub fn shutdown(&self) {
+        self.lifecycle.stop_listen();
+        self.lifecycle.disconnect();
+        self.lifecycle.notify_shutdown();
+    }
+
+    /// Shuts the server down gracefully: stops listening, answers the new
+    /// requests `UNAVAILABLE` and waits for the calls in flight up to
+    /// `timeout` before closing the connections. Returns `false` if calls
+    /// were still in flight.
+    pub fn drain(&self, timeout: Duration) -> bool {
+        self.lifecycle.stop_listen();
+        self.lifecycle.set_draining(true);
+        let drained = self.lifecycle.wait_idle(timeout);
+        self.shutdown();
+        drained
+    }
+
+    /// Returns `true` if the server has been shut down.
+    pub fn is_shutdown(&self) -> bool {
+        *self.lifecycle.shutdown.lock().unwrap()
+    }
+}
+
+/// The state of a connection its requests are handled with.
+struct HandlerContext {
+    connection: Arc<PipeConnection>,
+    quit: Arc<AtomicBool>,
+    lifecycle: Arc<Lifecycle>,
+    methods: MethodHandlers,
+    streams: StreamHandlers,
+    stream_map: StreamMap,
+    res_tx: MessageSender,
+    control_tx: SyncSender<()>,
+    cancel_rx: crossbeam::channel::Receiver<()>,
+    max_send: usize,
+    calls: CallRecords,
+    // The threads of the streams, shared by the connections when the requests
+    // are handled by the worker pool of the server.
+    stream_slots: Arc<ThreadSlots>,
+}
+
+impl HandlerContext {
+    /// Handles a workload, quits the connection if it can't be answered.
+    fn handle(self: &Arc<Self>, workload: Workload) -> bool {
+        match self.handle_workload(workload) {
+            Ok(()) => true,
+            Err(x) => {
+                debug!("handle request get error {:?}", x);
+                self.quit_connection();
+                false
+            }
+        }
+    }
+
+    fn handle_workload(self: &Arc<Self>, (mh, buf, stream_rx): Workload) -> Result<()> {
+        let buf = match buf {
+            Ok(buf) => buf,
+            Err(e) => return response_error_to_channel(mh.stream_id, e, self.res_tx.clone()),
+        };
+        if mh.type_ != MESSAGE_TYPE_REQUEST {
+            return Ok(());
+        }
+        let mut s = CodedInputStream::from_bytes(&buf);
+        let mut req = Request::new();
+        if let Err(x) = req.merge_from(&mut s) {
+            let status = get_status(Code::INVALID_ARGUMENT, x.to_string());
+            return self.reject(&mh, stream_rx, status);
+        }
+        trace!("Got Message request {:?}", req);
+
+        let path = format!("/{}/{}", req.service, req.method);
+        let method = self.methods.read().unwrap().get(&path).cloned();
+        let stream = self.streams.read().unwrap().get(&path).cloned();
+        // The unknown methods are not recorded, their names are up to the clients.
+        if method.is_some() || stream.is_some() {
+            let streaming = method.is_none();
+            if let Some(call) =
+                CallRecord::start(Side::Server, &req.service, &req.method, streaming)
+            {
+                self.calls.lock().unwrap().insert(mh.stream_id, call);
+            }
+        }
+        let Some(call) = self.lifecycle.track() else {
+            let status = get_status(Code::UNAVAILABLE, "server is draining");
+            return self.reject(&mh, stream_rx, status);
+        };
+        let ctx = TtrpcContext {
+            fd: self.connection.id(),
+            cancel_rx: self.cancel_rx.clone(),
+            mh,
+            res_tx: self.res_tx.clone(),
+            metadata: context::from_pb(&req.metadata),
+            timeout_nano: req.timeout_nano,
+            deadline: context::deadline(req.timeout_nano),
+            peer: self.connection.peer(),
+        };
+        if let Some(method) = method {
+            if stream_rx.is_some() {
+                self.stream_map.lock().unwrap().remove(&mh.stream_id);
+            }
+            method.handler(ctx, req)
+        } else if let Some(stream) = stream {
+            // The streams last as long as the clients keep them open, e.g. the
+            // health watches, they must not starve the threads of the requests.
+            let Some(slot) = self.stream_slots.try_acquire() else {
+                let status = get_status(Code::RESOURCE_EXHAUSTED, "too many streams in flight");
+                return self.reject(&mh, stream_rx, status);
+            };
+            let this = self.clone();
+            thread::Builder::new()
+                .name("stream_handler".into())
+                .spawn(move || {
+                    let (_call, _slot) = (call, slot);
+                    if let Err(x) = handle_stream(
+                        stream.as_ref(),
+                        ctx,
+                        req.payload,
+                        stream_rx,
+                        &this.stream_map,
+                        &this.res_tx,
+                        this.max_send,
+                    ) {
+                        debug!("handle stream get error {:?}", x);
+                        this.quit_connection();
+                    }
+                })
+                .map(|_| ())
+                .map_err(err_to_others_err!(e, "spawn stream handler thread failed "))
+        } else {
+            let status = get_status(Code::UNIMPLEMENTED, format!("{path} does not exist"));
+            self.reject(&mh, stream_rx, status)
+        }
+    }
+
+    /// Answers a request with `status`, closing the stream it opened.
+    fn reject(
+        &self,
+        mh: &MessageHeader,
+        stream_rx: Option<ResultReceiver>,
+        status: Status,
+    ) -> Result<()> {
+        if stream_rx.is_some() {
+            self.stream_map.lock().unwrap().remove(&mh.stream_id);
+        }
+        let mut res = Response::new();
+        res.set_status(status);
+        response_to_channel(mh.stream_id, res, self.res_tx.clone())
+    }
+
+    fn quit_connection(&self) {
+        self.quit.store(true, Ordering::SeqCst);
+        // the client connection would be closed and
+        // the connection dealing main thread would
+        // have exited.
+        self.control_tx
+            .send(())
+            .unwrap_or_else(|err| debug!("Failed to send {:?}", err));
+    }
+}
+
+/// The pool of workers shared by the connections, see
+/// [`Server::set_worker_pool`].
+type Pool = WorkerPool<(Arc<HandlerContext>, Workload)>;
+
 struct ThreadS<'a> {
-    connection: &'a Arc<PipeConnection>,
+    ctx: &'a Arc<HandlerContext>,
     workload_rx: &'a WorkloadReceiver,
     wtc: &'a Arc<AtomicUsize>,
-    quit: &'a Arc<AtomicBool>,
-    methods: &'a Arc<HashMap<String, Box<dyn MethodHandler + Send + Sync>>>,
-    res_tx: &'a MessageSender,
-    control_tx: &'a SyncSender<()>,
-    cancel_rx: &'a crossbeam::channel::Receiver<()>,
     default: usize,
     min: usize,
     max: usize,
 }
 
-#[allow(clippy::too_many_arguments)]
 fn start_method_handler_thread(
-    connection: Arc<PipeConnection>,
+    ctx: Arc<HandlerContext>,
     workload_rx: WorkloadReceiver,
     wtc: Arc<AtomicUsize>,
-    quit: Arc<AtomicBool>,
-    methods: Arc<HashMap<String, Box<dyn MethodHandler + Send + Sync>>>,
-    res_tx: MessageSender,
-    control_tx: SyncSender<()>,
-    cancel_rx: crossbeam::channel::Receiver<()>,
     min: usize,
     max: usize,
 ) {
     thread::spawn(move || {
-        while !quit.load(Ordering::SeqCst) {
+        while !ctx.quit.load(Ordering::SeqCst) {
             let c = wtc.fetch_add(1, Ordering::SeqCst) + 1;
             if c > max {
                 wtc.fetch_sub(1, Ordering::SeqCst);
@@ -118,9 +401,9 @@ fn start_method_handler_thread(
 
             let result = workload_rx.recv();
 
-            if quit.load(Ordering::SeqCst) {
+            if ctx.quit.load(Ordering::SeqCst) {
                 // notify the connection dealing main thread to stop.
-                control_tx
+                ctx.control_tx
                     .send(())
                     .unwrap_or_else(|err| trace!("Failed to send {:?}", err));
                 break;
@@ -129,79 +412,21 @@ fn start_method_handler_thread(
             let c = wtc.fetch_sub(1, Ordering::SeqCst) - 1;
             if c < min {
                 trace!("notify client handler to create much more worker threads!");
-                control_tx
+                ctx.control_tx
                     .send(())
                     .unwrap_or_else(|err| trace!("Failed to send {:?}", err));
             }
 
-            let mh;
-            let buf;
-            match result {
-                Ok((x, Ok(y))) => {
-                    mh = x;
-                    buf = y;
-                }
-                Ok((mh, Err(e))) => {
-                    if let Err(x) = response_error_to_channel(mh.stream_id, e, res_tx.clone()) {
-                        debug!("response_error_to_channel get error {:?}", x);
-                        quit_connection(quit, control_tx);
-                        break;
-                    }
-                    continue;
-                }
-                Err(x) => match x {
-                    crossbeam::channel::RecvError => {
-                        trace!("workload_rx recv error");
-                        quit_connection(quit, control_tx);
-                        trace!("workload_rx recv error, send control_tx");
-                        break;
-                    }
-                },
-            }
-
-            if mh.type_ != MESSAGE_TYPE_REQUEST {
-                continue;
-            }
-            let mut s = CodedInputStream::from_bytes(&buf);
-            let mut req = Request::new();
-            if let Err(x) = req.merge_from(&mut s) {
-                let status = get_status(Code::INVALID_ARGUMENT, x.to_string());
-                let mut res = Response::new();
-                res.set_status(status);
-                if let Err(x) = response_to_channel(mh.stream_id, res, res_tx.clone()) {
-                    debug!("response_to_channel get error {:?}", x);
-                    quit_connection(quit, control_tx);
+            let workload = match result {
+                Ok(workload) => workload,
+                Err(crossbeam::channel::RecvError) => {
+                    trace!("workload_rx recv error");
+                    ctx.quit_connection();
+                    trace!("workload_rx recv error, send control_tx");
                     break;
                 }
-                continue;
-            }
-            trace!("Got Message request {:?}", req);
-
-            let path = format!("/{}/{}", req.service, req.method);
-            let method = if let Some(x) = methods.get(&path) {
-                x
-            } else {
-                let status = get_status(Code::INVALID_ARGUMENT, format!("{path} does not exist"));
-                let mut res = Response::new();
-                res.set_status(status);
-                if let Err(x) = response_to_channel(mh.stream_id, res, res_tx.clone()) {
-                    info!("response_to_channel get error {:?}", x);
-                    quit_connection(quit, control_tx);
-                    break;
-                }
-                continue;
             };
-            let ctx = TtrpcContext {
-                fd: connection.id(),
-                cancel_rx: cancel_rx.clone(),
-                mh,
-                res_tx: res_tx.clone(),
-                metadata: context::from_pb(&req.metadata),
-                timeout_nano: req.timeout_nano,
-            };
-            if let Err(x) = method.handler(ctx, req) {
-                debug!("method handle {} get error {:?}", path, x);
-                quit_connection(quit, control_tx);
+            if !ctx.handle(workload) {
                 break;
             }
         }
@@ -210,18 +435,13 @@ fn start_method_handler_thread(
 
 fn start_method_handler_threads(num: usize, ts: &ThreadS) {
     for _ in 0..num {
-        if ts.quit.load(Ordering::SeqCst) {
+        if ts.ctx.quit.load(Ordering::SeqCst) {
             break;
         }
         start_method_handler_thread(
-            ts.connection.clone(),
+            ts.ctx.clone(),
             ts.workload_rx.clone(),
             ts.wtc.clone(),
-            ts.quit.clone(),
-            ts.methods.clone(),
-            ts.res_tx.clone(),
-            ts.control_tx.clone(),
-            ts.cancel_rx.clone(),
             ts.min,
             ts.max,
         );
@@ -239,15 +459,18 @@ impl Default for Server {
     fn default() -> Self {
         Server {
             listeners: Vec::with_capacity(1),
-            listener_quit_flag: Arc::new(AtomicBool::new(false)),
-            connections: Arc::new(Mutex::new(HashMap::new())),
-            methods: Arc::new(HashMap::new()),
+            lifecycle: Arc::new(Lifecycle::default()),
+            methods: Arc::new(RwLock::new(HashMap::new())),
+            streams: Arc::new(RwLock::new(HashMap::new())),
+            reflection: None,
             handler: None,
             reaper: None,
             thread_count_default: DEFAULT_WAIT_THREAD_COUNT_DEFAULT,
             thread_count_min: DEFAULT_WAIT_THREAD_COUNT_MIN,
             thread_count_max: DEFAULT_WAIT_THREAD_COUNT_MAX,
+            worker_pool: None,
             accept_retry_interval: DEFAULT_ACCEPT_RETRY_INTERVAL,
+            size_limits: MessageSizeLimits::default(),
         }
     }
 }
@@ -257,6 +480,9 @@ impl Server {
         Server::default()
     }
 
+    /// Listens on `sockaddr`, an address of one of the built-in schemes: the
+    /// transports registered with `r#async::transport::register_transport`
+    /// are used by the async server only.
     pub fn bind(mut self, sockaddr: &str) -> Result<Server> {
         if !self.listeners.is_empty() {
             return Err(Error::Others(
@@ -286,11 +512,52 @@ impl Server {
     }
 
     pub fn register_service(
-        mut self,
+        self,
         methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>>,
     ) -> Server {
-        let mut_methods = Arc::get_mut(&mut self.methods).unwrap();
-        mut_methods.extend(methods);
+        self.add_service(methods);
+        self
+    }
+
+    pub fn register_stream_service(
+        self,
+        streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>>,
+    ) -> Server {
+        self.add_stream_service(streams);
+        self
+    }
+
+    /// Adds the methods, by path, also while the server is running.
+    pub fn add_service(&self, methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>>) {
+        let methods = methods.into_iter().map(|(path, m)| (path, Arc::from(m)));
+        self.methods.write().unwrap().extend(methods);
+    }
+
+    /// Adds the streams, by path, also while the server is running.
+    pub fn add_stream_service(&self, streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>>) {
+        self.streams.write().unwrap().extend(streams);
+    }
+
+    /// Removes the methods and the streams of `service` while the server is
+    /// running, returns `false` if there are none. The calls in flight
+    /// complete, the new ones are answered `UNIMPLEMENTED`.
+    pub fn remove_service(&self, service: &str) -> bool {
+        let prefix = format!("/{service}/");
+        let mut removed = false;
+        let mut retain = |path: &String| {
+            let keep = !path.starts_with(&prefix);
+            removed |= !keep;
+            keep
+        };
+        self.methods.write().unwrap().retain(|path, _| retain(path));
+        self.streams.write().unwrap().retain(|path, _| retain(path));
+        removed
+    }
+
+    /// Registers the reflection service, which lists the services of the
+    /// server and returns the given file descriptors.
+    pub fn register_reflection(mut self, files: Vec<FileDescriptorProto>) -> Server {
+        self.reflection = Some(files);
         self
     }
 
@@ -304,32 +571,97 @@ impl Server {
         self
     }
 
+    /// Sets the most threads handling the requests of a connection. Up to as
+    /// many streaming requests of the connection are handled at once besides,
+    /// on threads of their own, the next ones are answered `RESOURCE_EXHAUSTED`.
     pub fn set_thread_count_max(mut self, count: usize) -> Server {
         self.thread_count_max = count;
         self
     }
 
+    /// Handles the requests of all the connections on a pool of `size`
+    /// threads, instead of the threads of each connection set by the
+    /// `set_thread_count_*` methods. Up to `queue` requests wait while all
+    /// the threads are busy, the next ones are answered `RESOURCE_EXHAUSTED`.
+    ///
+    /// Up to `streams` streaming requests are handled at once, on threads of
+    /// their own which last as long as their streams, the next ones are
+    /// answered `RESOURCE_EXHAUSTED`.
+    pub fn set_worker_pool(mut self, size: usize, queue: usize, streams: usize) -> Server {
+        self.worker_pool = Some((size, queue, streams));
+        self
+    }
+
     pub fn set_accept_retry_interval(mut self, interval: Duration) -> Server {
         self.accept_retry_interval = interval;
         self
     }
 
+    /// Sets the maximum sizes of the messages sent and received.
+    pub fn with_message_size_limits(mut self, limits: MessageSizeLimits) -> Server {
+        self.size_limits = limits;
+        self
+    }
+
     pub fn start_listen(&mut self) -> Result<()> {
-        let connections = self.connections.clone();
+        let connections = self.lifecycle.connections.clone();
 
         if self.listeners.is_empty() {
             return Err(Error::Others("ttrpc-rust not bind".to_string()));
         }
 
-        self.listener_quit_flag.store(false, Ordering::SeqCst);
+        let lifecycle = self.lifecycle.clone();
+        lifecycle.listener_quit_flag.store(false, Ordering::SeqCst);
+        lifecycle.set_draining(false);
+        *lifecycle.shutdown.lock().unwrap() = false;
+        *lifecycle.listener.lock().unwrap() = Some(self.listeners[0].clone());
+
+        if let Some(files) = self.reflection.take() {
+            // Weak, the reflection service is itself one of the methods.
+            let methods = Arc::downgrade(&self.methods);
+            let streams = Arc::downgrade(&self.streams);
+            let registry = reflection::Registry::new(
+                move || {
+                    let (Some(methods), Some(streams)) = (methods.upgrade(), streams.upgrade())
+                    else {
+                        return Vec::new();
+                    };
+                    let methods = methods.read().unwrap();
+                    let streams = streams.read().unwrap();
+                    let methods = methods.keys().map(|path| (path, false));
+                    let streams = streams.keys().map(|path| (path, true));
+                    methods
+                        .chain(streams)
+                        .filter_map(|(path, streaming)| {
+                            let (service, method) = path.trim_start_matches('/').split_once('/')?;
+                            Some((service.to_string(), method.to_string(), streaming))
+                        })
+                        .collect()
+                },
+                files,
+            );
+            let reflection = reflection::sync::create_reflection(registry);
+            let reflection = reflection.into_iter().map(|(path, m)| (path, Arc::from(m)));
+            self.methods.write().unwrap().extend(reflection);
+        }
 
         let listener = self.listeners[0].clone();
         let methods = self.methods.clone();
+        let streams = self.streams.clone();
         let default = self.thread_count_default;
         let min = self.thread_count_min;
         let max = self.thread_count_max;
-        let listener_quit_flag = self.listener_quit_flag.clone();
         let accept_retry_interval = self.accept_retry_interval;
+        let size_limits = self.size_limits;
+        let pool = match self.worker_pool {
+            Some((size, queue, _)) => Some(Arc::new(Pool::new(size, queue, |(ctx, workload)| {
+                ctx.handle(workload);
+            })?)),
+            None => None,
+        };
+        let stream_slots = self
+            .worker_pool
+            .map(|(_, _, streams)| Arc::new(ThreadSlots::new(streams)));
 
         let reaper_tx = match self.reaper.take() {
             None => {
@@ -368,7 +700,7 @@ impl Server {
             .spawn(move || {
                 loop {
                     trace!("listening...");
-                    if listener_quit_flag.load(Ordering::SeqCst) {
+                    if lifecycle.listener_quit_flag.load(Ordering::SeqCst) {
                         info!("listener shutdown for quit flag");
                         break;
                     }
@@ -396,6 +728,12 @@ impl Server {
                     };
 
                     let methods = methods.clone();
+                    let streams = streams.clone();
+                    let lifecycle = lifecycle.clone();
+                    let pool = pool.clone();
+                    let stream_slots = stream_slots
+                        .clone()
+                        .unwrap_or_else(|| Arc::new(ThreadSlots::new(max)));
                     let quit = Arc::new(AtomicBool::new(false));
                     let child_quit = quit.clone();
                     let reaper_tx_child = reaper_tx.clone();
@@ -407,18 +745,26 @@ impl Server {
                         .name("client_handler".into())
                         .spawn(move || {
                             debug!("Got new client");
+                            let _record = ConnectionRecord::open(Side::Server);
                             // Start response thread
                             let quit_res = child_quit.clone();
                             let pipe = pipe_connection_child.clone();
+                            let calls: CallRecords = Arc::new(Mutex::new(HashMap::new()));
+                            let res_calls = calls.clone();
                             let (res_tx, res_rx): (MessageSender, MessageReceiver) = channel();
                             let handler = thread::spawn(move || {
                                 for r in res_rx.iter() {
                                     trace!("response thread get {:?}", r);
-                                    if let Err(e) = write_message(&pipe, r.0, r.1) {
+                                    let (mh, buf) =
+                                        limit_response(r.0, r.1, size_limits.max_send);
+                                    let len = MESSAGE_HEADER_LENGTH + buf.len();
+                                    finish_call(&res_calls, &mh, &buf);
+                                    if let Err(e) = write_message(&pipe, mh, buf) {
                                         error!("write_message got {:?}", e);
                                         quit_res.store(true, Ordering::SeqCst);
                                         break;
                                     }
+                                    metrics::message_sent(Side::Server, len);
                                 }
 
                                 trace!("response thread quit");
@@ -426,43 +772,70 @@ impl Server {
 
                             let (control_tx, control_rx): (SyncSender<()>, Receiver<()>) =
                                 sync_channel(0);
+                            let (cancel_tx, cancel_rx) = crossbeam::channel::unbounded::<()>();
+                            let stream_map: StreamMap = Arc::new(Mutex::new(HashMap::new()));
+                            let ctx = Arc::new(HandlerContext {
+                                connection: pipe_connection_child.clone(),
+                                quit: child_quit.clone(),
+                                lifecycle,
+                                methods,
+                                streams,
+                                stream_map: stream_map.clone(),
+                                res_tx,
+                                control_tx,
+                                cancel_rx,
+                                max_send: size_limits.max_send,
+                                calls,
+                                stream_slots,
+                            });
 
                             // start read message thread
-                            let quit_reader = child_quit.clone();
-                            let pipe_reader = pipe_connection_child.clone();
+                            let reader_ctx = ctx.clone();
+                            let reader_pool = pool.clone();
                             let (workload_tx, workload_rx): (WorkloadSender, WorkloadReceiver) =
                                 crossbeam::channel::unbounded();
-                            let (cancel_tx, cancel_rx) = crossbeam::channel::unbounded::<()>();
-                            let control_tx_reader = control_tx.clone();
                             let reader = thread::spawn(move || {
-                                while !quit_reader.load(Ordering::SeqCst) {
-                                    let msg = read_message(&pipe_reader);
+                                let ctx = reader_ctx;
+                                while !ctx.quit.load(Ordering::SeqCst) {
+                                    let msg = read_message(&ctx.connection, size_limits.max_recv);
+                                    if let Ok((mh, _)) = &msg {
+                                        let len = MESSAGE_HEADER_LENGTH + mh.length as usize;
+                                        metrics::message_received(Side::Server, len);
+                                    }
                                     match msg {
+                                        Ok((x, Ok(y))) if x.type_ == MESSAGE_TYPE_DATA => {
+                                            dispatch_data(x, y, &ctx.stream_map, &ctx.res_tx);
+                                        }
                                         Ok((x, y)) => {
-                                            let res = workload_tx.send((x, y));
-                                            match res {
-                                                Ok(_) => {}
-                                                Err(crossbeam::channel::SendError(e)) => {
-                                                    error!("Send workload error {:?}", e);
-                                                    quit_reader.store(true, Ordering::SeqCst);
-                                                    control_tx_reader.send(()).unwrap_or_else(
-                                                        |err| trace!("Failed to send {:?}", err),
-                                                    );
-                                                    break;
+                                            let stream_rx = open_stream(&x, &ctx.stream_map);
+                                            let workload = (x, y, stream_rx);
+                                            let res = match &reader_pool {
+                                                Some(pool) => {
+                                                    match pool.try_execute((ctx.clone(), workload)) {
+                                                        Ok(()) => Ok(()),
+                                                        Err((_, workload)) => {
+                                                            reject_overload(&ctx, workload)
+                                                        }
+                                                    }
                                                 }
+                                                None => workload_tx.send(workload).map_err(
+                                                    err_to_others_err!(e, "Send workload error "),
+                                                ),
+                                            };
+                                            if let Err(e) = res {
+                                                error!("dispatch request got {:?}", e);
+                                                ctx.quit_connection();
+                                                break;
                                             }
                                         }
                                         Err(x) => match x {
                                             Error::Socket(y) => {
                                                 trace!("Socket error {}", y);
                                                 drop(cancel_tx);
-                                                quit_reader.store(true, Ordering::SeqCst);
                                                 // the client connection would be closed and
                                                 // the connection dealing main thread would
                                                 // have exited.
-                                                control_tx_reader.send(()).unwrap_or_else(|err| {
-                                                    trace!("Failed to send {:?}", err)
-                                                });
+                                                ctx.quit_connection();
                                                 trace!("Socket error send control_tx");
                                                 break;
                                             }
@@ -477,24 +850,24 @@ impl Server {
                                 trace!("read message thread quit");
                             });
 
-                            let pipe = pipe_connection_child.clone();
                             let ts = ThreadS {
-                                connection: &pipe,
+                                ctx: &ctx,
                                 workload_rx: &workload_rx,
                                 wtc: &Arc::new(AtomicUsize::new(0)),
-                                methods: &methods,
-                                res_tx: &res_tx,
-                                control_tx: &control_tx,
-                                cancel_rx: &cancel_rx,
-                                quit: &child_quit,
                                 default,
                                 min,
                                 max,
                             };
-                            start_method_handler_threads(ts.default, &ts);
+                            // The requests are handled by the threads of the
+                            // connection, unless the server has a pool.
+                            if pool.is_none() {
+                                start_method_handler_threads(ts.default, &ts);
+                            }
 
                             while !child_quit.load(Ordering::SeqCst) {
-                                check_method_handler_threads(&ts);
+                                if pool.is_none() {
+                                    check_method_handler_threads(&ts);
+                                }
                                 if control_rx.recv().is_err() {
                                     break;
                                 }
@@ -502,18 +875,22 @@ impl Server {
                             // drop the control_rx, thus all of the method handler threads would
                             // terminated.
                             drop(control_rx);
-                            // drop the res_tx, thus the res_rx would get terminated notification.
-                            drop(res_tx);
+                            // drop the context and its res_tx, thus the res_rx would get
+                            // terminated notification.
+                            drop(ctx);
                             drop(workload_rx);
-                            handler.join().unwrap_or(());
                             reader.join().unwrap_or(());
+                            // close the opened streams, thus the stream handlers blocked on
+                            // receiving would return and release their res_tx.
+                            stream_map.lock().unwrap().clear();
+                            handler.join().unwrap_or(());
 
                             //wait untile this connection had been inserted connections map;
                             sync_rx.recv().unwrap_or(());
 
                             // client_handler should not close fd before exit
                             // , which prevent fd reuse issue.
-                            reaper_tx_child.send(pipe.id()).unwrap();
+                            reaper_tx_child.send(pipe_connection_child.id()).unwrap();
 
                             debug!("client thread quit");
                         })
@@ -553,6 +930,11 @@ impl Server {
     }
 
     pub fn start(&mut self) -> Result<()> {
+        if self.worker_pool.is_some_and(|(size, _, _)| size == 0) {
+            return Err(Error::Others(
+                "worker pool size should be bigger than 0".to_string(),
+            ));
+        }
         if self.thread_count_default >= self.thread_count_max {
             return Err(Error::Others(
                 "thread_count_default should smaller than thread_count_max".to_string(),
@@ -569,42 +951,50 @@ impl Server {
     }
 
     pub fn stop_listen(mut self) -> Self {
-        self.listener_quit_flag.store(true, Ordering::SeqCst);
+        self.lifecycle.stop_listen();
+        self.join_listener();
+        self
+    }
 
-        self.listeners[0]
-            .close()
-            .unwrap_or_else(|e| warn!("failed to close connection with error: {}", e));
+    pub fn disconnect(mut self) {
+        self.lifecycle.disconnect();
+        self.lifecycle.notify_shutdown();
+        self.join_reaper();
+    }
 
-        info!("close monitor");
-        if let Some(handler) = self.handler.take() {
-            handler.join().unwrap();
+    pub fn shutdown(self) {
+        self.stop_listen().disconnect();
+    }
+
+    /// Returns a handle shutting the server down from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            lifecycle: self.lifecycle.clone(),
         }
-        info!("listener thread stopped");
-        self
     }
 
-    pub fn disconnect(mut self) {
-        info!("begin to shutdown connection");
-        let connections = self.connections.lock().unwrap();
+    /// Blocks until the server is shut down through a [`ShutdownHandle`] and
+    /// all its connections and handler threads have exited.
+    pub fn wait(&mut self) {
+        self.lifecycle.wait_shutdown();
+        self.join_listener();
+        self.join_reaper();
+    }
 
-        for (_fd, c) in connections.iter() {
-            c.shutdown();
+    fn join_listener(&mut self) {
+        if let Some(handler) = self.handler.take() {
+            handler.join().unwrap();
         }
-        // release connections's lock, since the following handler.join()
-        // would wait on the other thread's exit in which would take the lock.
-        drop(connections);
-        info!("connections closed");
+        info!("listener thread stopped");
+    }
 
+    fn join_reaper(&mut self) {
         if let Some(r) = self.reaper.take() {
             drop(r.0);
             r.1.join().unwrap();
         }
         info!("reaper thread stopped");
     }
-
-    pub fn shutdown(self) {
-        self.stop_listen().disconnect();
-    }
 }
 
 #[cfg(unix)]
@@ -621,14 +1011,139 @@ impl AsRawFd for Server {
     }
 }
 
-fn quit_connection(quit: Arc<AtomicBool>, control_tx: SyncSender<()>) {
-    quit.store(true, Ordering::SeqCst);
-    // the client connection would be closed and
-    // the connection dealing main thread would
-    // have exited.
-    control_tx
-        .send(())
-        .unwrap_or_else(|err| debug!("Failed to send {:?}", err));
+/// Answers a request the worker pool has no room for.
+fn reject_overload(ctx: &HandlerContext, (mh, _, stream_rx): Workload) -> Result<()> {
+    if mh.type_ != MESSAGE_TYPE_REQUEST {
+        return Ok(());
+    }
+    let status = get_status(Code::RESOURCE_EXHAUSTED, "too many requests in flight");
+    ctx.reject(&mh, stream_rx, status)
+}
+
+/// Opens the stream before its request is handled, so that the data messages
+/// following a client streaming request wouldn't get lost.
+fn open_stream(mh: &MessageHeader, stream_map: &StreamMap) -> Option<ResultReceiver> {
+    if mh.type_ != MESSAGE_TYPE_REQUEST || (mh.flags & FLAG_REMOTE_OPEN) != FLAG_REMOTE_OPEN {
+        return None;
+    }
+
+    let (tx, rx): (ResultSender, ResultReceiver) = channel();
+    stream_map.lock().unwrap().insert(mh.stream_id, tx);
+    Some(rx)
+}
+
+fn dispatch_data(mh: MessageHeader, buf: Vec<u8>, stream_map: &StreamMap, res_tx: &MessageSender) {
+    let stream_id = mh.stream_id;
+    let status = if (mh.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED && !buf.is_empty() {
+        get_status(
+            Code::INVALID_ARGUMENT,
+            format!("Stream id {stream_id}: data close message cannot include data"),
+        )
+    } else {
+        let stream_tx = stream_map.lock().unwrap().get(&stream_id).cloned();
+        let msg = GenMessage {
+            header: mh,
+            payload: buf,
+        };
+        match stream_tx.map(|tx| tx.send(Ok(msg))) {
+            Some(Ok(_)) => return,
+            _ => get_status(Code::INVALID_ARGUMENT, "Stream is no longer active"),
+        }
+    };
+
+    let mut res = Response::new();
+    res.set_status(status);
+    response_to_channel(stream_id, res, res_tx.clone())
+        .unwrap_or_else(|e| debug!("response_to_channel get error {:?}", e));
+}
+
+fn handle_stream(
+    stream: &(dyn StreamHandler + Send + Sync),
+    ctx: TtrpcContext,
+    payload: Vec<u8>,
+    stream_rx: Option<ResultReceiver>,
+    stream_map: &StreamMap,
+    res_tx: &MessageSender,
+    max_send: usize,
+) -> Result<()> {
+    let stream_id = ctx.mh.stream_id;
+    let no_data = (ctx.mh.flags & FLAG_NO_DATA) == FLAG_NO_DATA;
+
+    let (stream_tx, stream_rx) = match stream_rx {
+        Some(rx) => (stream_map.lock().unwrap().get(&stream_id).cloned(), rx),
+        None => {
+            let (tx, rx): (ResultSender, ResultReceiver) = channel();
+            stream_map.lock().unwrap().insert(stream_id, tx.clone());
+            (Some(tx), rx)
+        }
+    };
+
+    // Fake the first data message. The sender isn't kept, so that the stream
+    // ends once it is removed from the stream map, e.g. on disconnection.
+    if let Some(tx) = stream_tx.filter(|_| !no_data) {
+        let msg = GenMessage {
+            header: MessageHeader::new_data(stream_id, payload.len() as u32),
+            payload,
+        };
+        tx.send(Ok(msg)).unwrap_or(());
+    }
+
+    let si = StreamInner::new(
+        stream_id,
+        res_tx.clone(),
+        stream_rx,
+        true,
+        true,
+        Kind::Server,
+        stream_map.clone(),
+        None,
+    )
+    .with_max_send(max_send);
+
+    match stream.handler(ctx, si) {
+        Ok(None) => {
+            let mut mh = MessageHeader::new_data(stream_id, 0);
+            mh.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+            res_tx
+                .send((mh, Vec::new()))
+                .map_err(err_to_others_err!(e, "Send packet to sender error "))
+        }
+        Ok(Some(res)) => response_to_channel(stream_id, res, res_tx.clone()),
+        Err(e) => response_error_to_channel(stream_id, e, res_tx.clone()),
+    }
+}
+
+/// Finishes the record of the call closed by a message, if any.
+fn finish_call(calls: &CallRecords, mh: &MessageHeader, buf: &[u8]) {
+    let remote_closed = (mh.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED;
+    if mh.type_ != MESSAGE_TYPE_RESPONSE && !(mh.type_ == MESSAGE_TYPE_DATA && remote_closed) {
+        return;
+    }
+    let Some(call) = calls.lock().unwrap().remove(&mh.stream_id) else {
+        return;
+    };
+    let code = if mh.type_ == MESSAGE_TYPE_RESPONSE {
+        Response::parse_from_bytes(buf).map_or(Code::UNKNOWN, |res| res.status().code())
+    } else {
+        Code::OK
+    };
+    call.finish(code);
+}
+
+/// Replaces a response longer than `max_len` by an error, the handlers encode
+/// the responses without knowing the limit.
+fn limit_response(mh: MessageHeader, buf: Vec<u8>, max_len: usize) -> (MessageHeader, Vec<u8>) {
+    if mh.type_ != MESSAGE_TYPE_RESPONSE {
+        return (mh, buf);
+    }
+    match check_oversize(buf.len(), max_len, true) {
+        Ok(()) => (mh, buf),
+        Err(e) => {
+            let res: Response = e.into();
+            let buf = res.write_to_bytes().unwrap_or_default();
+            (MessageHeader::new_response(mh.stream_id, buf.len() as u32), buf)
+        }
+    }
 }
 
 fn is_resource_limit_error(e: std::io::Error) -> bool {
@@ -638,3 +1153,437 @@ fn is_resource_limit_error(e: std::io::Error) -> bool {
 
     false
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::sync::Client;
+
+    struct Sleep;
+
+    impl MethodHandler for Sleep {
+        fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<()> {
+            thread::sleep(Duration::from_millis(200));
+            response_to_channel(ctx.mh.stream_id, Response::new(), ctx.res_tx)
+        }
+    }
+
+    #[test]
+    fn test_remove_service() {
+        let addr = "memory://sync-server-unit-test-services";
+        let mut server = Server::new().bind(addr).unwrap();
+        server.start().unwrap();
+        let client = Client::connect(addr).unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+        let code = |res: Result<Response>| match res {
+            Err(Error::RpcStatus(status)) => status.code(),
+            res => panic!("unexpected response {:?}", res),
+        };
+        assert_eq!(code(client.request(req.clone())), Code::UNIMPLEMENTED);
+
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Sleep".to_string(), Box::new(Sleep));
+        server.add_service(methods);
+        assert!(client.request(req.clone()).is_ok());
+
+        // The call in flight completes after the removal.
+        let in_flight = thread::spawn({
+            let (client, req) = (client.clone(), req.clone());
+            move || client.request(req)
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(server.remove_service("test"));
+        assert!(!server.remove_service("test"));
+        assert!(in_flight.join().unwrap().is_ok());
+        assert_eq!(code(client.request(req)), Code::UNIMPLEMENTED);
+        server.shutdown();
+    }
+
+    fn sleep_server(addr: &str) -> Server {
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Sleep".to_string(), Box::new(Sleep));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(methods);
+        server.start().unwrap();
+        server
+    }
+
+    #[test]
+    fn test_shutdown_handle() {
+        let addr = "memory://sync-server-unit-test-shutdown";
+        let mut server = sleep_server(addr);
+        let handle = server.shutdown_handle();
+        assert!(!handle.is_shutdown());
+
+        let signal = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.shutdown();
+            handle
+        });
+        server.wait();
+        assert!(signal.join().unwrap().is_shutdown());
+        assert!(Client::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_drain() {
+        let addr = "memory://sync-server-unit-test-drain";
+        let mut server = sleep_server(addr);
+        let client = Client::connect(addr).unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+
+        let in_flight = thread::spawn({
+            let (client, req) = (client.clone(), req.clone());
+            move || client.request(req)
+        });
+        thread::sleep(Duration::from_millis(50));
+        let drain = thread::spawn({
+            let handle = server.shutdown_handle();
+            move || handle.drain(Duration::from_secs(5))
+        });
+        thread::sleep(Duration::from_millis(50));
+
+        match client.request(req) {
+            Err(Error::RpcStatus(status)) => assert_eq!(status.code(), Code::UNAVAILABLE),
+            res => panic!("unexpected response {:?}", res),
+        }
+        assert!(Client::connect(addr).is_err());
+        assert!(in_flight.join().unwrap().is_ok());
+        assert!(drain.join().unwrap());
+        server.wait();
+    }
+
+    #[test]
+    fn test_worker_pool() {
+        let addr = "memory://sync-server-unit-test-pool";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Sleep".to_string(), Box::new(Sleep));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(methods)
+            .set_worker_pool(1, 1, 2);
+        server.start().unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+
+        // One call in flight and one queued, from other connections.
+        let calls: Vec<_> = (0..2)
+            .map(|_| {
+                let (client, req) = (Client::connect(addr).unwrap(), req.clone());
+                let call = thread::spawn(move || client.request(req));
+                thread::sleep(Duration::from_millis(50));
+                call
+            })
+            .collect();
+        let client = Client::connect(addr).unwrap();
+        match client.request(req.clone()) {
+            Err(Error::RpcStatus(status)) => assert_eq!(status.code(), Code::RESOURCE_EXHAUSTED),
+            res => panic!("unexpected response {:?}", res),
+        }
+
+        for call in calls {
+            assert!(call.join().unwrap().is_ok());
+        }
+        assert!(client.request(req).is_ok());
+        server.shutdown();
+    }
+
+    struct Hold;
+
+    impl StreamHandler for Hold {
+        fn handler(&self, _ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+            // The messages until the stream is closed.
+            while inner.recv().is_ok() {}
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_shutdown_streams() {
+        let addr = "memory://sync-server-unit-test-shutdown-streams";
+        let mut streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>> = HashMap::new();
+        streams.insert("/test/Hold".to_string(), Arc::new(Hold));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_stream_service(streams);
+        server.start().unwrap();
+        let client = Client::connect(addr).unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Hold".to_string(),
+            ..Default::default()
+        };
+
+        // The handler waiting for the messages of the client returns once
+        // the connection is closed.
+        let _stream = client.new_stream(req, true, false).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_worker_pool_streams() {
+        let addr = "memory://sync-server-unit-test-pool-streams";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Sleep".to_string(), Box::new(Sleep));
+        let mut streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>> = HashMap::new();
+        streams.insert("/test/Hold".to_string(), Arc::new(Hold));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(methods)
+            .register_stream_service(streams)
+            .set_worker_pool(1, 1, 2);
+        server.start().unwrap();
+        let client = Client::connect(addr).unwrap();
+        let req = |method: &str| Request {
+            service: "test".to_string(),
+            method: method.to_string(),
+            ..Default::default()
+        };
+
+        // More streams open than the pool has workers and room in its queue.
+        let streams: Vec<_> = (0..2)
+            .map(|_| {
+                let stream = client.new_stream(req("Hold"), true, true).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                stream
+            })
+            .collect();
+        assert!(client.request(req("Sleep")).is_ok());
+
+        // But no more than the threads of the streams.
+        let mut stream = client.new_stream(req("Hold"), true, true).unwrap();
+        match stream.recv() {
+            Err(Error::RpcStatus(status)) => assert_eq!(status.code(), Code::RESOURCE_EXHAUSTED),
+            res => panic!("unexpected response {:?}", res),
+        }
+
+        // The threads are given back once the streams end.
+        for mut stream in streams {
+            stream.close_send().unwrap();
+            assert!(matches!(stream.recv(), Err(Error::Eof)));
+        }
+        thread::sleep(Duration::from_millis(50));
+        let mut stream = client.new_stream(req("Hold"), true, true).unwrap();
+        stream.close_send().unwrap();
+        assert!(matches!(stream.recv(), Err(Error::Eof)));
+        server.shutdown();
+    }
+
+    struct Sum;
+
+    impl StreamHandler for Sum {
+        fn handler(&self, _ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+            let mut sum = 0;
+            while let Ok(buf) = inner.recv() {
+                sum += buf.iter().sum::<u8>();
+            }
+            Ok(Some(Response {
+                payload: vec![sum],
+                ..Default::default()
+            }))
+        }
+    }
+
+    struct Count;
+
+    impl StreamHandler for Count {
+        fn handler(&self, _ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+            let n = inner.recv()?[0];
+            for i in 0..n {
+                inner.send(vec![i])?;
+            }
+            Ok(None)
+        }
+    }
+
+    struct Echo;
+
+    impl StreamHandler for Echo {
+        fn handler(&self, _ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+            while let Ok(buf) = inner.recv() {
+                inner.send(buf)?;
+            }
+            Ok(None)
+        }
+    }
+
+    fn start_stream_server(addr: &str) -> Server {
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Sleep".to_string(), Box::new(Sleep));
+        let mut streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>> = HashMap::new();
+        streams.insert("/test/Sum".to_string(), Arc::new(Sum));
+        streams.insert("/test/Count".to_string(), Arc::new(Count));
+        streams.insert("/test/Echo".to_string(), Arc::new(Echo));
+        streams.insert("/test/Hold".to_string(), Arc::new(Hold));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(methods)
+            .register_stream_service(streams)
+            .set_thread_count_default(2)
+            .set_thread_count_min(1)
+            .set_thread_count_max(3);
+        server.start().unwrap();
+        server
+    }
+
+    fn stream_req(method: &str, payload: Vec<u8>) -> Request {
+        Request {
+            service: "test".to_string(),
+            method: method.to_string(),
+            payload,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_client_stream() {
+        let addr = "memory://sync-server-unit-test-client-stream";
+        let server = start_stream_server(addr);
+        let client = Client::connect(addr).unwrap();
+
+        let mut stream = client
+            .new_stream(stream_req("Sum", Vec::new()), true, false)
+            .unwrap();
+        for i in 1..=3 {
+            stream.send(vec![i]).unwrap();
+        }
+        stream.close_send().unwrap();
+        assert_eq!(stream.recv().unwrap(), vec![6]);
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_server_stream() {
+        let addr = "memory://sync-server-unit-test-server-stream";
+        let server = start_stream_server(addr);
+        let client = Client::connect(addr).unwrap();
+
+        let mut stream = client
+            .new_stream(stream_req("Count", vec![3]), false, true)
+            .unwrap();
+        for i in 0..3 {
+            assert_eq!(stream.recv().unwrap(), vec![i]);
+        }
+        assert!(matches!(stream.recv(), Err(Error::Eof)));
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_duplex_stream() {
+        let addr = "memory://sync-server-unit-test-duplex-stream";
+        let server = start_stream_server(addr);
+        let client = Client::connect(addr).unwrap();
+
+        let mut stream = client
+            .new_stream(stream_req("Echo", Vec::new()), true, true)
+            .unwrap();
+        for i in 0..3 {
+            stream.send(vec![i]).unwrap();
+            assert_eq!(stream.recv().unwrap(), vec![i]);
+        }
+        stream.close_send().unwrap();
+        assert!(matches!(stream.recv(), Err(Error::Eof)));
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_connection_streams() {
+        let addr = "memory://sync-server-unit-test-connection-streams";
+        let server = start_stream_server(addr);
+        let client = Client::connect(addr).unwrap();
+
+        // As many streams open as the connection has threads, the unary
+        // calls are still handled.
+        let _streams: Vec<_> = (0..3)
+            .map(|_| {
+                let stream = client
+                    .new_stream(stream_req("Hold", Vec::new()), true, true)
+                    .unwrap();
+                thread::sleep(Duration::from_millis(50));
+                stream
+            })
+            .collect();
+        assert!(client.request(stream_req("Sleep", Vec::new())).is_ok());
+
+        // But no more streams.
+        let mut stream = client
+            .new_stream(stream_req("Hold", Vec::new()), true, true)
+            .unwrap();
+        match stream.recv() {
+            Err(Error::RpcStatus(status)) => assert_eq!(status.code(), Code::RESOURCE_EXHAUSTED),
+            res => panic!("unexpected response {:?}", res),
+        }
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_metrics() {
+        let registry = crate::metrics::test_registry();
+        let addr = "memory://sync-server-unit-test-metrics";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/sync-metrics/Sleep".to_string(), Box::new(Sleep));
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(methods);
+        server.start().unwrap();
+        let client = Client::connect(addr).unwrap();
+        let mut req = Request {
+            service: "sync-metrics".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+        assert!(client.request(req.clone()).is_ok());
+        req.method = "Unknown".to_string();
+        assert!(client.request(req).is_err());
+        server.shutdown();
+
+        let text = registry.render();
+        for line in [
+            r#"ttrpc_requests_total{side="server",method="/sync-metrics/Sleep",code="OK"} 1"#,
+            r#"ttrpc_requests_total{side="client",method="/sync-metrics/Sleep",code="OK"} 1"#,
+            r#"ttrpc_requests_total{side="client",method="/sync-metrics/Unknown",code="UNIMPLEMENTED"} 1"#,
+            r#"ttrpc_requests_in_flight{side="server",method="/sync-metrics/Sleep"} 0"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+        }
+        assert!(!text.contains(r#"side="server",method="/sync-metrics/Unknown""#));
+        assert!(text.contains(r#"ttrpc_sent_bytes_total{side="client"}"#));
+    }
+
+    #[test]
+    fn test_drain_timeout() {
+        let addr = "memory://sync-server-unit-test-drain-timeout";
+        let mut server = sleep_server(addr);
+        let client = Client::connect(addr).unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Sleep".to_string(),
+            ..Default::default()
+        };
+
+        let in_flight = thread::spawn(move || client.request(req));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!server.shutdown_handle().drain(Duration::from_millis(10)));
+        server.wait();
+        in_flight.join().unwrap().ok();
+    }
+}
@@ -0,0 +1,510 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Streams of the sync server and client.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::metrics::CallRecord;
+use crate::proto::{
+    check_oversize, Code, Codec, GenMessage, MessageHeader, Response, FLAG_NO_DATA,
+    FLAG_REMOTE_CLOSED, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
+};
+use crate::sync::sys::ClientConnection;
+
+pub(crate) type MessageSender = mpsc::Sender<(MessageHeader, Vec<u8>)>;
+
+pub(crate) type ResultSender = mpsc::Sender<Result<GenMessage>>;
+pub(crate) type ResultReceiver = mpsc::Receiver<Result<GenMessage>>;
+
+pub(crate) type StreamMap = Arc<Mutex<HashMap<u32, ResultSender>>>;
+
+#[derive(Debug)]
+pub struct ClientStream<Q, P> {
+    tx: CSSender<Q>,
+    rx: CSReceiver<P>,
+}
+
+impl<Q, P> ClientStream<Q, P>
+where
+    Q: Codec,
+    P: Codec,
+    <Q as Codec>::E: std::fmt::Display,
+    <P as Codec>::E: std::fmt::Display,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        let (tx, rx) = inner.split();
+        Self {
+            tx: CSSender {
+                tx,
+                _send: PhantomData,
+            },
+            rx: CSReceiver {
+                rx,
+                _recv: PhantomData,
+            },
+        }
+    }
+
+    pub fn split(self) -> (CSSender<Q>, CSReceiver<P>) {
+        (self.tx, self.rx)
+    }
+
+    pub fn send(&self, req: &Q) -> Result<()> {
+        self.tx.send(req)
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        self.tx.close_send()
+    }
+
+    pub fn recv(&mut self) -> Result<P> {
+        self.rx.recv()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CSSender<Q> {
+    tx: StreamSender,
+    _send: PhantomData<Q>,
+}
+
+impl<Q> CSSender<Q>
+where
+    Q: Codec,
+    <Q as Codec>::E: std::fmt::Display,
+{
+    pub fn send(&self, req: &Q) -> Result<()> {
+        let msg_buf = req
+            .encode()
+            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        self.tx.send(msg_buf)
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        self.tx.close_send()
+    }
+}
+
+#[derive(Debug)]
+pub struct CSReceiver<P> {
+    rx: StreamReceiver,
+    _recv: PhantomData<P>,
+}
+
+impl<P> CSReceiver<P>
+where
+    P: Codec,
+    <P as Codec>::E: std::fmt::Display,
+{
+    pub fn recv(&mut self) -> Result<P> {
+        let msg_buf = self.rx.recv()?;
+        P::decode(msg_buf).map_err(err_to_others_err!(e, "Decode message failed."))
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerStream<P, Q> {
+    tx: SSSender<P>,
+    rx: SSReceiver<Q>,
+}
+
+impl<P, Q> ServerStream<P, Q>
+where
+    P: Codec,
+    Q: Codec,
+    <P as Codec>::E: std::fmt::Display,
+    <Q as Codec>::E: std::fmt::Display,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        let (tx, rx) = inner.split();
+        Self {
+            tx: SSSender {
+                tx,
+                _send: PhantomData,
+            },
+            rx: SSReceiver {
+                rx,
+                _recv: PhantomData,
+            },
+        }
+    }
+
+    pub fn split(self) -> (SSSender<P>, SSReceiver<Q>) {
+        (self.tx, self.rx)
+    }
+
+    pub fn send(&self, resp: &P) -> Result<()> {
+        self.tx.send(resp)
+    }
+
+    pub fn recv(&mut self) -> Result<Option<Q>> {
+        self.rx.recv()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SSSender<P> {
+    tx: StreamSender,
+    _send: PhantomData<P>,
+}
+
+impl<P> SSSender<P>
+where
+    P: Codec,
+    <P as Codec>::E: std::fmt::Display,
+{
+    pub fn send(&self, resp: &P) -> Result<()> {
+        let msg_buf = resp
+            .encode()
+            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        self.tx.send(msg_buf)
+    }
+}
+
+#[derive(Debug)]
+pub struct SSReceiver<Q> {
+    rx: StreamReceiver,
+    _recv: PhantomData<Q>,
+}
+
+impl<Q> SSReceiver<Q>
+where
+    Q: Codec,
+    <Q as Codec>::E: std::fmt::Display,
+{
+    pub fn recv(&mut self) -> Result<Option<Q>> {
+        let res = self.rx.recv();
+
+        if matches!(res, Err(Error::Eof)) {
+            return Ok(None);
+        }
+        let msg_buf = res?;
+        Q::decode(msg_buf)
+            .map_err(err_to_others_err!(e, "Decode message failed."))
+            .map(Some)
+    }
+}
+
+pub struct ClientStreamSender<Q, P> {
+    inner: StreamInner,
+    _send: PhantomData<Q>,
+    _recv: PhantomData<P>,
+}
+
+impl<Q, P> ClientStreamSender<Q, P>
+where
+    Q: Codec,
+    P: Codec,
+    <Q as Codec>::E: std::fmt::Display,
+    <P as Codec>::E: std::fmt::Display,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner,
+            _send: PhantomData,
+            _recv: PhantomData,
+        }
+    }
+
+    pub fn send(&self, req: &Q) -> Result<()> {
+        let msg_buf = req
+            .encode()
+            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        self.inner.send(msg_buf)
+    }
+
+    pub fn close_and_recv(&mut self) -> Result<P> {
+        self.inner.close_send()?;
+        let msg_buf = self.inner.recv()?;
+        P::decode(msg_buf).map_err(err_to_others_err!(e, "Decode message failed."))
+    }
+}
+
+pub struct ServerStreamSender<P> {
+    inner: StreamSender,
+    _send: PhantomData<P>,
+}
+
+impl<P> ServerStreamSender<P>
+where
+    P: Codec,
+    <P as Codec>::E: std::fmt::Display,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner: inner.split().0,
+            _send: PhantomData,
+        }
+    }
+
+    pub fn send(&self, resp: &P) -> Result<()> {
+        let msg_buf = resp
+            .encode()
+            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        self.inner.send(msg_buf)
+    }
+}
+
+pub struct ClientStreamReceiver<P> {
+    inner: StreamReceiver,
+    _recv: PhantomData<P>,
+}
+
+impl<P> ClientStreamReceiver<P>
+where
+    P: Codec,
+    <P as Codec>::E: std::fmt::Display,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner: inner.split().1,
+            _recv: PhantomData,
+        }
+    }
+
+    pub fn recv(&mut self) -> Result<Option<P>> {
+        let res = self.inner.recv();
+        if matches!(res, Err(Error::Eof)) {
+            return Ok(None);
+        }
+        let msg_buf = res?;
+        P::decode(msg_buf)
+            .map_err(err_to_others_err!(e, "Decode message failed."))
+            .map(Some)
+    }
+}
+
+pub struct ServerStreamReceiver<Q> {
+    inner: StreamReceiver,
+    _recv: PhantomData<Q>,
+}
+
+impl<Q> ServerStreamReceiver<Q>
+where
+    Q: Codec,
+    <Q as Codec>::E: std::fmt::Display,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner: inner.split().1,
+            _recv: PhantomData,
+        }
+    }
+
+    pub fn recv(&mut self) -> Result<Option<Q>> {
+        let res = self.inner.recv();
+        if matches!(res, Err(Error::Eof)) {
+            return Ok(None);
+        }
+        let msg_buf = res?;
+        Q::decode(msg_buf)
+            .map_err(err_to_others_err!(e, "Decode message failed."))
+            .map(Some)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Client,
+    Server,
+}
+
+#[derive(Debug)]
+pub struct StreamInner {
+    sender: StreamSender,
+    receiver: StreamReceiver,
+}
+
+impl StreamInner {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        stream_id: u32,
+        tx: MessageSender,
+        rx: ResultReceiver,
+        sendable: bool,
+        recveivable: bool,
+        kind: Kind,
+        streams: StreamMap,
+        connection: Option<Arc<ClientConnection>>,
+    ) -> Self {
+        Self {
+            sender: StreamSender {
+                tx,
+                stream_id,
+                sendable,
+                local_closed: Arc::new(AtomicBool::new(false)),
+                kind,
+                max_send: MESSAGE_LENGTH_MAX,
+            },
+            receiver: StreamReceiver {
+                rx,
+                stream_id,
+                recveivable,
+                remote_closed: false,
+                kind,
+                streams,
+                _connection: connection,
+                call: None,
+            },
+        }
+    }
+
+    /// Sets the maximum size of the messages sent on the stream.
+    pub(crate) fn with_max_send(mut self, max_send: usize) -> Self {
+        self.sender.max_send = max_send;
+        self
+    }
+
+    /// Sets the record of the call, finished once the stream is closed by
+    /// the server.
+    pub(crate) fn with_call(mut self, call: Option<CallRecord>) -> Self {
+        self.receiver.call = call;
+        self
+    }
+
+    fn split(self) -> (StreamSender, StreamReceiver) {
+        (self.sender, self.receiver)
+    }
+
+    pub fn send(&self, buf: Vec<u8>) -> Result<()> {
+        self.sender.send(buf)
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        self.sender.close_send()
+    }
+
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        self.receiver.recv()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamSender {
+    tx: MessageSender,
+    stream_id: u32,
+    sendable: bool,
+    local_closed: Arc<AtomicBool>,
+    kind: Kind,
+    max_send: usize,
+}
+
+pub struct StreamReceiver {
+    rx: ResultReceiver,
+    stream_id: u32,
+    recveivable: bool,
+    remote_closed: bool,
+    kind: Kind,
+    streams: StreamMap,
+    // Keep the client connection alive while the stream is in use.
+    _connection: Option<Arc<ClientConnection>>,
+    call: Option<CallRecord>,
+}
+
+impl std::fmt::Debug for StreamReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamReceiver")
+            .field("stream_id", &self.stream_id)
+            .field("recveivable", &self.recveivable)
+            .field("remote_closed", &self.remote_closed)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl Drop for StreamReceiver {
+    fn drop(&mut self) {
+        self.streams.lock().unwrap().remove(&self.stream_id);
+    }
+}
+
+impl StreamSender {
+    pub fn send(&self, buf: Vec<u8>) -> Result<()> {
+        debug_assert!(self.sendable);
+        if self.local_closed.load(Ordering::Relaxed) {
+            debug_assert_eq!(self.kind, Kind::Client);
+            return Err(Error::LocalClosed);
+        }
+        check_oversize(buf.len(), self.max_send, true)?;
+
+        let header = MessageHeader::new_data(self.stream_id, buf.len() as u32);
+        self.tx
+            .send((header, buf))
+            .map_err(err_to_others_err!(e, "Send data packet to sender error "))
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        debug_assert_eq!(self.kind, Kind::Client);
+        debug_assert!(self.sendable);
+        if self.local_closed.load(Ordering::Relaxed) {
+            return Err(Error::LocalClosed);
+        }
+        let mut header = MessageHeader::new_data(self.stream_id, 0);
+        header.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+        self.tx
+            .send((header, Vec::new()))
+            .map_err(err_to_others_err!(e, "Send data packet to sender error "))?;
+        self.local_closed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl StreamReceiver {
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        if self.remote_closed {
+            return Err(Error::RemoteClosed);
+        }
+        let msg = self.rx.recv().unwrap_or_else(|_| {
+            Err(Error::Others(
+                "Receive packet from Receiver error".to_string(),
+            ))
+        })?;
+
+        let payload = match msg.header.type_ {
+            MESSAGE_TYPE_RESPONSE => {
+                debug_assert_eq!(self.kind, Kind::Client);
+                self.remote_closed = true;
+                let resp = Response::decode(&msg.payload)
+                    .map_err(err_to_others_err!(e, "Decode message failed."))?;
+                self.finish_call(resp.status.as_ref().map_or(Code::OK, |s| s.code()));
+                if let Some(status) = resp.status.as_ref() {
+                    if status.code() != Code::OK {
+                        return Err(Error::RpcStatus((*status).clone()));
+                    }
+                }
+                resp.payload
+            }
+            MESSAGE_TYPE_DATA => {
+                if !self.recveivable {
+                    self.remote_closed = true;
+                    return Err(Error::Others(
+                        "received data from non-streaming server.".to_string(),
+                    ));
+                }
+                if (msg.header.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED {
+                    self.remote_closed = true;
+                    self.finish_call(Code::OK);
+                    if (msg.header.flags & FLAG_NO_DATA) == FLAG_NO_DATA {
+                        return Err(Error::Eof);
+                    }
+                }
+                msg.payload
+            }
+            _ => {
+                return Err(Error::Others("not support".to_string()));
+            }
+        };
+        Ok(payload)
+    }
+
+    fn finish_call(&mut self, code: Code) {
+        if let Some(call) = self.call.take() {
+            call.finish(code);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! In-process listeners, for the `memory://name` addresses.
+//!
+//! Nothing is bound: the client creates a socket pair and hands one end to
+//! the listener of the name, whose fd is a pipe signaled for each of them.
+
+use std::collections::HashMap;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use nix::sys::socket::{socketpair, AddressFamily, SockType};
+use nix::unistd::{close, read, write};
+
+use super::net::PipeListener;
+use crate::common::SOCK_CLOEXEC;
+use crate::error::{Error, Result};
+
+/// The listener of a name, signaled through `notify`.
+struct Entry {
+    id: u64,
+    tx: Sender<RawFd>,
+    notify: OwnedFd,
+}
+
+fn listeners() -> &'static Mutex<HashMap<String, Entry>> {
+    static LISTENERS: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+    LISTENERS.get_or_init(Default::default)
+}
+
+pub(crate) struct MemoryListener {
+    id: u64,
+    name: String,
+    fd: RawFd,
+    rx: Receiver<RawFd>,
+}
+
+impl MemoryListener {
+    pub(crate) fn bind(name: &str) -> Result<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let mut listeners = listeners().lock().unwrap();
+        if listeners.contains_key(name) {
+            return Err(Error::Others(format!(
+                "memory listener {name:?} is already bound"
+            )));
+        }
+        let (fd, notify) = PipeListener::new_monitor_fd()?;
+        let notify = unsafe { OwnedFd::from_raw_fd(notify) };
+        let (tx, rx) = unbounded();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        listeners.insert(name.to_string(), Entry { id, tx, notify });
+        Ok(MemoryListener {
+            id,
+            name: name.to_string(),
+            fd,
+            rx,
+        })
+    }
+
+    /// The fd to poll, readable when a connection is pending.
+    pub(crate) fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Returns the server end of a pending connection.
+    pub(crate) fn accept(&self) -> Option<RawFd> {
+        let mut buf = [0u8];
+        read(self.fd, &mut buf).ok()?;
+        self.rx.try_recv().ok()
+    }
+
+    /// Stops accepting, the connections already established are kept.
+    pub(crate) fn unbind(&self) {
+        let mut listeners = listeners().lock().unwrap();
+        if listeners.get(&self.name).map(|e| e.id) == Some(self.id) {
+            listeners.remove(&self.name);
+        }
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        self.unbind();
+        close(self.fd).unwrap_or_else(|e| warn!("failed to close memory listener: {}", e));
+        for fd in self.rx.try_iter() {
+            let _ = close(fd);
+        }
+    }
+}
+
+/// Connects to the listener of `name`, returns the client end.
+pub(crate) fn connect(name: &str) -> Result<RawFd> {
+    let listeners = listeners().lock().unwrap();
+    let entry = listeners
+        .get(name)
+        .ok_or_else(|| Error::Others(format!("memory listener {name:?} is not bound")))?;
+
+    let (client, server) = socketpair(AddressFamily::Unix, SockType::Stream, None, SOCK_CLOEXEC)?;
+    #[cfg(target_os = "macos")]
+    {
+        crate::common::set_fd_close_exec(client)?;
+        crate::common::set_fd_close_exec(server)?;
+    }
+    if entry.tx.send(server).is_err() || write(entry.notify.as_raw_fd(), &[0]).is_err() {
+        let _ = close(client);
+        let _ = close(server);
+        return Err(Error::Others(format!(
+            "memory listener {name:?} is closed"
+        )));
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::peer::PeerInfo;
+    use crate::proto::{Request, Response};
+    use crate::sync::{response_to_channel, Client, MethodHandler, Server, TtrpcContext};
+
+    struct Peer;
+
+    impl MethodHandler for Peer {
+        fn handler(&self, ctx: TtrpcContext, _req: Request) -> crate::Result<()> {
+            let res = Response {
+                payload: vec![(ctx.peer == PeerInfo::InProcess) as u8],
+                ..Default::default()
+            };
+            response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        assert!(Client::connect("memory://sync-test").is_err());
+
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("/test/Peer".to_string(), Box::new(Peer));
+        let mut server = Server::new()
+            .bind("memory://sync-test")
+            .unwrap()
+            .register_service(methods);
+        server.start().unwrap();
+        assert!(Server::new().bind("memory://sync-test").is_err());
+
+        let client = Client::connect("memory://sync-test").unwrap();
+        let req = Request {
+            service: "test".to_string(),
+            method: "Peer".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(client.request(req).unwrap().payload, vec![1]);
+
+        server.shutdown();
+        assert!(Client::connect("memory://sync-test").is_err());
+    }
+}
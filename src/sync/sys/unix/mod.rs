@@ -1,2 +1,3 @@
+mod memory;
 mod net;
 pub use net::{PipeConnection, PipeListener, ClientConnection};
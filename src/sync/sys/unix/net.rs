@@ -14,6 +14,7 @@
 	limitations under the License.
 */
 use crate::error::Result;
+use crate::peer::PeerInfo;
 use nix::sys::socket::*;
 use std::io::{self};
 use std::os::unix::io::RawFd;
@@ -22,6 +23,7 @@ use nix::Error;
 
 use nix::unistd::*;
 use crate::common::{self, client_connect, SOCK_CLOEXEC};
+use super::memory::{self, MemoryListener};
 #[cfg(target_os = "macos")] 
 use crate::common::set_fd_close_exec;
 use nix::sys::socket::{self};
@@ -32,6 +34,7 @@ const POLL_MAX_TIME: i32 = 10;
 pub struct PipeListener {
     fd: RawFd,
     monitor_fd: (RawFd, RawFd),
+    memory: Option<MemoryListener>,
 }
 
 impl AsRawFd for PipeListener {
@@ -42,6 +45,15 @@ impl AsRawFd for PipeListener {
 
 impl PipeListener {
     pub(crate) fn new(sockaddr: &str) -> Result<PipeListener> {
+        if let Some(name) = sockaddr.strip_prefix("memory://") {
+            let memory = MemoryListener::bind(name)?;
+            return Ok(PipeListener {
+                fd: memory.fd(),
+                monitor_fd: PipeListener::new_monitor_fd()?,
+                memory: Some(memory),
+            });
+        }
+
         let (fd, _) = common::do_bind(sockaddr)?;
         common::do_listen(fd)?;
 
@@ -50,6 +62,7 @@ impl PipeListener {
         Ok(PipeListener {
             fd,
             monitor_fd: fds,
+            memory: None,
         })
     }
 
@@ -59,10 +72,11 @@ impl PipeListener {
         Ok(PipeListener {
             fd,
             monitor_fd: fds,
+            memory: None,
         })
     }
 
-    fn new_monitor_fd() ->  Result<(i32, i32)> {
+    pub(super) fn new_monitor_fd() ->  Result<(i32, i32)> {
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let fds = pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
  
@@ -121,6 +135,13 @@ impl PipeListener {
             return Ok(None);
         }
 
+        if let Some(memory) = &self.memory {
+            return Ok(memory.accept().map(|fd| PipeConnection {
+                fd,
+                peer: PeerInfo::InProcess,
+            }));
+        }
+
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let fd = match accept4(self.fd, SockFlag::SOCK_CLOEXEC) {
             Ok(fd) => fd,
@@ -149,10 +170,16 @@ impl PipeListener {
         };
 
 
-        Ok(Some(PipeConnection { fd }))
+        Ok(Some(PipeConnection {
+            fd,
+            peer: PeerInfo::from_fd(fd),
+        }))
     }
 
     pub fn close(&self) -> Result<()> {
+        if let Some(memory) = &self.memory {
+            memory.unbind();
+        }
         close(self.monitor_fd.1).unwrap_or_else(|e| {
             warn!(
                 "failed to close notify fd: {} with error: {}",
@@ -166,17 +193,26 @@ impl PipeListener {
 
 pub struct PipeConnection {
     fd: RawFd,
+    peer: PeerInfo,
 }
 
 impl PipeConnection {
     pub(crate) fn new(fd: RawFd) -> PipeConnection {
-        PipeConnection { fd }
+        PipeConnection {
+            fd,
+            peer: PeerInfo::Unknown,
+        }
     }
 
     pub(crate) fn id(&self) -> i32 {
         self.fd
     }
 
+    /// The peer captured when the connection was accepted.
+    pub(crate) fn peer(&self) -> PeerInfo {
+        self.peer.clone()
+    }
+
     pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         loop {
             match  recv(self.fd, buf, MsgFlags::empty()) {
@@ -229,7 +265,10 @@ pub struct ClientConnection {
 
 impl ClientConnection {
     pub fn client_connect(sockaddr: &str) -> Result<ClientConnection> {
-        let fd = unsafe { client_connect(sockaddr)? };
+        let fd = match sockaddr.strip_prefix("memory://") {
+            Some(name) => memory::connect(name)?,
+            None => unsafe { client_connect(sockaddr)? },
+        };
         ClientConnection::new(fd)
     }
 
@@ -16,6 +16,7 @@
 
 use crate::error::Result;
 use crate::error::Error;
+use crate::peer::PeerInfo;
 use std::cell::UnsafeCell;
 use std::ffi::OsStr;
 use std::fs::OpenOptions;
@@ -209,6 +210,10 @@ impl PipeConnection {
         self.named_pipe as i32
     }
 
+    pub(crate) fn peer(&self) -> PeerInfo {
+        PeerInfo::Unknown
+    }
+
     pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         trace!("starting read for thread {:?} on pipe instance {}", std::thread::current().id(), self.named_pipe as i32);
         let ol = Overlapped::new_with_event(self.read_event);
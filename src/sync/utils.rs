@@ -5,7 +5,7 @@
 
 use crate::error::{Error, Result};
 use crate::proto::{
-    check_oversize, Codec, MessageHeader, Request, Response, MESSAGE_TYPE_RESPONSE,
+    Codec, MessageHeader, Request, Response, MESSAGE_TYPE_RESPONSE,
 };
 use std::collections::HashMap;
 
@@ -16,12 +16,8 @@ pub fn response_to_channel(
     res: Response,
     tx: std::sync::mpsc::Sender<(MessageHeader, Vec<u8>)>,
 ) -> Result<()> {
-    let mut buf = res.encode().map_err(err_to_others_err!(e, ""))?;
-
-    if let Err(e) = check_oversize(buf.len(), true) {
-        let resp: Response = e.into();
-        buf = resp.encode().map_err(err_to_others_err!(e, ""))?;
-    };
+    // The size is checked against the limit of the server when it is sent.
+    let buf = res.encode().map_err(err_to_others_err!(e, ""))?;
 
     let mh = MessageHeader {
         length: buf.len() as u32,
@@ -62,17 +58,7 @@ macro_rules! request_handler {
                     .map_err(::ttrpc::err_to_others!(e, ""))?;
                 s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
             }
-            Err(x) => match x {
-                ::ttrpc::Error::RpcStatus(s) => {
-                    res.set_status(s);
-                }
-                _ => {
-                    res.set_status(::ttrpc::get_status(
-                        ::ttrpc::Code::UNKNOWN,
-                        format!("{:?}", x),
-                    ));
-                }
-            },
+            Err(x) => res.set_status(::ttrpc::IntoStatus::into_status(x)),
         }
         ::ttrpc::response_to_channel($ctx.mh.stream_id, res, $ctx.res_tx)?
     };
@@ -85,7 +71,7 @@ macro_rules! client_request {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
         let md = ::ttrpc::context::to_pb($ctx.metadata);
         creq.set_metadata(md);
         creq.payload.reserve($req.compute_size() as usize);
@@ -104,6 +90,127 @@ macro_rules! client_request {
     };
 }
 
+/// Handle client streaming in sync mode.
+#[macro_export]
+macro_rules! client_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_fn: ident) => {
+        let stream = ::ttrpc::sync::ServerStreamReceiver::new($inner);
+        let mut res = ::ttrpc::Response::new();
+        match $class.service.$req_fn(&$ctx, stream) {
+            Ok(rep) => {
+                res.set_status(::ttrpc::get_status(::ttrpc::Code::OK, "".to_string()));
+                res.payload.reserve(rep.compute_size() as usize);
+                let mut s = protobuf::CodedOutputStream::vec(&mut res.payload);
+                rep.write_to(&mut s)
+                    .map_err(::ttrpc::err_to_others!(e, ""))?;
+                s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+            }
+            Err(x) => res.set_status(::ttrpc::IntoStatus::into_status(x)),
+        }
+        return Ok(Some(res));
+    };
+}
+
+/// Handle server streaming in sync mode.
+#[macro_export]
+macro_rules! server_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $server: ident, $req_type: ident, $req_fn: ident) => {
+        let req_buf = $inner.recv()?;
+        let req = <super::$server::$req_type as ::ttrpc::proto::Codec>::decode(&req_buf)
+            .map_err(|e| ::ttrpc::Error::Others(e.to_string()))?;
+        let stream = ::ttrpc::sync::ServerStreamSender::new($inner);
+        match $class.service.$req_fn(&$ctx, req, stream) {
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                let mut res = ::ttrpc::Response::new();
+                res.set_status(::ttrpc::IntoStatus::into_status(x));
+                return Ok(Some(res));
+            }
+        }
+    };
+}
+
+/// Handle duplex streaming in sync mode.
+#[macro_export]
+macro_rules! duplex_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_fn: ident) => {
+        let stream = ::ttrpc::sync::ServerStream::new($inner);
+        match $class.service.$req_fn(&$ctx, stream) {
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                let mut res = ::ttrpc::Response::new();
+                res.set_status(::ttrpc::IntoStatus::into_status(x));
+                return Ok(Some(res));
+            }
+        }
+    };
+}
+
+/// Duplex streaming through sync client.
+#[macro_export]
+macro_rules! client_stream {
+    ($self: ident, $ctx: ident, $server: expr, $method: expr) => {
+        let mut creq = ::ttrpc::Request::new();
+        creq.set_service($server.to_string());
+        creq.set_method($method.to_string());
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_metadata(md);
+
+        let inner = $self.client.new_stream(creq, true, true)?;
+        let stream = ::ttrpc::sync::ClientStream::new(inner);
+
+        return Ok(stream);
+    };
+}
+
+/// Only send streaming through sync client.
+#[macro_export]
+macro_rules! client_stream_send {
+    ($self: ident, $ctx: ident, $server: expr, $method: expr) => {
+        let mut creq = ::ttrpc::Request::new();
+        creq.set_service($server.to_string());
+        creq.set_method($method.to_string());
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_metadata(md);
+
+        let inner = $self.client.new_stream(creq, true, false)?;
+        let stream = ::ttrpc::sync::ClientStreamSender::new(inner);
+
+        return Ok(stream);
+    };
+}
+
+/// Only receive streaming through sync client.
+#[macro_export]
+macro_rules! client_stream_receive {
+    ($self: ident, $ctx: ident, $req: ident, $server: expr, $method: expr) => {
+        let mut creq = ::ttrpc::Request::new();
+        creq.set_service($server.to_string());
+        creq.set_method($method.to_string());
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_metadata(md);
+        creq.payload.reserve($req.compute_size() as usize);
+        {
+            let mut s = CodedOutputStream::vec(&mut creq.payload);
+            $req.write_to(&mut s)
+                .map_err(::ttrpc::err_to_others!(e, ""))?;
+            s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+        }
+
+        let inner = $self.client.new_stream(creq, false, true)?;
+        let stream = ::ttrpc::sync::ClientStreamReceiver::new(inner);
+
+        return Ok(stream);
+    };
+}
+
 /// The context of ttrpc (sync).
 #[derive(Debug)]
 pub struct TtrpcContext {
@@ -116,9 +223,39 @@ pub struct TtrpcContext {
     pub res_tx: std::sync::mpsc::Sender<(MessageHeader, Vec<u8>)>,
     pub metadata: HashMap<String, Vec<String>>,
     pub timeout_nano: i64,
+    /// The deadline of the request, set from `timeout_nano` on receipt.
+    pub deadline: Option<std::time::Instant>,
+    /// The peer of the connection the request was received on.
+    pub peer: crate::peer::PeerInfo,
+}
+
+impl TtrpcContext {
+    /// Returns the time left before the deadline of the request, or `None`
+    /// if it has no deadline.
+    pub fn remaining(&self) -> Option<std::time::Duration> {
+        crate::context::remaining(self.deadline)
+    }
+
+    /// Derives a context for the outgoing calls made while handling the
+    /// request, which carries the remaining time budget of the request.
+    pub fn child_context(&self) -> crate::context::Context {
+        crate::context::Context {
+            deadline: self.deadline,
+            ..Default::default()
+        }
+    }
 }
 
 /// Trait that implements handler which is a proxy to the desired method (sync).
 pub trait MethodHandler {
     fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()>;
 }
+
+/// Trait that implements handler which is a proxy to the stream (sync).
+pub trait StreamHandler {
+    fn handler(
+        &self,
+        ctx: TtrpcContext,
+        stream: crate::sync::StreamInner,
+    ) -> Result<Option<Response>>;
+}
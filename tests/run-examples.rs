@@ -88,17 +88,35 @@ fn wait_with_output(name: &str, cmd: Child) {
     }
 }
 
+/// Runs an example which serves its own requests in the process.
+fn run_in_process_example(example: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let output = do_run_example(example, &[]).output()?;
+    if !output.status.success() {
+        println!("==== {example} output begin");
+        println!("==== stdout:\n{}", String::from_utf8_lossy(&output.stdout));
+        println!("==== stderr:\n{}", String::from_utf8_lossy(&output.stderr));
+        println!("==== {example} output end");
+    }
+    assert!(output.status.success());
+    Ok(())
+}
+
 #[test]
 fn run_examples() -> Result<(), Box<dyn std::error::Error>> {
     // Local
     run_example("server", "client", &[])?;
+    run_example("stream-server", "stream-client", &[])?;
     run_example("async-server", "async-client", &[])?;
     run_example("async-stream-server", "async-stream-client", &[])?;
 
+    // In-process
+    run_in_process_example("error-type")?;
+
     // TCP
     #[cfg(not(windows))]
     {
         run_example("server", "client", &["--tcp"])?;
+        run_example("stream-server", "stream-client", &["--tcp"])?;
         run_example("async-server", "async-client", &["--tcp"])?;
         run_example("async-stream-server", "async-stream-client", &["--tcp"])?;
     }
@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Invokes the methods with dynamic messages.
+
+use std::convert::Infallible;
+
+use protobuf::reflect::MethodDescriptor;
+use protobuf::MessageDyn;
+use ttrpc::context::{self, Context};
+use ttrpc::proto::{Codec, Request};
+use ttrpc::r#async::{Client, ClientStream, ClientStreamReceiver};
+
+use crate::{Error, Result};
+
+/// A message already encoded from its descriptor.
+struct Raw(Vec<u8>);
+
+impl Codec for Raw {
+    type E = Infallible;
+
+    fn size(&self) -> u32 {
+        self.0.len() as u32
+    }
+
+    fn encode(&self) -> std::result::Result<Vec<u8>, Infallible> {
+        Ok(self.0.clone())
+    }
+
+    fn decode(buf: impl AsRef<[u8]>) -> std::result::Result<Self, Infallible> {
+        Ok(Raw(buf.as_ref().to_vec()))
+    }
+}
+
+/// Calls `method` of `service` with `requests`, `output` is invoked with each
+/// response. A method which is not client streaming takes a single request.
+pub async fn call(
+    client: &Client,
+    ctx: Context,
+    service: &str,
+    method: &MethodDescriptor,
+    requests: Vec<Box<dyn MessageDyn>>,
+    mut output: impl FnMut(&dyn MessageDyn),
+) -> Result<()> {
+    let proto = method.proto();
+    let (client_streaming, server_streaming) = (proto.client_streaming(), proto.server_streaming());
+    if !client_streaming && requests.len() != 1 {
+        return Err(format!("method {} takes a single request", proto.name()).into());
+    }
+    let mut requests = requests
+        .iter()
+        .map(|m| m.write_to_bytes_dyn())
+        .collect::<protobuf::Result<Vec<_>>>()?;
+    let output_type = method.output_type();
+    let mut output = |buf: &[u8]| -> Result<()> {
+        output(&*output_type.parse_from_bytes(buf)?);
+        Ok(())
+    };
+
+    let mut req = Request {
+        service: service.to_string(),
+        method: proto.name().to_string(),
+        timeout_nano: ctx.request_timeout_nano()?,
+        metadata: context::to_pb(ctx.metadata),
+        ..Default::default()
+    };
+    if !client_streaming {
+        req.payload = requests.pop().unwrap_or_default();
+    }
+    if !client_streaming && !server_streaming {
+        let res = client.request(req).await?;
+        return output(&res.payload);
+    }
+
+    let inner = client
+        .new_stream(req, client_streaming, server_streaming)
+        .await?;
+    if !client_streaming {
+        let mut stream = ClientStreamReceiver::<Raw>::new(inner, client.clone());
+        while let Some(Raw(buf)) = stream.recv().await? {
+            output(&buf)?;
+        }
+        return Ok(());
+    }
+
+    // Receive while sending, the server may respond before reading all the
+    // requests.
+    let (tx, mut rx) = ClientStream::<Raw, Raw>::new(inner).split();
+    let send = async {
+        for buf in requests {
+            tx.send(&Raw(buf)).await?;
+        }
+        tx.close_send().await?;
+        Ok::<(), Error>(())
+    };
+    let recv = async {
+        loop {
+            match rx.recv().await {
+                Ok(Raw(buf)) => output(&buf)?,
+                Err(ttrpc::Error::Eof) => return Ok::<(), Error>(()),
+                Err(e) => return Err(e.into()),
+            }
+            if !server_streaming {
+                return Ok(());
+            }
+        }
+    };
+    tokio::try_join!(send, recv)?;
+    Ok(())
+}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Loads the descriptors of the services, either from `.proto` files or from
+//! the reflection service of the server.
+
+use std::path::{Path, PathBuf};
+
+use protobuf::descriptor::FileDescriptorProto;
+use protobuf::reflect::{FileDescriptor, MethodDescriptor, ServiceDescriptor};
+use protobuf::Message;
+use ttrpc::context::Context;
+use ttrpc::r#async::Client;
+use ttrpc::reflection::r#async::ReflectionClient;
+use ttrpc::reflection::FileDescriptorsRequest;
+
+use crate::Result;
+
+/// The services defined by a set of files, keyed by their name prefixed by
+/// the package, as in the requests.
+pub struct Descriptors {
+    services: Vec<(String, ServiceDescriptor)>,
+}
+
+impl Descriptors {
+    /// Parses the `protos` files, the directories of the files are searched
+    /// for the imports if no `includes` are given.
+    pub fn from_protos(includes: &[PathBuf], protos: &[PathBuf]) -> Result<Descriptors> {
+        let mut includes: Vec<&Path> = includes.iter().map(PathBuf::as_path).collect();
+        if includes.is_empty() {
+            includes = protos
+                .iter()
+                .map(|p| p.parent().unwrap_or_else(|| Path::new(".")))
+                .collect();
+        }
+        let protos: Vec<&Path> = protos.iter().map(PathBuf::as_path).collect();
+        let parsed = ttrpc_codegen::parse_and_typecheck(&includes, &protos)?;
+        Self::new(parsed.file_descriptors)
+    }
+
+    /// Fetches the files defining `service` from the reflection service.
+    pub async fn from_reflection(client: &Client, service: &str) -> Result<Descriptors> {
+        let req = FileDescriptorsRequest {
+            service: service.to_string(),
+            ..Default::default()
+        };
+        let res = ReflectionClient::new(client.clone())
+            .file_descriptors(Context::default(), &req)
+            .await?;
+        let files = res
+            .file_descriptor_proto
+            .iter()
+            .map(|buf| FileDescriptorProto::parse_from_bytes(buf))
+            .collect::<protobuf::Result<_>>()?;
+        Self::new(files)
+    }
+
+    fn new(files: Vec<FileDescriptorProto>) -> Result<Descriptors> {
+        let mut services = Vec::new();
+        for file in FileDescriptor::new_dynamic_fds(files, &[])? {
+            for service in file.services() {
+                let name = match file.package() {
+                    "" => service.proto().name().to_string(),
+                    package => format!("{}.{}", package, service.proto().name()),
+                };
+                services.push((name, service));
+            }
+        }
+        services.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Descriptors { services })
+    }
+
+    pub fn services(&self) -> impl Iterator<Item = &(String, ServiceDescriptor)> {
+        self.services.iter()
+    }
+
+    pub fn service(&self, name: &str) -> Result<&ServiceDescriptor> {
+        self.services
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, service)| service)
+            .ok_or_else(|| format!("unknown service {}", name).into())
+    }
+
+    pub fn method(&self, service: &str, method: &str) -> Result<MethodDescriptor> {
+        self.service(service)?
+            .methods()
+            .find(|m| m.proto().name() == method)
+            .ok_or_else(|| format!("unknown method {} of service {}", method, service).into())
+    }
+}
@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Conversion between JSON and dynamic messages.
+//!
+//! It follows the proto3 JSON mapping, except that the well-known types are
+//! handled as plain messages.
+
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use protobuf::reflect::{
+    MessageDescriptor, ReflectFieldRef, ReflectValueBox, ReflectValueRef, RuntimeFieldType,
+    RuntimeType,
+};
+use protobuf::MessageDyn;
+use serde_json::{Map, Value};
+
+use crate::Result;
+
+/// Converts `m` to JSON, the fields which are not set are omitted.
+pub fn to_json(m: &dyn MessageDyn) -> Value {
+    let mut object = Map::new();
+    for field in m.descriptor_dyn().fields() {
+        let value = match field.get_reflect(m) {
+            ReflectFieldRef::Optional(v) => match v.value() {
+                Some(v) => value_to_json(v),
+                None => continue,
+            },
+            ReflectFieldRef::Repeated(r) if r.is_empty() => continue,
+            ReflectFieldRef::Repeated(r) => r.into_iter().map(value_to_json).collect(),
+            ReflectFieldRef::Map(m) if m.is_empty() => continue,
+            ReflectFieldRef::Map(m) => Value::Object(
+                (&m).into_iter()
+                    .map(|(k, v)| (key_to_string(k), value_to_json(v)))
+                    .collect(),
+            ),
+        };
+        object.insert(field.json_name().to_string(), value);
+    }
+    Value::Object(object)
+}
+
+fn value_to_json(v: ReflectValueRef) -> Value {
+    match v {
+        ReflectValueRef::U32(v) => v.into(),
+        ReflectValueRef::I32(v) => v.into(),
+        // 64-bit integers are strings, they do not fit in a JSON number.
+        ReflectValueRef::U64(v) => v.to_string().into(),
+        ReflectValueRef::I64(v) => v.to_string().into(),
+        ReflectValueRef::F32(v) => float_to_json(v.into()),
+        ReflectValueRef::F64(v) => float_to_json(v),
+        ReflectValueRef::Bool(v) => v.into(),
+        ReflectValueRef::String(v) => v.into(),
+        ReflectValueRef::Bytes(v) => STANDARD.encode(v).into(),
+        ReflectValueRef::Enum(d, v) => match d.value_by_number(v) {
+            Some(e) => e.name().into(),
+            None => v.into(),
+        },
+        ReflectValueRef::Message(m) => to_json(&*m),
+    }
+}
+
+fn float_to_json(v: f64) -> Value {
+    if v.is_nan() {
+        "NaN".into()
+    } else if v.is_infinite() {
+        if v > 0.0 { "Infinity" } else { "-Infinity" }.into()
+    } else {
+        v.into()
+    }
+}
+
+fn key_to_string(k: ReflectValueRef) -> String {
+    match value_to_json(k) {
+        Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+/// Creates a message of type `descriptor` from its JSON form, the fields
+/// are named either by their name or their JSON name.
+pub fn from_json(descriptor: &MessageDescriptor, json: &Value) -> Result<Box<dyn MessageDyn>> {
+    let mut m = descriptor.new_instance();
+    let object = json
+        .as_object()
+        .ok_or_else(|| format!("expect an object for {}", descriptor.full_name()))?;
+    for (name, value) in object {
+        let field = descriptor
+            .field_by_name_or_json_name(name)
+            .ok_or_else(|| format!("unknown field {} of {}", name, descriptor.full_name()))?;
+        if value.is_null() {
+            continue;
+        }
+        match field.runtime_field_type() {
+            RuntimeFieldType::Singular(t) => {
+                field.set_singular_field(&mut *m, value_from_json(&t, value)?)
+            }
+            RuntimeFieldType::Repeated(t) => {
+                let items = value
+                    .as_array()
+                    .ok_or_else(|| format!("expect an array for {}", field.full_name()))?;
+                let mut repeated = field.mut_repeated(&mut *m);
+                for item in items {
+                    repeated.push(value_from_json(&t, item)?);
+                }
+            }
+            RuntimeFieldType::Map(k, v) => {
+                let entries = value
+                    .as_object()
+                    .ok_or_else(|| format!("expect an object for {}", field.full_name()))?;
+                let mut map = field.mut_map(&mut *m);
+                for (key, value) in entries {
+                    map.insert(key_from_string(&k, key)?, value_from_json(&v, value)?);
+                }
+            }
+        }
+    }
+    Ok(m)
+}
+
+fn value_from_json(t: &RuntimeType, v: &Value) -> Result<ReflectValueBox> {
+    let invalid = || format!("invalid {} value {}", t, v);
+    Ok(match t {
+        RuntimeType::I32 => ReflectValueBox::I32(number(v).ok_or_else(invalid)?),
+        RuntimeType::I64 => ReflectValueBox::I64(number(v).ok_or_else(invalid)?),
+        RuntimeType::U32 => ReflectValueBox::U32(number(v).ok_or_else(invalid)?),
+        RuntimeType::U64 => ReflectValueBox::U64(number(v).ok_or_else(invalid)?),
+        RuntimeType::F32 => ReflectValueBox::F32(number(v).ok_or_else(invalid)?),
+        RuntimeType::F64 => ReflectValueBox::F64(number(v).ok_or_else(invalid)?),
+        RuntimeType::Bool => ReflectValueBox::Bool(v.as_bool().ok_or_else(invalid)?),
+        RuntimeType::String => ReflectValueBox::String(v.as_str().ok_or_else(invalid)?.into()),
+        RuntimeType::VecU8 => {
+            let s = v.as_str().ok_or_else(invalid)?;
+            ReflectValueBox::Bytes(STANDARD.decode(s).map_err(|_| invalid())?)
+        }
+        RuntimeType::Enum(d) => {
+            let value = match v {
+                Value::String(name) => d.value_by_name(name).map(|e| e.value()),
+                v => number(v),
+            };
+            ReflectValueBox::Enum(d.clone(), value.ok_or_else(invalid)?)
+        }
+        RuntimeType::Message(d) => ReflectValueBox::Message(from_json(d, v)?),
+    })
+}
+
+fn key_from_string(t: &RuntimeType, key: &str) -> Result<ReflectValueBox> {
+    match t {
+        RuntimeType::Bool => match key {
+            "true" => Ok(ReflectValueBox::Bool(true)),
+            "false" => Ok(ReflectValueBox::Bool(false)),
+            _ => Err(format!("invalid bool key {}", key).into()),
+        },
+        t => value_from_json(t, &Value::String(key.to_string())),
+    }
+}
+
+/// Parses a number given either as a JSON number or as a string.
+fn number<T: FromStr>(v: &Value) -> Option<T> {
+    match v {
+        Value::Number(n) => n.to_string().parse().ok(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protobuf::descriptor::FileDescriptorProto;
+    use protobuf::MessageFull;
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::json!({
+            "name": "echo.proto",
+            "dependency": ["empty.proto"],
+            "messageType": [{
+                "name": "Echo",
+                "field": [{
+                    "name": "seq",
+                    "number": 1,
+                    "label": "LABEL_OPTIONAL",
+                    "type": "TYPE_UINT64",
+                }],
+                "options": {
+                    "uninterpretedOption": [{
+                        "positiveIntValue": "18446744073709551615",
+                        "doubleValue": "Infinity",
+                        "stringValue": "dHRycGM=",
+                    }],
+                },
+            }],
+        });
+        let m = from_json(&FileDescriptorProto::descriptor(), &json).unwrap();
+        let file = m.downcast_ref::<FileDescriptorProto>().unwrap();
+        assert_eq!(file.message_type[0].field[0].number(), 1);
+        let option = &file.message_type[0].options.uninterpreted_option[0];
+        assert_eq!(option.positive_int_value(), u64::MAX);
+        assert_eq!(option.string_value(), b"ttrpc");
+        assert_eq!(to_json(&*m), json);
+
+        // Fields can also be named as in the proto file, numbers as strings.
+        let m = from_json(
+            &FileDescriptorProto::descriptor(),
+            &serde_json::json!({"message_type": [{"field": [{"number": "2"}]}]}),
+        )
+        .unwrap();
+        let file = m.downcast_ref::<FileDescriptorProto>().unwrap();
+        assert_eq!(file.message_type[0].field[0].number(), 2);
+
+        let invalid = serde_json::json!({"messageType": [{"field": [{"label": "LABEL_FOO"}]}]});
+        assert!(from_json(&FileDescriptorProto::descriptor(), &invalid).is_err());
+    }
+}
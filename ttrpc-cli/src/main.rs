@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Command-line client calling the methods of any ttrpc server.
+//!
+//! The messages are described by `.proto` files, or fetched from the
+//! reflection service of the server, and written as JSON or text format:
+//!
+//! ```text
+//! ttrpc-cli --proto agent.proto unix:///run/agent.sock \
+//!     call grpc.AgentService/GetGuestDetails '{"memBlockSize": true}'
+//! ```
+
+mod call;
+mod descriptor;
+mod json;
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use protobuf::reflect::MessageDescriptor;
+use protobuf::MessageDyn;
+use ttrpc::context::{self, Context};
+use ttrpc::r#async::Client;
+use ttrpc::reflection::r#async::ReflectionClient;
+
+use descriptor::Descriptors;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Parser)]
+#[command(version, about = "Calls the methods of a ttrpc server")]
+struct Args {
+    /// The .proto files defining the services, the reflection service of the
+    /// server is queried if none is given.
+    #[arg(long = "proto", value_name = "FILE")]
+    protos: Vec<PathBuf>,
+
+    /// The directories searched for the imports, defaults to the directories
+    /// of the .proto files.
+    #[arg(short = 'I', long = "import-path", value_name = "DIR")]
+    includes: Vec<PathBuf>,
+
+    /// The timeout of the request in seconds.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// The metadata sent with the request, can be repeated.
+    #[arg(short = 'H', long = "header", value_name = "KEY=VALUE", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// The format of the requests and responses.
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// The address of the server, e.g. unix:///run/agent.sock,
+    /// vsock://3:1024, hvsock:///run/vm.vsock:1024 or tcp://127.0.0.1:1234.
+    address: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// The proto3 JSON mapping, the requests are consecutive JSON values.
+    Json,
+    /// The protobuf text format, the requests of a client stream are one per
+    /// line.
+    Text,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists the services, or the methods of SERVICE.
+    List { service: Option<String> },
+    /// Calls METHOD, as package.Service/Method, with the requests read from
+    /// DATA, or from the standard input if DATA is missing or `-`.
+    Call {
+        method: String,
+        data: Option<String>,
+    },
+}
+
+fn parse_header(s: &str) -> std::result::Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("invalid header {}, expect KEY=VALUE", s))
+}
+
+fn parse_timeout(s: &str) -> std::result::Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid timeout {}, expect a number of seconds", s))
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(&args).await {
+        match e.downcast_ref::<ttrpc::Error>() {
+            Some(ttrpc::Error::RpcStatus(s)) => {
+                eprintln!("Error: {:?}: {}", s.code(), s.message())
+            }
+            _ => eprintln!("Error: {}", e),
+        }
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: &Args) -> Result<()> {
+    let client = Client::connect(&args.address).await?;
+    match &args.command {
+        Command::List { service } => list(args, &client, service.as_deref()).await,
+        Command::Call { method, data } => call(args, &client, method, data.as_deref()).await,
+    }
+}
+
+async fn descriptors(args: &Args, client: &Client, service: &str) -> Result<Descriptors> {
+    if args.protos.is_empty() {
+        Descriptors::from_reflection(client, service).await
+    } else {
+        Descriptors::from_protos(&args.includes, &args.protos)
+    }
+}
+
+async fn list(args: &Args, client: &Client, service: Option<&str>) -> Result<()> {
+    let Some(service) = service else {
+        if args.protos.is_empty() {
+            let res = ReflectionClient::new(client.clone())
+                .list_services(Context::default())
+                .await?;
+            res.services.iter().for_each(|s| println!("{}", s.name));
+        } else {
+            let descriptors = Descriptors::from_protos(&args.includes, &args.protos)?;
+            descriptors
+                .services()
+                .for_each(|(name, _)| println!("{}", name));
+        }
+        return Ok(());
+    };
+
+    let descriptors = descriptors(args, client, service).await?;
+    for method in descriptors.service(service)?.methods() {
+        let stream = |streaming| if streaming { "stream " } else { "" };
+        println!(
+            "rpc {}({}{}) returns ({}{})",
+            method.proto().name(),
+            stream(method.proto().client_streaming()),
+            method.input_type().full_name(),
+            stream(method.proto().server_streaming()),
+            method.output_type().full_name(),
+        );
+    }
+    Ok(())
+}
+
+async fn call(args: &Args, client: &Client, path: &str, data: Option<&str>) -> Result<()> {
+    let (service, name) = split_path(path)?;
+    let method = descriptors(args, client, service)
+        .await?
+        .method(service, name)?;
+
+    let input = match data {
+        Some(data) if data != "-" => data.to_string(),
+        _ => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let client_streaming = method.proto().client_streaming();
+    let mut requests = parse_requests(args.format, &method.input_type(), &input, client_streaming)?;
+    if requests.is_empty() && !client_streaming {
+        requests.push(method.input_type().new_instance());
+    }
+
+    let mut ctx = match args.timeout {
+        Some(timeout) => context::with_duration(timeout),
+        None => Context::default(),
+    };
+    for (key, value) in &args.headers {
+        ctx.add(key.clone(), value.clone());
+    }
+    let format = args.format;
+    call::call(client, ctx, service, &method, requests, |m| {
+        print_response(format, m)
+    })
+    .await
+}
+
+/// Splits `package.Service/Method`, `/package.Service/Method` or
+/// `package.Service.Method` into the service and the method.
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    let path = path.trim_start_matches('/');
+    path.split_once('/')
+        .or_else(|| path.rsplit_once('.'))
+        .ok_or_else(|| format!("invalid method {}, expect package.Service/Method", path).into())
+}
+
+fn parse_requests(
+    format: Format,
+    descriptor: &MessageDescriptor,
+    input: &str,
+    client_streaming: bool,
+) -> Result<Vec<Box<dyn MessageDyn>>> {
+    match format {
+        Format::Json => serde_json::Deserializer::from_str(input)
+            .into_iter()
+            .map(|value| json::from_json(descriptor, &value?))
+            .collect(),
+        Format::Text => {
+            // A single request may span several lines.
+            let inputs: Vec<&str> = if client_streaming {
+                input.lines().filter(|l| !l.trim().is_empty()).collect()
+            } else if input.trim().is_empty() {
+                vec![]
+            } else {
+                vec![input]
+            };
+            inputs
+                .into_iter()
+                .map(|input| {
+                    let mut m = descriptor.new_instance();
+                    protobuf::text_format::merge_from_str(&mut *m, input)?;
+                    Ok(m)
+                })
+                .collect()
+        }
+    }
+}
+
+fn print_response(format: Format, m: &dyn MessageDyn) {
+    match format {
+        Format::Json => println!("{:#}", json::to_json(m)),
+        Format::Text => println!("{}", protobuf::text_format::print_to_string(m)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_timeout("0"), Ok(Duration::ZERO));
+        for s in ["-1", "inf", "NaN", "1e30", "soon"] {
+            assert!(parse_timeout(s).is_err(), "{} is accepted", s);
+        }
+    }
+}
@@ -1034,6 +1034,7 @@ pub fn file_descriptor(
     output.set_name(name);
     output.set_package(input.package.clone());
     output.set_syntax(syntax(input.syntax));
+    output.dependency = input.import_paths.clone();
 
     for m in &input.messages {
         output
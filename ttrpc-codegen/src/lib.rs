@@ -399,4 +399,34 @@ mod test {
             relative_path_to_protobuf_path(Path::new("foo/bar.proto"))
         );
     }
+
+    #[test]
+    fn test_parse_and_typecheck_dependency() {
+        let dir = std::env::temp_dir().join(format!("ttrpc-codegen-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("base.proto"),
+            "syntax = \"proto3\";\npackage base;\nmessage Base {}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("user.proto"),
+            "syntax = \"proto3\";\npackage user;\nimport \"base.proto\";\n\
+             message User { base.Base base = 1; }\n",
+        )
+        .unwrap();
+
+        let parsed = parse_and_typecheck(&[&dir], &[&dir.join("user.proto")]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut files = parsed.unwrap().file_descriptors;
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(files[0].name(), "base.proto");
+        assert!(files[0].dependency.is_empty());
+        assert_eq!(files[1].name(), "user.proto");
+        assert_eq!(files[1].dependency, ["base.proto"]);
+
+        // The dependencies are needed to build the dynamic descriptors.
+        protobuf::reflect::FileDescriptor::new_dynamic_fds(files, &[]).unwrap();
+    }
 }